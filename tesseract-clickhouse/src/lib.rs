@@ -4,7 +4,9 @@ use failure::{Error, format_err};
 use futures::{future, Future, Stream};
 use log::*;
 use std::time::{Duration, Instant};
-use tesseract_core::{Backend, DataFrame, QueryIr};
+use tesseract_core::{Backend, DataFrame, QueryIr, Table};
+use tesseract_core::query_ir::quote_text;
+use tesseract_core::schema::TimePart;
 
 use regex::Regex;
 
@@ -12,7 +14,8 @@ mod df;
 mod sql;
 
 use self::df::{block_to_df};
-use self::sql::clickhouse_sql;
+use self::sql::{clickhouse_sql, table_columns_sql};
+use self::sql::time::time_part_sql;
 
 // Ping timeout in millis
 const PING_TIMEOUT: u64 = 100_000;
@@ -94,5 +97,21 @@ impl Backend for Clickhouse {
             &query_ir
         )
     }
+
+    fn check_query_ir(&self, _query_ir: &QueryIr) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn time_part_sql(&self, column: &str, time_part: &TimePart, fiscal_year_start_month: Option<u32>) -> String {
+        time_part_sql(column, time_part, fiscal_year_start_month)
+    }
+
+    fn quote_text(&self, s: &str) -> String {
+        quote_text(s)
+    }
+
+    fn table_columns_sql(&self, table: &Table) -> String {
+        table_columns_sql(table)
+    }
 }
 
@@ -1,10 +1,15 @@
 mod aggregator;
+mod bins;
 mod cuts;
+mod dense;
 mod growth;
 mod options;
 mod primary_agg;
+mod rank;
 mod rate;
 mod rca;
+pub(crate) mod time;
+mod totals;
 
 use tesseract_core::query_ir::{
     TableSql,
@@ -15,11 +20,17 @@ use tesseract_core::query_ir::{
     RcaSql,
     GrowthSql,
     RateSql,
+    RankSql,
+    TotalsSql,
+    DenseSql,
+    BinSql,
+    DimSubquery,
     dim_subquery,
+    quote_text,
 };
-use tesseract_core::{QueryIr};
+use tesseract_core::{Aggregator, QueryIr, Table};
 
-use self::options::wrap_options;
+use self::options::{wrap_options, top_other_meas};
 use self::primary_agg::primary_agg;
 use self::rate::rate_calculation;
 
@@ -30,7 +41,11 @@ pub fn clickhouse_sql(
     query_ir: &QueryIr
     ) -> String
 {
-    let meas = &query_ir.meas;
+    // the "Other" row of top_other rolls averages up from extra measures
+    let meas = &match query_ir.top {
+        Some(ref top) if top.other => top_other_meas(&query_ir.meas),
+        _ => query_ir.meas.clone(),
+    };
 
     let (mut final_sql, mut final_drill_cols) = {
         // HiddenDrilldownSql, for grouped median, only works with primar agg,
@@ -43,32 +58,60 @@ pub fn clickhouse_sql(
         let rca = &query_ir.rca;
         let rate = &query_ir.rate;
 
-        if let Some(rca) = rca {
+        if query_ir.distinct {
+            // distinct members are the groupings of any aggregation on the
+            // fact table; the measure is then dropped.
+            let count = [MeasureSql { aggregator: Aggregator::Count, column: "1".to_owned() }];
+            let (sql, drill_cols) = primary_agg(table, cuts, drills, &count, None, None);
+
+            (format!("SELECT {} FROM ({})", drill_cols, sql), drill_cols)
+        } else if let Some(rca) = rca {
             rca::calculate(table, cuts, drills, meas, rca)
         } else if let Some(rate) = rate {
             rate_calculation(table, cuts, drills, meas, rate)
         } else {
-            primary_agg(table, cuts, drills, meas, Some(&query_ir.hidden_drills))
+            primary_agg(table, cuts, drills, meas, Some(&query_ir.hidden_drills), query_ir.totals.as_ref())
         }
     };
+    if let Some(dense) = &query_ir.dense {
+        final_sql = dense::calculate(final_sql, &final_drill_cols, &query_ir.drills, &query_ir.cuts, meas.len(), dense);
+    }
     if let Some(growth) = &query_ir.growth {
         let (sql, drill_cols) = growth::calculate(final_sql, &final_drill_cols, meas.len(), growth);
         final_sql = sql;
         final_drill_cols = drill_cols;
     }
+    if let Some(rank) = &query_ir.rank {
+        final_sql = rank::calculate(final_sql, &final_drill_cols, rank);
+    }
 
-    final_sql = wrap_options(final_sql, &final_drill_cols, &query_ir, meas.len());
+    final_sql = wrap_options(final_sql, &final_drill_cols, &query_ir, query_ir.meas.len());
 
     final_sql
 }
 
+/// Names and types of the columns of a table in order, from `system.columns`.
+/// Without a schema, the table is looked up in the current database.
+pub fn table_columns_sql(table: &Table) -> String {
+    let database = match table.schema {
+        Some(ref schema) => quote_text(schema),
+        None => "currentDatabase()".to_owned(),
+    };
+
+    format!("select name, type from system.columns where table = {} and database = {} order by position",
+        quote_text(&table.name),
+        database,
+    )
+}
+
 
 // TODO test having not cuts or drilldowns
 #[cfg(test)]
 mod test {
     use super::*;
+    use super::cuts::cut_sql_string;
     use tesseract_core::Table;
-    use tesseract_core::names::Mask;
+    use tesseract_core::names::{CutRange, Mask};
     use tesseract_core::query_ir::{LevelColumn, MemberType};
 
     // TODO move this to better place?
@@ -80,34 +123,99 @@ mod test {
                 foreign_key: "".into(),
                 primary_key: "".into(),
                 inline_table: None,
+                parent_child: None,
+                snowflake_sql: None,
                 table: Table { name: "".into(), schema: None, primary_key: None },
                 column: "geo".into(),
                 members: vec!["1".into(), "2".into()],
                 member_type: MemberType::Text,
                 mask: Mask::Include,
                 for_match: false,
+                range: None,
+                time_part: None,
+                fiscal_year_start_month: None,
             },
             CutSql {
                 foreign_key: "".into(),
                 primary_key: "".into(),
                 inline_table: None,
+                parent_child: None,
+                snowflake_sql: None,
                 table: Table { name: "".into(), schema: None, primary_key: None },
                 column: "age".into(),
                 members: vec!["3".into()],
                 member_type: MemberType::NonText,
                 mask: Mask::Include,
                 for_match: false,
+                range: None,
+                time_part: None,
+                fiscal_year_start_month: None,
             },
         ];
 
         assert_eq!(
-            cuts[0].members_string(),
+            cuts[0].members_string(&quote_text),
             "'1', '2'",
         );
         assert_eq!(
-            cuts[1].members_string(),
+            cuts[1].members_string(&quote_text),
             "3",
         );
+
+        // text members, like property values, are escaped
+        let mut property_cut = cuts[0].clone();
+        property_cut.members = vec!["South'; drop table sales; --".into(), "a\\".into()];
+        assert_eq!(
+            property_cut.members_string(&quote_text),
+            "'South''; drop table sales; --', 'a\\\\'",
+        );
+
+        property_cut.for_match = true;
+        assert_eq!(
+            property_cut.members_like_string(&quote_text),
+            "(geo like '%South''; drop table sales; --%' or geo like '%a\\\\\\\\%')",
+        );
+
+        // wildcards are matched literally
+        property_cut.members = vec!["50%_off".into()];
+        assert_eq!(
+            property_cut.members_like_string(&quote_text),
+            "(geo like '%50\\\\%\\\\_off%')",
+        );
+    }
+
+    #[test]
+    fn cutsql_range() {
+        let mut cut = CutSql {
+            foreign_key: "".into(),
+            primary_key: "".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: "".into(), schema: None, primary_key: None },
+            column: "year".into(),
+            members: vec![],
+            member_type: MemberType::NonText,
+            mask: Mask::Include,
+            for_match: false,
+            range: Some(CutRange::Between("2010".into(), "2020".into())),
+            time_part: None,
+            fiscal_year_start_month: None,
+        };
+
+        assert_eq!(cut_sql_string(&cut), "year between 2010 and 2020");
+
+        cut.member_type = MemberType::Text;
+        cut.mask = Mask::Exclude;
+        cut.range = Some(CutRange::From("2015".into()));
+
+        assert_eq!(cut_sql_string(&cut), "not (year >= '2015')");
+
+        // text range ends are escaped
+        cut.mask = Mask::Include;
+        cut.range = Some(CutRange::To("2015'; drop table sales; --".into()));
+
+        assert_eq!(cut_sql_string(&cut), "year <= '2015''; drop table sales; --'");
     }
 
     // TODO move this to better place?
@@ -119,6 +227,8 @@ mod test {
             foreign_key: "product_id".into(),
             primary_key: "product_id".into(),
             inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
             table: Table { name: "dim_products".into(), schema: None, primary_key: None },
             level_columns: vec![
                 LevelColumn {
@@ -131,6 +241,8 @@ mod test {
                 },
             ],
             property_columns: vec!["hexcode".to_owned(), "form".to_owned()],
+            bin: None,
+            time: None,
         };
 
         assert_eq!(
@@ -139,4 +251,12 @@ mod test {
         );
     }
 
+    #[test]
+    fn table_columns_in_order() {
+        let table = Table { name: "sales".into(), schema: None, primary_key: None };
+        assert_eq!(
+            table_columns_sql(&table),
+            "select name, type from system.columns where table = 'sales' and database = currentDatabase() order by position".to_owned(),
+        );
+    }
 }
@@ -0,0 +1,164 @@
+//! Bin drilldowns group by buckets of a column instead of by member. The
+//! bucket id and label are generated in the dimension subquery, so the rest
+//! of the query can treat them like any other level columns.
+//!
+//! Cuts on a bin filter the dimension subquery on bucket labels; since the
+//! subquery is inner joined to the fact table, that cuts the fact rows too.
+
+use itertools::join;
+use tesseract_core::names::Mask;
+use tesseract_core::query::BinType;
+use tesseract_core::query_ir::quote_text;
+
+use super::{BinSql, DimSubquery, DrilldownSql};
+
+pub fn bin_dim_subquery(drill: &DrilldownSql, bin: &BinSql) -> DimSubquery {
+    // bin drilldowns always have a single level column, for the bucket
+    // id and label
+    let key_col = drill.level_columns[0].key_column.clone();
+    let label_col = drill.level_columns[0].name_column.clone()
+        .unwrap_or(format!("{}_label", key_col));
+
+    let table = drill.table_sql();
+
+    let (with_sql, key_sql, label_sql) = bin_sql_strings(bin, &key_col, &table);
+
+    let cut_sql = if bin.members.is_empty() {
+        "".to_owned()
+    } else {
+        let members = bin.members.iter().map(|m| quote_text(m));
+        let mask_sql = match bin.mask {
+            Mask::Include => "in",
+            Mask::Exclude => "not in",
+        };
+        format!(" where {} {} ({})", label_col, mask_sql, join(members, ", "))
+    };
+
+    let sql = format!("select {}, {} as {} from ({}select {}, {} as {}, {} as {} from {}{})",
+        drill.col_alias_string(),
+        drill.primary_key,
+        drill.foreign_key,
+        with_sql,
+        drill.primary_key,
+        key_sql,
+        key_col,
+        label_sql,
+        label_col,
+        table,
+        cut_sql,
+    );
+
+    DimSubquery {
+        sql,
+        foreign_key: drill.foreign_key.clone(),
+        dim_cols: Some(drill.col_alias_only_string()),
+    }
+}
+
+/// Returns the with clause (if needed), and the bucket id and label
+/// expressions. The label expression refers to the bucket id by its alias.
+fn bin_sql_strings(bin: &BinSql, key_col: &str, table: &str) -> (String, String, String) {
+    let col = &bin.column;
+
+    match bin.bin_type {
+        BinType::Width(width) => {
+            let key_sql = format!("toInt64(floor({} / {}))", col, width);
+            let label_sql = format!("concat(toString({key} * {w}), '-', toString({key} * {w} + {w_end}))",
+                key = key_col,
+                w = width,
+                w_end = width - 1,
+            );
+
+            ("".to_owned(), key_sql, label_sql)
+        },
+        BinType::Breaks(ref breaks) => {
+            let key_sql = format!("arrayCount(b -> b <= {}, [{}])", col, join(breaks, ", "));
+
+            let labels = BinType::break_labels(breaks).into_iter()
+                .map(|l| quote_text(&l));
+            // arrays are 1-indexed
+            let label_sql = format!("[{}][{} + 1]", join(labels, ", "), key_col);
+
+            ("".to_owned(), key_sql, label_sql)
+        },
+        BinType::Quantiles(n) => {
+            let edges = format!("{}_edges", key_col);
+            let levels = (1..n).map(|i| (i as f64) / (n as f64));
+
+            // edges are min, the inner quantiles, then max
+            let with_sql = format!("with (select arrayConcat([toFloat64(min({col}))], quantilesExact({levels})({col}), [toFloat64(max({col}))]) from {table}) as {edges} ",
+                col = col,
+                levels = join(levels, ", "),
+                table = table,
+                edges = edges,
+            );
+            let key_sql = format!("arrayCount(b -> b <= {}, arraySlice({}, 2, {}))", col, edges, n - 1);
+            let label_sql = format!("concat(toString({edges}[{key} + 1]), '-', toString({edges}[{key} + 2]))",
+                edges = edges,
+                key = key_col,
+            );
+
+            (with_sql, key_sql, label_sql)
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Table;
+    use tesseract_core::query_ir::LevelColumn;
+
+    fn bin_drill(bin_type: BinType, members: Vec<String>) -> DrilldownSql {
+        DrilldownSql {
+            alias_postfix: "Geography_Population".into(),
+            foreign_key: "geo_id".into(),
+            primary_key: "municipality_id".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: "dim_geo".into(), schema: None, primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: "population_bin".into(),
+                    name_column: Some("population_bin_label".into()),
+                },
+            ],
+            property_columns: vec![],
+            bin: Some(BinSql {
+                column: "population".into(),
+                bin_type,
+                members,
+                mask: Mask::Include,
+            }),
+            time: None,
+        }
+    }
+
+    #[test]
+    fn bin_breaks() {
+        let drill = bin_drill(BinType::Breaks(vec![0, 1000, 5000]), vec!["1000-4999".into()]);
+        let dim_subquery = bin_dim_subquery(&drill, drill.bin.as_ref().unwrap());
+
+        assert_eq!(
+            dim_subquery.sql,
+            "select population_bin as population_bin_Geography_Population, population_bin_label as population_bin_label_Geography_Population, municipality_id as geo_id from (select municipality_id, arrayCount(b -> b <= population, [0, 1000, 5000]) as population_bin, ['<0', '0-999', '1000-4999', '5000+'][population_bin + 1] as population_bin_label from dim_geo where population_bin_label in ('1000-4999'))".to_owned(),
+        );
+        assert_eq!(dim_subquery.foreign_key, "geo_id");
+    }
+
+    #[test]
+    fn bin_width_and_quantiles() {
+        let drill = bin_drill(BinType::Width(1000), vec![]);
+        let (with_sql, key_sql, label_sql) = bin_sql_strings(drill.bin.as_ref().unwrap(), "population_bin", "dim_geo");
+        assert_eq!(with_sql, "");
+        assert_eq!(key_sql, "toInt64(floor(population / 1000))");
+        assert_eq!(label_sql, "concat(toString(population_bin * 1000), '-', toString(population_bin * 1000 + 999))");
+
+        let drill = bin_drill(BinType::Quantiles(4), vec![]);
+        let (with_sql, key_sql, label_sql) = bin_sql_strings(drill.bin.as_ref().unwrap(), "population_bin", "dim_geo");
+        assert_eq!(with_sql, "with (select arrayConcat([toFloat64(min(population))], quantilesExact(0.25, 0.5, 0.75)(population), [toFloat64(max(population))]) from dim_geo) as population_bin_edges ");
+        assert_eq!(key_sql, "arrayCount(b -> b <= population, arraySlice(population_bin_edges, 2, 3))");
+        assert_eq!(label_sql, "concat(toString(population_bin_edges[population_bin + 1]), '-', toString(population_bin_edges[population_bin + 2]))");
+    }
+}
@@ -1,10 +1,20 @@
-use super::CutSql;
+use super::{CutSql, quote_text};
+use super::time::time_part_sql;
 
 pub fn cut_sql_string(cut: &CutSql) -> String {
-    if cut.for_match {
-        format!("{}", cut.members_like_string())
+    // virtual time levels cut on a part of the date column
+    let column = match cut.time_part {
+        Some(ref time_part) => time_part_sql(&cut.column, time_part, cut.fiscal_year_start_month),
+        None => cut.column.clone(),
+    };
+
+    if let Some(ref range) = cut.range {
+        // col between start and end
+        cut.range_string(&column, range, &quote_text)
+    } else if cut.for_match {
+        format!("{}", cut.members_like_string(&quote_text))
     } else {
         // col not in ('', '',...)
-        format!("{} {} ({})", cut.column, cut.mask_sql_in_string(), cut.members_string())
+        format!("{} {} ({})", column, cut.mask_sql_in_string(), cut.members_string(&quote_text))
     }
 }
@@ -0,0 +1,162 @@
+//! Dense results have a row for every combination of drilldown members, not
+//! just the combinations that have data in the fact table.
+//!
+//! The members of each drilldown are selected from its dimension table (or
+//! the fact table, for virtual time levels), with any cuts on the same
+//! dimension applied, and cross joined into a grid. The aggregated result is
+//! then left joined onto the grid.
+//!
+//! ClickHouse fills unmatched columns of a left join with default values,
+//! so the aggregated result is marked with `dense_matched` to tell missing
+//! combinations apart from real zeros.
+
+use itertools::join;
+
+use super::bins::bin_dim_subquery;
+use super::cuts::cut_sql_string;
+use super::time::time_part_sql;
+use super::{CutSql, DenseSql, DrilldownSql};
+
+pub fn calculate(
+    final_sql: String,
+    final_drill_cols: &str,
+    drills: &[DrilldownSql],
+    cuts: &[CutSql],
+    num_measures: usize,
+    dense: &DenseSql,
+    ) -> String
+{
+    if drills.is_empty() {
+        return final_sql;
+    }
+
+    let mut grid_sql = drill_members_sql(&drills[0], cuts);
+
+    for drill in &drills[1..] {
+        grid_sql = format!("select * from ({}) dense_grid cross join ({}) dense_members",
+            grid_sql,
+            drill_members_sql(drill, cuts),
+        );
+    }
+
+    let fill = dense.fill
+        .map(|f| f.to_string())
+        .unwrap_or_else(|| "NULL".to_owned());
+
+    let mea_cols = (0..num_measures)
+        .map(|i| format!("if(dense_matched = 1, final_m{i}, {fill}) as final_m{i}", i = i, fill = fill));
+
+    format!("select {drill_cols}, {mea_cols} from ({grid_sql}) dense_grid \
+        all left join (select *, 1 as dense_matched from ({final_sql})) dense_result \
+        using {drill_cols}",
+        drill_cols = final_drill_cols,
+        mea_cols = join(mea_cols, ", "),
+        grid_sql = grid_sql,
+        final_sql = final_sql,
+    )
+}
+
+/// Every member of a drilldown, with the same column aliases as the final result.
+fn drill_members_sql(drill: &DrilldownSql, cuts: &[CutSql]) -> String {
+    // bin buckets only exist in the bin subquery, which already applies bin cuts
+    if let Some(ref bin) = drill.bin {
+        return format!("select distinct {} from ({})",
+            drill.col_alias_only_string(),
+            bin_dim_subquery(drill, bin).sql,
+        );
+    }
+
+    let drill_cols = match drill.time {
+        Some(ref time) => drill.time_col_alias_string(time, time_part_sql),
+        None => drill.col_alias_string(),
+    };
+
+    let drill_table = drill.table_sql();
+
+    // cuts on the same dimension limit its members. Cuts without members or
+    // range are only markers for the default hierarchy.
+    let cut_clauses: Vec<_> = cuts.iter()
+        .filter(|c| {
+            c.table.name == drill.table.name &&
+            c.foreign_key == drill.foreign_key &&
+            c.inline_table == drill.inline_table
+        })
+        .filter(|c| !c.members.is_empty() || c.range.is_some())
+        .map(|c| cut_sql_string(c))
+        .collect();
+
+    let where_sql = if cut_clauses.is_empty() {
+        "".to_owned()
+    } else {
+        format!(" where {}", join(cut_clauses, " and "))
+    };
+
+    format!("select distinct {} from {}{}",
+        drill_cols,
+        drill_table,
+        where_sql,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Table;
+    use tesseract_core::names::Mask;
+    use tesseract_core::query_ir::{LevelColumn, MemberType};
+
+    fn drill(key_column: &str, alias_postfix: &str) -> DrilldownSql {
+        DrilldownSql {
+            alias_postfix: alias_postfix.into(),
+            foreign_key: format!("{}_id", key_column),
+            primary_key: "id".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: format!("dim_{}", key_column), schema: None, primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: key_column.into(),
+                    name_column: None,
+                },
+            ],
+            property_columns: vec![],
+            bin: None,
+            time: None,
+        }
+    }
+
+    #[test]
+    fn dense_grid() {
+        let drills = vec![drill("state", "Geography"), drill("year", "Year")];
+        let cuts = vec![
+            CutSql {
+                foreign_key: "state_id".into(),
+                primary_key: "id".into(),
+                inline_table: None,
+                parent_child: None,
+                snowflake_sql: None,
+                table: Table { name: "dim_state".into(), schema: None, primary_key: None },
+                column: "region".into(),
+                members: vec!["West".into()],
+                member_type: MemberType::Text,
+                mask: Mask::Include,
+                for_match: false,
+                range: None,
+                time_part: None,
+                fiscal_year_start_month: None,
+            },
+        ];
+        let dense = DenseSql { fill: Some(0.0) };
+
+        assert_eq!(
+            calculate("final".into(), "state_Geography, year_Year", &drills, &cuts, 1, &dense),
+            "select state_Geography, year_Year, if(dense_matched = 1, final_m0, 0) as final_m0 from (\
+                select * from (select distinct state as state_Geography from dim_state where region in ('West')) dense_grid \
+                cross join (select distinct year as year_Year from dim_year) dense_members\
+            ) dense_grid \
+            all left join (select *, 1 as dense_matched from (final)) dense_result \
+            using state_Geography, year_Year".to_owned(),
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use itertools::join;
-use tesseract_core::{QueryIr};
+use tesseract_core::{Aggregator, QueryIr};
+use tesseract_core::query_ir::{MeasureSql, TopSql};
 
 
 pub fn wrap_options(
@@ -15,17 +16,51 @@ pub fn wrap_options(
     let sort = &query_ir.sort;
     let limit = &query_ir.limit;
     let filters = &query_ir.filters;
+    let filter_exprs = &query_ir.filter_exprs;
     // Now that final groupings are done, do wrapping options
     // like top, filter, sort
     if let Some(top) = top {
-        final_sql = format!("select * from ({}) {} order by {} {} limit {} by {}",
+        let top_where_sql = if let Some(tw) = top_where {
+            format!("where {} {}", tw.by_column, tw.constraint.sql_string())
+        } else {
+            "".into()
+        };
+
+        // the measures of top_other can be followed by the parts of its averages,
+        // which are left out of the result. Columns that are 'Other' or null in
+        // the "Other" row are converted to the same type here, for the union.
+        let top_cols = if top.other {
+            let drill_cols = top_other_drill_cols(final_drill_cols, query_ir, top).into_iter()
+                .map(|(col, other_col)| {
+                    match other_col {
+                        OtherCol::By => col.to_owned(),
+                        OtherCol::Label => format!("toString({}) as {}", col, col),
+                        OtherCol::Null => format!("toNullable({}) as {}", col, col),
+                    }
+                });
+            let mea_cols = (0..num_measures).map(|i| format!("toNullable(final_m{}) as final_m{}", i, i));
+            format!("{}, {}", join(drill_cols, ", "), join(mea_cols, ", "))
+        } else {
+            "*".to_owned()
+        };
+
+        let top_sql = format!("select {} from ({}) {} order by {} limit {} by {}",
+            top_cols,
             final_sql,
-            if let Some(tw) = top_where { format!("where {} {}", tw.by_column, tw.constraint.sql_string()) } else { "".into() },
-            join(&top.sort_columns, ", "),
-            top.sort_direction.sql_string(),
+            top_where_sql,
+            join(top.sort.iter().map(|s| s.sql_string()), ", "),
             top.n,
             top.by_column,
         );
+
+        final_sql = if top.other {
+            format!("select * from ({}) union all {}",
+                top_sql,
+                top_other_sql(&final_sql, &top_where_sql, final_drill_cols, query_ir, top),
+            )
+        } else {
+            top_sql
+        };
     }
 
     // There's a final wrapper clause no matter what.
@@ -46,16 +81,26 @@ pub fn wrap_options(
     };
 
     let sort_sql = {
-        if let Some(sort) = sort {
-            format!("order by {} {}, {}",
-                sort.column,
-                sort.direction.sql_string(),
+        if !sort.is_empty() {
+            format!("order by {}, {}",
+                join(sort.iter().map(|s| s.sql_string()), ", "),
                 final_drill_cols,
             )
         } else if let Some(top) = top {
-            format!("order by {} asc, {}",
+            // the "Other" row is the only one with a null member key,
+            // and always goes at the end of its group
+            let other_sort = if top.other {
+                other_member_key_col(final_drill_cols, query_ir, top)
+                    .map(|col| format!("isNull({}) asc, ", col))
+                    .unwrap_or_else(|| "".into())
+            } else {
+                "".into()
+            };
+
+            format!("order by {} asc, {}{}",
                 top.by_column,
-                join(top.sort_columns.iter().map(|c| format!("{} desc", c)), ", "),
+                other_sort,
+                join(top.sort.iter().map(|s| s.sql_string()), ", "),
             )
         } else {
             // default uses just final drill cols
@@ -66,7 +111,7 @@ pub fn wrap_options(
         }
     };
 
-    let mut filters_sql = if !filters.is_empty() {
+    let mut filters_sql = if !filters.is_empty() || !filter_exprs.is_empty() {
         let filter_clauses = filters.iter()
             .map(|filter| {
                 if let Some(operator) = &filter.operator {
@@ -75,7 +120,8 @@ pub fn wrap_options(
                 } else {
                     format!("{} {}", filter.by_column, filter.constraint.sql_string())
                 }
-            });
+            })
+            .chain(filter_exprs.iter().map(|expr| expr.sql_string()));
         format!("where {}", join(filter_clauses, " and "))
     }
     else {
@@ -86,13 +132,24 @@ pub fn wrap_options(
     {
         let sparse_clauses = (0..num_measures).into_iter().map(|i| format!("isNotNull(final_m{})", i));
         let sparse_filter_sql = join(sparse_clauses, " and ");
-        if filters.is_empty() && query_ir.sparse {
+        if filters_sql.is_empty() && query_ir.sparse {
             filters_sql = format!("where {}", sparse_filter_sql);
-        } else if !filters.is_empty() && query_ir.sparse {
+        } else if !filters_sql.is_empty() && query_ir.sparse {
             filters_sql = format!("{} and {}", filters_sql, sparse_filter_sql);
         }
     }
 
+    // Nonempty only needs one measure with a value
+    if query_ir.nonempty && num_measures > 0 {
+        let nonempty_clauses = (0..num_measures).into_iter().map(|i| format!("isNotNull(final_m{})", i));
+        let nonempty_filter_sql = format!("({})", join(nonempty_clauses, " or "));
+        if filters_sql.is_empty() {
+            filters_sql = format!("where {}", nonempty_filter_sql);
+        } else {
+            filters_sql = format!("{} and {}", filters_sql, nonempty_filter_sql);
+        }
+    }
+
 
     final_sql = format!("select * from ({}) {} {} {}",
         final_sql,
@@ -103,3 +160,319 @@ pub fn wrap_options(
 
     final_sql
 }
+
+/// How a drilldown column is filled in the "Other" row of a top_other query.
+enum OtherCol {
+    /// a column of the `by_column` drilldown, which is grouped on
+    By,
+    /// a label of a ranked drilldown, set to 'Other'
+    Label,
+    /// any other column, set to null
+    Null,
+}
+
+/// Each column of `final_drill_cols`, with how it's filled in the "Other" row.
+fn top_other_drill_cols<'a>(final_drill_cols: &'a str, query_ir: &QueryIr, top: &TopSql) -> Vec<(&'a str, OtherCol)> {
+    let by_drill_cols = top_by_drill_cols(query_ir, top);
+
+    let label_cols: Vec<_> = query_ir.drills.iter()
+        .flat_map(|d| {
+            d.level_columns.iter()
+                .filter_map(|l| l.name_column.as_ref())
+                .map(|name_col| format!("{}_{}", name_col, d.alias_postfix))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    final_drill_cols.split(",")
+        .map(|col| col.trim())
+        .filter(|col| !col.is_empty())
+        .map(|col| {
+            if by_drill_cols.iter().any(|c| c == col) {
+                (col, OtherCol::By)
+            } else if label_cols.iter().any(|c| c == col) {
+                (col, OtherCol::Label)
+            } else {
+                (col, OtherCol::Null)
+            }
+        })
+        .collect()
+}
+
+/// The columns of the drilldown that `top.by_column` belongs to.
+fn top_by_drill_cols(query_ir: &QueryIr, top: &TopSql) -> Vec<String> {
+    query_ir.drills.iter()
+        .map(|d| d.col_alias_only_vec())
+        .find(|cols| cols.contains(&top.by_column))
+        .unwrap_or_else(|| vec![top.by_column.clone()])
+}
+
+/// Aggregates everything below the top n of each `by_column` group into
+/// one "Other" row per group.
+///
+/// The ranked drilldown label columns are set to 'Other', and all other columns outside of
+/// the `by_column` drilldown are set to null. The ranked rows are converted to match
+/// in `wrap_options`, since ClickHouse doesn't convert them in the union.
+///
+/// Measures are re-aggregated from the final measure columns with their own aggregator.
+/// Averages are the sum of their numerators over the sum of their denominators, which
+/// are the extra measures from `top_other_meas`. Other aggregators can't be rolled up
+/// again, and are rejected before this point.
+fn top_other_sql(
+    final_sql: &str,
+    top_where_sql: &str,
+    final_drill_cols: &str,
+    query_ir: &QueryIr,
+    top: &TopSql,
+    ) -> String
+{
+    let by_drill_cols = top_by_drill_cols(query_ir, top);
+
+    let drill_cols = top_other_drill_cols(final_drill_cols, query_ir, top).into_iter()
+        .map(|(col, other_col)| {
+            match other_col {
+                OtherCol::By => col.to_owned(),
+                OtherCol::Label => format!("'Other' as {}", col),
+                OtherCol::Null => format!("NULL as {}", col),
+            }
+        });
+
+    let mut avg_part_idx = query_ir.meas.len();
+
+    let mea_cols: Vec<_> = query_ir.meas.iter().enumerate()
+        .map(|(i, mea)| {
+            match mea.aggregator {
+                Aggregator::Sum | Aggregator::Count | Aggregator::WeightedSum { .. } => {
+                    format!("toNullable(sum(final_m{})) as final_m{}", i, i)
+                },
+                Aggregator::Max => format!("toNullable(max(final_m{})) as final_m{}", i, i),
+                Aggregator::Min => format!("toNullable(min(final_m{})) as final_m{}", i, i),
+                Aggregator::Average | Aggregator::WeightedAverage { .. } => {
+                    let sql = format!("toNullable(sum(final_m{}) / sum(final_m{})) as final_m{}", avg_part_idx, avg_part_idx + 1, i);
+                    avg_part_idx += 2;
+                    sql
+                },
+                // other aggregators are rejected by `Schema::sql_query`
+                _ => format!("NULL as final_m{}", i),
+            }
+        })
+        .collect();
+
+    // 18446744073709551615 is the max u64, for an unbounded limit after the offset
+    format!("select {}, {} from (\
+            select * from ({}) {} order by {} limit {}, 18446744073709551615 by {}\
+        ) group by {}",
+        join(drill_cols, ", "),
+        join(mea_cols, ", "),
+        final_sql,
+        top_where_sql,
+        join(top.sort.iter().map(|s| s.sql_string()), ", "),
+        top.n,
+        top.by_column,
+        join(&by_drill_cols, ", "),
+    )
+}
+
+/// Measures to aggregate for a top_other query: the query's measures, followed by the
+/// numerator and denominator of each average, for rolling up the "Other" row.
+pub fn top_other_meas(meas: &[MeasureSql]) -> Vec<MeasureSql> {
+    let mut all_meas = meas.to_vec();
+
+    for mea in meas {
+        let avg_parts = match mea.aggregator {
+            Aggregator::Average => [
+                MeasureSql { aggregator: Aggregator::Sum, column: mea.column.clone() },
+                MeasureSql { aggregator: Aggregator::Count, column: mea.column.clone() },
+            ],
+            Aggregator::WeightedAverage { ref weight_column } => [
+                MeasureSql { aggregator: Aggregator::Sum, column: format!("{} * {}", mea.column, weight_column) },
+                MeasureSql { aggregator: Aggregator::Sum, column: weight_column.clone() },
+            ],
+            _ => continue,
+        };
+
+        all_meas.extend(avg_parts.iter().cloned());
+    }
+
+    all_meas
+}
+
+/// The key column of the first drilldown that is not the `by_column` drilldown; this
+/// is null only for the "Other" row.
+fn other_member_key_col(final_drill_cols: &str, query_ir: &QueryIr, top: &TopSql) -> Option<String> {
+    query_ir.drills.iter()
+        .filter(|d| !d.col_alias_only_vec().contains(&top.by_column))
+        .filter_map(|d| {
+            d.level_columns.last()
+                .map(|l| format!("{}_{}", l.key_column, d.alias_postfix))
+        })
+        .find(|col| final_drill_cols.split(",").any(|c| c.trim() == col))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Table;
+    use tesseract_core::query::SortDirection;
+    use tesseract_core::query_ir::{DrilldownSql, LevelColumn, SortSql, TableSql};
+
+    fn drill(key_column: &str, name_column: &str, alias_postfix: &str) -> DrilldownSql {
+        DrilldownSql {
+            alias_postfix: alias_postfix.into(),
+            foreign_key: format!("{}_id", key_column),
+            primary_key: "id".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: format!("dim_{}", key_column), schema: None, primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: key_column.into(),
+                    name_column: Some(name_column.into()),
+                },
+            ],
+            property_columns: vec![],
+            bin: None,
+            time: None,
+        }
+    }
+
+    fn top_other_query_ir(meas: Vec<MeasureSql>) -> QueryIr {
+        QueryIr {
+            table: TableSql { name: "sales".into(), primary_key: None },
+            cuts: vec![],
+            drills: vec![drill("year", "year_name", "Year"), drill("product", "product_name", "Product")],
+            meas,
+            hidden_drills: vec![],
+            filters: vec![],
+            filter_exprs: vec![],
+            top: Some(TopSql {
+                n: 3,
+                by_column: "year_Year".into(),
+                sort: vec![SortSql { direction: SortDirection::Desc, column: "final_m0".into() }],
+                other: true,
+            }),
+            top_where: None,
+            sort: vec![],
+            limit: None,
+            rca: None,
+            growth: None,
+            rate: None,
+            rank: None,
+            totals: None,
+            sparse: false,
+            dense: None,
+            nonempty: false,
+            distinct: false,
+        }
+    }
+
+    fn mea(aggregator: Aggregator, column: &str) -> MeasureSql {
+        MeasureSql { aggregator, column: column.into() }
+    }
+
+    const DRILL_COLS: &str = "year_Year, year_name_Year, product_Product, product_name_Product";
+
+    #[test]
+    fn top_other_rollup() {
+        let query_ir = top_other_query_ir(vec![
+            mea(Aggregator::Sum, "sales"),
+            mea(Aggregator::Max, "price"),
+            mea(Aggregator::Min, "price"),
+        ]);
+        let top = query_ir.top.as_ref().unwrap();
+
+        assert_eq!(
+            top_other_sql("sub", "", DRILL_COLS, &query_ir, top),
+            "select year_Year, year_name_Year, NULL as product_Product, 'Other' as product_name_Product, \
+            toNullable(sum(final_m0)) as final_m0, toNullable(max(final_m1)) as final_m1, toNullable(min(final_m2)) as final_m2 from (\
+                select * from (sub)  order by final_m0 desc limit 3, 18446744073709551615 by year_Year\
+            ) group by year_Year, year_name_Year".to_owned(),
+        );
+    }
+
+    #[test]
+    fn top_other_averages() {
+        let meas = vec![
+            mea(Aggregator::Sum, "sales"),
+            mea(Aggregator::Average, "price"),
+            mea(Aggregator::WeightedAverage { weight_column: "weight".into() }, "rating"),
+        ];
+
+        let all_meas = top_other_meas(&meas);
+        let all_meas: Vec<_> = all_meas.iter()
+            .map(|m| (m.aggregator.clone(), m.column.as_str()))
+            .collect();
+        assert_eq!(
+            all_meas,
+            vec![
+                (Aggregator::Sum, "sales"),
+                (Aggregator::Average, "price"),
+                (Aggregator::WeightedAverage { weight_column: "weight".into() }, "rating"),
+                (Aggregator::Sum, "price"),
+                (Aggregator::Count, "price"),
+                (Aggregator::Sum, "rating * weight"),
+                (Aggregator::Sum, "weight"),
+            ],
+        );
+
+        let query_ir = top_other_query_ir(meas);
+        let top = query_ir.top.as_ref().unwrap();
+
+        assert_eq!(
+            top_other_sql("sub", "", DRILL_COLS, &query_ir, top),
+            "select year_Year, year_name_Year, NULL as product_Product, 'Other' as product_name_Product, \
+            toNullable(sum(final_m0)) as final_m0, toNullable(sum(final_m3) / sum(final_m4)) as final_m1, toNullable(sum(final_m5) / sum(final_m6)) as final_m2 from (\
+                select * from (sub)  order by final_m0 desc limit 3, 18446744073709551615 by year_Year\
+            ) group by year_Year, year_name_Year".to_owned(),
+        );
+    }
+
+    #[test]
+    fn top_other_leaves_out_average_parts() {
+        let query_ir = top_other_query_ir(vec![mea(Aggregator::Average, "price")]);
+
+        let sql = wrap_options("sub".into(), DRILL_COLS, &query_ir, 1);
+
+        assert!(sql.starts_with(
+            "select * from (select * from (\
+                select year_Year, year_name_Year, toNullable(product_Product) as product_Product, toString(product_name_Product) as product_name_Product, \
+                toNullable(final_m0) as final_m0 from (sub)  \
+                order by final_m0 desc limit 3 by year_Year\
+            ) union all select"
+        ));
+        assert!(sql.contains("toNullable(sum(final_m1) / sum(final_m2)) as final_m0"));
+    }
+
+    #[test]
+    fn top_other_numeric_key() {
+        // without a name column, the ranked key is the only column set to null
+        let mut query_ir = top_other_query_ir(vec![mea(Aggregator::Sum, "sales")]);
+        query_ir.drills[1].level_columns[0].name_column = None;
+
+        let drill_cols = "year_Year, year_name_Year, product_Product";
+        let sql = wrap_options("sub".into(), drill_cols, &query_ir, 1);
+
+        assert_eq!(
+            sql,
+            "select * from (select * from (select year_Year, year_name_Year, toNullable(product_Product) as product_Product, \
+                toNullable(final_m0) as final_m0 from (sub)  order by final_m0 desc limit 3 by year_Year\
+            ) union all \
+            select year_Year, year_name_Year, NULL as product_Product, toNullable(sum(final_m0)) as final_m0 from (\
+                select * from (sub)  order by final_m0 desc limit 3, 18446744073709551615 by year_Year\
+            ) group by year_Year, year_name_Year)  \
+            order by year_Year asc, isNull(product_Product) asc, final_m0 desc ".to_owned(),
+        );
+    }
+
+    #[test]
+    fn top_other_member_key_col() {
+        // a column is only found by its whole name
+        let query_ir = top_other_query_ir(vec![mea(Aggregator::Sum, "sales")]);
+        let top = query_ir.top.as_ref().unwrap();
+
+        let drill_cols = "year_Year, year_name_Year, product_Product_parent, product_name_Product";
+        assert_eq!(other_member_key_col(drill_cols, &query_ir, top), None);
+        assert_eq!(other_member_key_col(DRILL_COLS, &query_ir, top), Some("product_Product".to_owned()));
+    }
+}
@@ -1,17 +1,22 @@
 use itertools::join;
+use tesseract_core::names::Mask;
 
 use super::aggregator::{
     agg_sql_string_pass_1,
     agg_sql_string_pass_2,
     agg_sql_string_select_mea,
 };
+use super::bins::bin_dim_subquery;
 use super::cuts::cut_sql_string;
+use super::time::time_part_sql;
+use super::totals;
 use super::{
     TableSql,
     CutSql,
     DrilldownSql,
     MeasureSql,
     HiddenDrilldownSql,
+    TotalsSql,
     dim_subquery,
 };
 
@@ -24,6 +29,7 @@ pub fn primary_agg(
     drills: &[DrilldownSql],
     meas: &[MeasureSql],
     hidden_drills: Option<&[HiddenDrilldownSql]>,
+    totals: Option<&TotalsSql>,
     ) -> (String, String)
 {
     // Before first section, need to separate out inline dims.
@@ -66,9 +72,16 @@ pub fn primary_agg(
 
     // For each of the external drilldowns, we will need to add a subquery
     while let Some(drill) = ext_drills.pop() {
-        dim_subqueries.push(
-            dim_subquery(Some(drill), None)
-        );
+        // bin drilldowns generate their bucket columns in the subquery
+        if let Some(ref bin) = drill.bin {
+            dim_subqueries.push(
+                bin_dim_subquery(drill, bin)
+            );
+        } else {
+            dim_subqueries.push(
+                dim_subquery(Some(drill), None)
+            );
+        }
     }
 
     // If the table has a primary key set, check all the subqueries and find if any of them
@@ -99,17 +112,24 @@ pub fn primary_agg(
         });
     let mea_cols = join(mea_cols, ", ");
 
-    let inline_dim_cols = inline_drills.iter().map(|d| d.col_alias_string());
+    let inline_dim_cols = inline_drills.iter().map(|d| inline_col_alias_string(d));
     let inline_dim_aliass = inline_drills.iter().map(|d| d.col_alias_only_string());
 
-    let dim_idx_cols = dim_subqueries.iter().map(|d| d.foreign_key.clone());
+    // Drilldowns on the same dimension (e.g. a level and a property drilldown)
+    // join on the same foreign key, which only needs to be selected once
+    let mut dim_idx_cols: Vec<String> = vec![];
+    for d in dim_subqueries.iter() {
+        if !dim_idx_cols.contains(&d.foreign_key) {
+            dim_idx_cols.push(d.foreign_key.clone());
+        }
+    }
 
     let all_fact_dim_cols = join(inline_dim_cols.chain(dim_idx_cols.clone()), ", ");
     let all_fact_dim_aliass = join(inline_dim_aliass.chain(dim_idx_cols), ", ");
 
     // TODO remove allocation
     let hidden_drills = hidden_drills.map(|ds| ds.to_vec()).unwrap_or(vec![]);
-    let hidden_dim_cols = join(hidden_drills.iter().map(|d| d.drilldown_sql.col_alias_string()), ", ");
+    let hidden_dim_cols = join(hidden_drills.iter().map(|d| inline_col_alias_string(&d.drilldown_sql)), ", ");
 
     let mut fact_sql = format!("SELECT {}", all_fact_dim_cols);
 
@@ -128,15 +148,9 @@ pub fn primary_agg(
         let ext_cut_clause = ext_cuts_for_inline
             .iter()
             .map(|c| {
-                let cut_table = match &c.inline_table {
-                    Some(it) => {
-                        let inline_table_sql = it.sql_string();
-                        format!("({}) as {}", inline_table_sql, c.table.full_name())
-                    },
-                    None => c.table.full_name()
-                };
-
-                if c.members.is_empty() {
+                let cut_table = c.table_sql();
+
+                if c.members.is_empty() && c.range.is_none() {
                     // this case is for default hierarchy
                     // in multiple hierarchies
                     format!("{} in (SELECT {} FROM {})",
@@ -144,6 +158,17 @@ pub fn primary_agg(
                         c.primary_key,
                         cut_table,
                     )
+                } else if c.parent_child.is_some() && c.mask == Mask::Exclude {
+                    // excluding a member of a parent-child hierarchy also
+                    // excludes all of its descendants
+                    let include_cut = CutSql { mask: Mask::Include, ..(*c).clone() };
+
+                    format!("{} NOT IN (SELECT {} FROM {} WHERE {})",
+                        c.foreign_key,
+                        c.primary_key,
+                        cut_table,
+                        cut_sql_string(&include_cut),
+                    )
                 } else {
                     format!("{} IN (SELECT {} FROM {} WHERE {})",
                         c.foreign_key,
@@ -223,12 +248,74 @@ pub fn primary_agg(
     let final_mea_cols = join(final_mea_cols, ", ");
 
     // This is the final result of the groupings.
-    let final_sql = format!("SELECT {}, {} FROM ({}) GROUP BY {}",
-        final_drill_cols,
-        final_mea_cols,
-        sub_queries,
-        final_drill_cols,
-    );
+    let final_sql = if let Some(totals) = totals {
+        totals::calculate(&sub_queries, drills, &final_mea_cols, meas.len(), totals)
+    } else {
+        format!("SELECT {}, {} FROM ({}) GROUP BY {}",
+            final_drill_cols,
+            final_mea_cols,
+            sub_queries,
+            final_drill_cols,
+        )
+    };
 
     (final_sql, final_drill_cols)
 }
+
+/// Virtual time levels are generated from the fact table's date column.
+fn inline_col_alias_string(drill: &DrilldownSql) -> String {
+    match drill.time {
+        Some(ref time) => drill.time_col_alias_string(time, time_part_sql),
+        None => drill.col_alias_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::{Aggregator, Table};
+    use tesseract_core::query_ir::{MemberType, ParentChildSql};
+
+    #[test]
+    fn parent_child_cut() {
+        let table = Table { name: "dim_employee".into(), schema: None, primary_key: None };
+        let mut cut = CutSql {
+            foreign_key: "employee_id".into(),
+            primary_key: "descendant_key".into(),
+            inline_table: None,
+            parent_child: Some(ParentChildSql {
+                table: table.clone(),
+                primary_key: "id".into(),
+                parent_column: "manager_id".into(),
+                columns: vec!["id".into(), "manager_id".into()],
+                closure: None,
+                max_depth: 1,
+                ancestors: 0,
+            }),
+            snowflake_sql: None,
+            table,
+            column: "id".into(),
+            members: vec!["1".into()],
+            member_type: MemberType::NonText,
+            mask: Mask::Include,
+            for_match: false,
+            range: None,
+            time_part: None,
+            fiscal_year_start_month: None,
+        };
+        let fact_table = TableSql { name: "payroll".into(), primary_key: None };
+        let meas = vec![MeasureSql { aggregator: Aggregator::Sum, column: "salary".into() }];
+
+        let dim_table = "(select t0.id as descendant_key, t0.id as id, t0.manager_id as manager_id from dim_employee as t0 \
+            union all select t0.id as descendant_key, t1.id as id, t1.manager_id as manager_id from dim_employee as t0 \
+            inner join dim_employee as t1 on t1.id = t0.manager_id) as dim_employee";
+
+        let (sql, _) = primary_agg(&fact_table, &[cut.clone()], &[], &meas, None, None);
+        assert!(sql.contains(&format!("WHERE employee_id IN (SELECT descendant_key FROM {} WHERE id in (1))", dim_table)));
+
+        // excluding a member also excludes its descendants
+        cut.mask = Mask::Exclude;
+        let (sql, _) = primary_agg(&fact_table, &[cut], &[], &meas, None, None);
+        assert!(sql.contains(&format!("WHERE employee_id NOT IN (SELECT descendant_key FROM {} WHERE id in (1))", dim_table)));
+    }
+}
@@ -0,0 +1,113 @@
+//! Rank is calculated the same way as growth: the final result is grouped by
+//! every drilldown except the ranked one, and measure values are collected into
+//! arrays (already sorted) from which ranks can be calculated.
+//!
+//! The ranks are then joined back onto the final result, so that the rest of
+//! the columns do not need to be packed and unpacked.
+//!
+//! Members with a null measure value are not ranked, and get a rank of 0.
+
+use itertools::join;
+use tesseract_core::query::RankType;
+
+use super::RankSql;
+
+pub fn calculate(
+    final_sql: String,
+    final_drill_cols: &str,
+    rank: &RankSql,
+    ) -> String
+{
+    // Everything that's not in the ranked drilldown is a group
+    let rank_drill_cols: Vec<_> = rank.drill.col_alias_only_vec().iter()
+        .flat_map(|cols| cols.split(", ").map(|c| c.to_owned()).collect::<Vec<_>>())
+        .collect();
+
+    let group_cols: Vec<_> = final_drill_cols.split(",")
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty() && !rank_drill_cols.iter().any(|r| r == c))
+        .collect();
+    let group_cols = join(group_cols, ", ");
+
+    let key_col = rank.key_col_alias();
+
+    // The measure array is sorted, so the first occurrence of a value is its
+    // standard rank, and the order of first occurrences is its dense rank.
+    let ranks = match rank.rank_type {
+        RankType::Standard => "arrayMap(i -> indexOf(rank_meas, rank_meas[i]), arrayEnumerate(rank_meas))",
+        RankType::Dense => "arrayEnumerateDense(rank_meas)",
+    };
+
+    format!("\
+        select * from ({fnl_sql}) \
+        all left join (\
+            select {group_cols}{comma_for_group_cols} rank_key as {key_col}, rank from (\
+                select {group_cols}{comma_for_group_cols} \
+                    groupArray({key_col}) as rank_keys, \
+                    groupArray({rank_mea}) as rank_meas, \
+                    {ranks} as ranks \
+                from (\
+                    select * from ({fnl_sql}) \
+                    where isNotNull({rank_mea}) \
+                    order by {rank_mea} {direction}\
+                ) \
+                {group_by_for_group_cols} {group_cols}\
+            ) \
+            array join rank_keys as rank_key, ranks as rank\
+        ) using {group_cols}{comma_for_group_cols} {key_col}",
+        fnl_sql = final_sql,
+        group_cols = group_cols,
+        comma_for_group_cols = if group_cols.is_empty() {""} else {","},
+        group_by_for_group_cols = if group_cols.is_empty() {""} else {"group by"},
+        key_col = key_col,
+        rank_mea = rank.mea,
+        ranks = ranks,
+        direction = rank.direction.sql_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Table;
+    use tesseract_core::query::SortDirection;
+    use tesseract_core::query_ir::{DrilldownSql, LevelColumn};
+
+    #[test]
+    fn rank_within_groups() {
+        let rank = RankSql {
+            drill: DrilldownSql {
+                alias_postfix: "geo".into(),
+                foreign_key: "geo_id".into(),
+                primary_key: "state_id".into(),
+                inline_table: None,
+                parent_child: None,
+                snowflake_sql: None,
+                table: Table { name: "dim_geo".into(), schema: None, primary_key: None },
+                level_columns: vec![
+                    LevelColumn {
+                        key_column: "state_id".into(),
+                        name_column: Some("state_name".into()),
+                    },
+                ],
+                property_columns: vec![],
+                bin: None,
+                time: None,
+            },
+            mea: "final_m0".into(),
+            direction: SortDirection::Desc,
+            rank_type: RankType::Dense,
+        };
+
+        let sql = calculate(
+            "select 1".into(),
+            "year_year, state_id_geo, state_name_geo",
+            &rank,
+        );
+
+        assert_eq!(
+            sql,
+            "select * from (select 1) all left join (select year_year, rank_key as state_id_geo, rank from (select year_year, groupArray(state_id_geo) as rank_keys, groupArray(final_m0) as rank_meas, arrayEnumerateDense(rank_meas) as ranks from (select * from (select 1) where isNotNull(final_m0) order by final_m0 desc) group by year_year) array join rank_keys as rank_key, ranks as rank) using year_year, state_id_geo".to_owned(),
+        );
+    }
+}
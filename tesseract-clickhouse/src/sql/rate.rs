@@ -38,7 +38,7 @@ pub fn rate_calculation(
 
     // Call primary agg
     let (final_sql, _final_drill_cols) = {
-        primary_agg(table, cuts, &new_drills, meas, None)
+        primary_agg(table, cuts, &new_drills, meas, None, None)
     };
 
     let mut rate_sql = "".to_string();
@@ -132,8 +132,8 @@ pub fn calculate(
     // If there's no internal cuts, then b, c, d are calculated from a.
 
     // First do aggregation for part a, b
-    let (a, a_final_drills) = primary_agg(table, &ac_cuts, &a_drills, &all_meas, None);
-    let (b, b_final_drills) = primary_agg(table, &bd_cuts, &b_drills, &all_meas, None);
+    let (a, a_final_drills) = primary_agg(table, &ac_cuts, &a_drills, &all_meas, None, None);
+    let (b, b_final_drills) = primary_agg(table, &bd_cuts, &b_drills, &all_meas, None, None);
 
     // replace final_m0 with letter name.
     // I put the rca measure at the beginning of the drills, so it should
@@ -0,0 +1,70 @@
+//! Virtual time levels are generated from a date column of the fact table,
+//! as integer ids like the usual time dimension tables (e.g. `201901` for
+//! January 2019).
+
+use tesseract_core::schema::TimePart;
+
+pub fn time_part_sql(column: &str, time_part: &TimePart, fiscal_year_start_month: Option<u32>) -> String {
+    // fiscal parts are the calendar parts of the shifted date
+    let column = match time_part.month_offset(fiscal_year_start_month) {
+        0 => column.to_owned(),
+        months => format!("addMonths({}, {})", column, months),
+    };
+
+    match time_part {
+        TimePart::Year => format!("toYear({})", column),
+        TimePart::Quarter => format!("toYear({c}) * 10 + toQuarter({c})", c = column),
+        TimePart::Month => format!("toYYYYMM({})", column),
+        TimePart::Week => format!("toISOYear({c}) * 100 + toISOWeek({c})", c = column),
+        TimePart::Day => format!("toYYYYMMDD({})", column),
+        TimePart::IsoYear => format!("toISOYear({})", column),
+        TimePart::FiscalYear => format!("toYear({})", column),
+        TimePart::FiscalQuarter => format!("toYear({c}) * 10 + toQuarter({c})", c = column),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Table;
+    use tesseract_core::names::Mask;
+    use tesseract_core::query_ir::{CutSql, MemberType};
+    use super::super::cuts::cut_sql_string;
+
+    #[test]
+    fn time_part_cut() {
+        let cut = CutSql {
+            foreign_key: "sale_date".into(),
+            primary_key: "sale_date".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: "sales".into(), schema: None, primary_key: None },
+            column: "sale_date".into(),
+            members: vec!["20191".into(), "20192".into()],
+            member_type: MemberType::NonText,
+            mask: Mask::Include,
+            for_match: false,
+            range: None,
+            time_part: Some(TimePart::Quarter),
+            fiscal_year_start_month: None,
+        };
+
+        assert_eq!(
+            cut_sql_string(&cut),
+            "toYear(sale_date) * 10 + toQuarter(sale_date) in (20191, 20192)".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::Week, None),
+            "toISOYear(sale_date) * 100 + toISOWeek(sale_date)".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::FiscalQuarter, Some(7)),
+            "toYear(addMonths(sale_date, 6)) * 10 + toQuarter(addMonths(sale_date, 6))".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::FiscalYear, None),
+            "toYear(sale_date)".to_owned(),
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Totals roll up the final grouping `WITH ROLLUP`, which removes grouping
+//! keys from the right: the first subtotal rolls up the last totals drilldown,
+//! and so on until the grand total.
+//!
+//! ClickHouse fills rolled up keys with default values instead of nulls, so
+//! each totals drilldown is grouped as a tuple with a leading 1; once rolled
+//! up, the tuple has a leading 0. The drilldowns that aren't rolled up are
+//! grouped as a single leading tuple, so the only extra grouping is the one
+//! where that tuple is rolled up too, which is filtered out.
+//!
+//! Rolled up drilldown columns are null, and each row is flagged with the
+//! number of drilldowns rolled up (0 for regular rows).
+
+use itertools::join;
+
+use super::{DrilldownSql, TotalsSql};
+
+pub fn calculate(
+    sub_queries: &str,
+    drills: &[DrilldownSql],
+    final_mea_cols: &str,
+    num_measures: usize,
+    totals: &TotalsSql,
+    ) -> String
+{
+    let fixed_drills: Vec<_> = drills.iter()
+        .filter(|d| !totals.drills.contains(d))
+        .collect();
+
+    let mut key_cols = vec![];
+    let mut key_aliases = vec![];
+
+    if !fixed_drills.is_empty() {
+        let fixed_cols = fixed_drills.iter().flat_map(|d| drill_cols(d));
+        key_cols.push(format!("tuple(1, {}) as totals_fixed", join(fixed_cols, ", ")));
+        key_aliases.push("totals_fixed".to_owned());
+    }
+
+    for (i, drill) in totals.drills.iter().enumerate() {
+        key_cols.push(format!("tuple(1, {}) as totals_key_{}", join(drill_cols(drill), ", "), i));
+        key_aliases.push(format!("totals_key_{}", i));
+    }
+
+    // unpack the key tuples, in the original drilldown order
+    let mut fixed_idx = 1;
+    let mut select_cols = vec![];
+
+    for drill in drills {
+        match totals.drills.iter().position(|d| d == drill) {
+            Some(i) => {
+                for (j, col) in drill_cols(drill).iter().enumerate() {
+                    select_cols.push(format!("if(tupleElement(totals_key_{i}, 1) = 1, tupleElement(totals_key_{i}, {j}), NULL) as {col}",
+                        i = i,
+                        j = j + 2,
+                        col = col,
+                    ));
+                }
+            },
+            None => {
+                for col in drill_cols(drill) {
+                    fixed_idx += 1;
+                    select_cols.push(format!("tupleElement(totals_fixed, {}) as {}", fixed_idx, col));
+                }
+            },
+        }
+    }
+
+    let mea_cols = (0..num_measures).map(|i| format!("final_m{}", i));
+    select_cols.extend(mea_cols);
+
+    let total_flag = (0..totals.drills.len())
+        .map(|i| format!("(tupleElement(totals_key_{}, 1) = 0)", i));
+    select_cols.push(format!("{} as total", join(total_flag, " + ")));
+
+    let where_sql = if fixed_drills.is_empty() {
+        "".to_owned()
+    } else {
+        " where tupleElement(totals_fixed, 1) = 1".to_owned()
+    };
+
+    format!("select {} from (select {}, {} from ({}) group by {} with rollup){}",
+        join(select_cols, ", "),
+        join(key_cols, ", "),
+        final_mea_cols,
+        sub_queries,
+        join(key_aliases, ", "),
+        where_sql,
+    )
+}
+
+/// Flattens the drilldown's column aliases; property columns are joined into
+/// one entry by `col_alias_only_vec`.
+fn drill_cols(drill: &DrilldownSql) -> Vec<String> {
+    drill.col_alias_only_vec().iter()
+        .flat_map(|cols| cols.split(", ").map(|c| c.to_owned()).collect::<Vec<_>>())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Table;
+    use tesseract_core::query_ir::LevelColumn;
+
+    fn drill(key_column: &str, alias_postfix: &str) -> DrilldownSql {
+        DrilldownSql {
+            alias_postfix: alias_postfix.into(),
+            foreign_key: format!("{}_id", key_column),
+            primary_key: "id".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: format!("dim_{}", key_column), schema: None, primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: key_column.into(),
+                    name_column: None,
+                },
+            ],
+            property_columns: vec![],
+            bin: None,
+            time: None,
+        }
+    }
+
+    #[test]
+    fn totals_rollup() {
+        let year = drill("year", "Year");
+        let product = drill("product", "Product");
+        let totals = TotalsSql { drills: vec![product.clone()] };
+
+        assert_eq!(
+            calculate("sub", &[year, product], "sum(m0) as final_m0", 1, &totals),
+            "select tupleElement(totals_fixed, 2) as year_Year, \
+            if(tupleElement(totals_key_0, 1) = 1, tupleElement(totals_key_0, 2), NULL) as product_Product, \
+            final_m0, \
+            (tupleElement(totals_key_0, 1) = 0) as total \
+            from (\
+                select tuple(1, year_Year) as totals_fixed, tuple(1, product_Product) as totals_key_0, sum(m0) as final_m0 \
+                from (sub) group by totals_fixed, totals_key_0 with rollup\
+            ) \
+            where tupleElement(totals_fixed, 1) = 1".to_owned(),
+        );
+    }
+
+    #[test]
+    fn totals_grand_total() {
+        let year = drill("year", "Year");
+        let product = drill("product", "Product");
+        let totals = TotalsSql { drills: vec![year.clone(), product.clone()] };
+
+        assert_eq!(
+            calculate("sub", &[year, product], "sum(m0) as final_m0", 1, &totals),
+            "select if(tupleElement(totals_key_0, 1) = 1, tupleElement(totals_key_0, 2), NULL) as year_Year, \
+            if(tupleElement(totals_key_1, 1) = 1, tupleElement(totals_key_1, 2), NULL) as product_Product, \
+            final_m0, \
+            (tupleElement(totals_key_0, 1) = 0) + (tupleElement(totals_key_1, 1) = 0) as total \
+            from (\
+                select tuple(1, year_Year) as totals_key_0, tuple(1, product_Product) as totals_key_1, sum(m0) as final_m0 \
+                from (sub) group by totals_key_0, totals_key_1 with rollup\
+            )".to_owned(),
+        );
+    }
+}
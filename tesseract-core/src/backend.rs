@@ -2,7 +2,8 @@ use failure::Error;
 use futures::{Future, Stream};
 
 use crate::dataframe::DataFrame;
-use crate::query_ir::QueryIr;
+use crate::query_ir::{QueryIr, quote_standard_text};
+use crate::schema::{Table, TimePart};
 use crate::sql;
 
 
@@ -36,8 +37,36 @@ pub trait Backend {
             &query_ir.limit,
             &query_ir.rca,
             &query_ir.growth,
+            &query_ir.totals,
+            &|column, time_part, fiscal_year_start_month| self.time_part_sql(column, time_part, fiscal_year_start_month),
+            &|s| self.quote_text(s),
         )
     }
+
+    /// Checks that `generate_sql` can write the query. The standard sql
+    /// implementation only writes drilldowns, cuts, measures and totals.
+    fn check_query_ir(&self, query_ir: &QueryIr) -> Result<(), Error> {
+        sql::check_query_ir(query_ir)
+    }
+
+    /// Sql expression for the member ids of a virtual time level, over a
+    /// date column. Must match the expression used in `generate_sql`.
+    fn time_part_sql(&self, column: &str, time_part: &TimePart, fiscal_year_start_month: Option<u32>) -> String {
+        sql::time_part_sql(column, time_part, fiscal_year_start_month)
+    }
+
+    /// A text value as a string literal. Must match the quoting used in
+    /// `generate_sql`. The standard sql implementation reads backslashes
+    /// literally, as Postgres does.
+    fn quote_text(&self, s: &str) -> String {
+        quote_standard_text(s)
+    }
+
+    /// Sql returning the name and type of each column of a table, as the
+    /// first two columns. Used to check the schema against the database.
+    fn table_columns_sql(&self, table: &Table) -> String {
+        sql::table_columns_sql(table)
+    }
 }
 
 impl Clone for Box<dyn Backend + Send + Sync> {
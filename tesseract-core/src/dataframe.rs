@@ -1,4 +1,5 @@
 use failure::{Error, format_err};
+use std::collections::HashMap;
 
 
 #[derive(Debug)]
@@ -51,6 +52,107 @@ impl DataFrame {
             0
         }
     }
+
+    /// Joins dataframes on their first `num_key_cols` columns, which must be the
+    /// same in each dataframe (used for the drilldowns of multi-cube queries).
+    ///
+    /// Only key combinations found in every dataframe are kept, in the row order
+    /// of the first dataframe. The result has the key columns, then the other
+    /// columns of each dataframe in order.
+    pub fn inner_join(dfs: Vec<DataFrame>, num_key_cols: usize) -> Result<Self, Error> {
+        if dfs.is_empty() {
+            return Ok(DataFrame::new());
+        }
+
+        for df in &dfs {
+            if df.columns.len() < num_key_cols {
+                return Err(format_err!("Cannot join dataframe with fewer than {} key columns", num_key_cols));
+            }
+        }
+
+        // one key string per row, from the stringified key columns
+        let row_keys = |df: &DataFrame| -> Vec<String> {
+            let key_cols: Vec<_> = df.columns[..num_key_cols].iter()
+                .map(|col| col.stringify_column_data())
+                .collect();
+
+            (0..df.len())
+                .map(|row| {
+                    key_cols.iter()
+                        .map(|col| col[row].as_str())
+                        .collect::<Vec<_>>()
+                        .join("\u{1f}")
+                })
+                .collect()
+        };
+
+        let other_key_idxs: Vec<HashMap<String, usize>> = dfs[1..].iter()
+            .map(|df| {
+                row_keys(df).into_iter()
+                    .enumerate()
+                    .map(|(row, key)| (key, row))
+                    .collect()
+            })
+            .collect();
+
+        // row indexes into each dataframe, for each row of the result
+        let mut row_idxs: Vec<Vec<usize>> = vec![vec![]; dfs.len()];
+
+        for (row, key) in row_keys(&dfs[0]).iter().enumerate() {
+            let other_rows: Option<Vec<usize>> = other_key_idxs.iter()
+                .map(|key_idxs| key_idxs.get(key).cloned())
+                .collect();
+
+            if let Some(other_rows) = other_rows {
+                row_idxs[0].push(row);
+                for (i, other_row) in other_rows.into_iter().enumerate() {
+                    row_idxs[i + 1].push(other_row);
+                }
+            }
+        }
+
+        let mut columns: Vec<Column> = dfs[0].columns[..num_key_cols].iter()
+            .map(|col| col.take(&row_idxs[0]))
+            .collect();
+
+        for (df, idxs) in dfs.iter().zip(row_idxs.iter()) {
+            columns.extend(df.columns[num_key_cols..].iter().map(|col| col.take(idxs)));
+        }
+
+        Ok(DataFrame::from_vec(columns))
+    }
+
+    /// Divides the columns after the first `num_key_cols` by the last column,
+    /// which is removed, and multiplies them by `scale`.
+    ///
+    /// Normalized columns are nullable floats; rows with a null or zero
+    /// denominator are null.
+    pub fn normalize(mut self, num_key_cols: usize, scale: f64) -> Result<Self, Error> {
+        if self.columns.len() < num_key_cols + 2 {
+            return Err(format_err!("Cannot normalize dataframe without measure and denominator columns"));
+        }
+
+        let denominator = self.columns.pop()
+            .expect("checked column count")
+            .to_f64_vec()?;
+
+        for col in self.columns[num_key_cols..].iter_mut() {
+            let values = col.to_f64_vec()?
+                .into_iter()
+                .zip(denominator.iter())
+                .map(|(value, denom)| {
+                    match (value, denom) {
+                        (Some(value), Some(denom)) if *denom != 0.0 => Some(value / denom * scale),
+                        _ => None,
+                    }
+                })
+                .collect();
+
+            col.column_data = ColumnData::NullableFloat64(values);
+        }
+
+        Ok(self)
+    }
 }
 
 #[derive(Debug)]
@@ -109,6 +211,85 @@ impl Column {
         Ok(())
     }
 
+    /// Selects rows by index, in the order given.
+    pub fn take(&self, idxs: &[usize]) -> Column {
+        macro_rules! take {
+            ($variant:ident, $v:expr) => {
+                ColumnData::$variant(idxs.iter().map(|&i| $v[i].clone()).collect())
+            };
+        }
+
+        let column_data = match &self.column_data {
+            ColumnData::Int8(v) => take!(Int8, v),
+            ColumnData::Int16(v) => take!(Int16, v),
+            ColumnData::Int32(v) => take!(Int32, v),
+            ColumnData::Int64(v) => take!(Int64, v),
+            ColumnData::UInt8(v) => take!(UInt8, v),
+            ColumnData::UInt16(v) => take!(UInt16, v),
+            ColumnData::UInt32(v) => take!(UInt32, v),
+            ColumnData::UInt64(v) => take!(UInt64, v),
+            ColumnData::Float32(v) => take!(Float32, v),
+            ColumnData::Float64(v) => take!(Float64, v),
+            ColumnData::Text(v) => take!(Text, v),
+            ColumnData::NullableInt8(v) => take!(NullableInt8, v),
+            ColumnData::NullableInt16(v) => take!(NullableInt16, v),
+            ColumnData::NullableInt32(v) => take!(NullableInt32, v),
+            ColumnData::NullableInt64(v) => take!(NullableInt64, v),
+            ColumnData::NullableUInt8(v) => take!(NullableUInt8, v),
+            ColumnData::NullableUInt16(v) => take!(NullableUInt16, v),
+            ColumnData::NullableUInt32(v) => take!(NullableUInt32, v),
+            ColumnData::NullableUInt64(v) => take!(NullableUInt64, v),
+            ColumnData::NullableFloat32(v) => take!(NullableFloat32, v),
+            ColumnData::NullableFloat64(v) => take!(NullableFloat64, v),
+            ColumnData::NullableText(v) => take!(NullableText, v),
+        };
+
+        Column::new(self.name.clone(), column_data)
+    }
+
+    /// Converts numeric column data to floats, keeping nulls. Text columns
+    /// can't be converted.
+    pub fn to_f64_vec(&self) -> Result<Vec<Option<f64>>, Error> {
+        macro_rules! to_f64 {
+            ($v:expr) => {
+                $v.iter().map(|&e| Some(e as f64)).collect()
+            };
+        }
+        macro_rules! nullable_to_f64 {
+            ($v:expr) => {
+                $v.iter().map(|&e| e.map(|e| e as f64)).collect()
+            };
+        }
+
+        let values = match &self.column_data {
+            ColumnData::Int8(v) => to_f64!(v),
+            ColumnData::Int16(v) => to_f64!(v),
+            ColumnData::Int32(v) => to_f64!(v),
+            ColumnData::Int64(v) => to_f64!(v),
+            ColumnData::UInt8(v) => to_f64!(v),
+            ColumnData::UInt16(v) => to_f64!(v),
+            ColumnData::UInt32(v) => to_f64!(v),
+            ColumnData::UInt64(v) => to_f64!(v),
+            ColumnData::Float32(v) => to_f64!(v),
+            ColumnData::Float64(v) => to_f64!(v),
+            ColumnData::NullableInt8(v) => nullable_to_f64!(v),
+            ColumnData::NullableInt16(v) => nullable_to_f64!(v),
+            ColumnData::NullableInt32(v) => nullable_to_f64!(v),
+            ColumnData::NullableInt64(v) => nullable_to_f64!(v),
+            ColumnData::NullableUInt8(v) => nullable_to_f64!(v),
+            ColumnData::NullableUInt16(v) => nullable_to_f64!(v),
+            ColumnData::NullableUInt32(v) => nullable_to_f64!(v),
+            ColumnData::NullableUInt64(v) => nullable_to_f64!(v),
+            ColumnData::NullableFloat32(v) => nullable_to_f64!(v),
+            ColumnData::NullableFloat64(v) => nullable_to_f64!(v),
+            ColumnData::Text(_) | ColumnData::NullableText(_) => {
+                return Err(format_err!("Cannot convert text column {} to numbers", self.name));
+            },
+        };
+
+        Ok(values)
+    }
+
     /// DataFrame columns can come in many different types. This function converts
     /// all data to a common type (String).
     pub fn stringify_column_data(&self) -> Vec<String> {
@@ -379,3 +560,46 @@ pub fn is_same_columndata_type(col_1: &ColumnData, col_2: &ColumnData) -> bool {
         },
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_inner_join() {
+        let population = DataFrame::from_vec(vec![
+            Column::new("state".into(), ColumnData::Text(vec!["CA".into(), "NY".into(), "TX".into()])),
+            Column::new("year".into(), ColumnData::Int32(vec![2018, 2018, 2018])),
+            Column::new("population".into(), ColumnData::UInt64(vec![39, 19, 28])),
+        ]);
+        let income = DataFrame::from_vec(vec![
+            Column::new("state".into(), ColumnData::Text(vec!["TX".into(), "CA".into()])),
+            Column::new("year".into(), ColumnData::Int32(vec![2018, 2018])),
+            Column::new("income".into(), ColumnData::NullableFloat64(vec![Some(59.5), None])),
+        ]);
+
+        let df = DataFrame::inner_join(vec![population, income], 2).unwrap();
+
+        assert_eq!(df.columns.len(), 4);
+        assert_eq!(df.columns[0].column_data, ColumnData::Text(vec!["CA".into(), "TX".into()]));
+        assert_eq!(df.columns[1].column_data, ColumnData::Int32(vec![2018, 2018]));
+        assert_eq!(df.columns[2].column_data, ColumnData::UInt64(vec![39, 28]));
+        assert_eq!(df.columns[3].column_data, ColumnData::NullableFloat64(vec![None, Some(59.5)]));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let df = DataFrame::from_vec(vec![
+            Column::new("state".into(), ColumnData::Text(vec!["CA".into(), "NY".into(), "TX".into()])),
+            Column::new("cases".into(), ColumnData::UInt64(vec![20, 5, 7])),
+            Column::new("population".into(), ColumnData::NullableInt64(vec![Some(400), Some(0), None])),
+        ]);
+
+        let df = df.normalize(1, 100.0).unwrap();
+
+        assert_eq!(df.columns.len(), 2);
+        assert_eq!(df.columns[0].column_data, ColumnData::Text(vec!["CA".into(), "NY".into(), "TX".into()]));
+        assert_eq!(df.columns[1].column_data, ColumnData::NullableFloat64(vec![Some(5.0), None, None]));
+    }
+}
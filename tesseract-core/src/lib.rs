@@ -9,6 +9,7 @@ pub mod query;
 pub mod query_ir;
 
 use failure::{Error, format_err, bail};
+use futures::future::{self, Future};
 use log::*;
 use serde_xml_rs as serde_xml;
 use serde_xml::from_reader;
@@ -21,6 +22,9 @@ pub use self::dataframe::{DataFrame, Column, ColumnData, is_same_columndata_type
 
 pub static DEFAULT_ALLOWED_ACCESS: i32 = 0;
 
+/// Deepest descendants of a parent-child hierarchy without a closure table.
+pub static DEFAULT_PARENT_CHILD_MAX_DEPTH: u32 = 10;
+
 use self::names::{
     Cut,
     Drilldown,
@@ -30,7 +34,9 @@ use self::names::{
     Mask,
 };
 pub use self::schema::{Schema, Cube, Dimension, Table, Aggregator};
+use self::schema::{Hierarchy, Level, AggregateTable};
 use self::schema::metadata::{SchemaMetadata, CubeMetadata};
+pub use self::schema::validation::{SchemaDiagnostic, SchemaValidationError};
 use self::query_ir::{
     CutSql,
     DrilldownSql,
@@ -45,10 +51,19 @@ use self::query_ir::{
     RcaSql,
     GrowthSql,
     RateSql,
+    RankSql,
+    TotalsSql,
+    DenseSql,
     FilterSql,
+    FilterExprSql,
+    BinSql,
+    TimeSql,
+    ParentChildSql,
+    DESCENDANT_KEY,
+    ancestor_column,
 };
-pub use self::query::{Query, MeaOrCalc, FilterQuery};
-pub use self::query_ir::QueryIr;
+pub use self::query::{Query, MeaOrCalc, FilterQuery, FilterExpr, SortQuery, ColumnKey, BinQuery, NormalizeQuery};
+pub use self::query_ir::{QueryIr, MultiCubeQueryIr, MultiCubeJoin};
 macro_rules! mea_or_calc {
     ($m_or_c:expr, $query:expr) => {
         match $m_or_c {
@@ -75,6 +90,18 @@ impl Schema {
     /// Deserializes JSON schema into a `Schema`.
     pub fn from_json(raw_schema: &str) -> Result<Self, Error> {
         let schema_config = serde_json::from_str::<SchemaConfigJson>(raw_schema)?;
+        if schema_config.includes.is_some() {
+            bail!("Schemas with includes must be read from files");
+        }
+        Ok(schema_config.into())
+    }
+
+    /// Reads XML or JSON schema files, and the files that they include, into
+    /// one `Schema`. `read` returns the contents of the file at a path.
+    pub fn from_files<F>(paths: &[String], read: F) -> Result<Self, Error>
+        where F: Fn(&str) -> Result<String, Error>
+    {
+        let schema_config = schema::read_schema_config(paths, read)?;
         Ok(schema_config.into())
     }
 
@@ -90,64 +117,76 @@ impl Schema {
         Schema::from_json(&serialized)
     }
 
+    /// Imports a Mondrian 3 or Mondrian 4 XML schema into a `Schema`.
+    pub fn from_mondrian_xml(raw_schema: &str) -> Result<Self, Error> {
+        let schema_config = schema::read_mondrian_schema(raw_schema)?;
+        Ok(schema_config.into())
+    }
+
     /// Schema Validation
+    ///
+    /// Returns a `SchemaValidationError` with all the problems found.
     pub fn validate(&mut self) -> Result<(), Error> {
-        // There should be at least one dimension. Both dim and shared dim are optional,
-        // so need to do a validation check here.
-
-        for cube in &self.cubes {
-            if cube.dimensions.is_empty() {
-                bail!("Between Dimensions and Shared Dimensions, a cube must have a total of at least 1.");
-            }
-        };
-
-        // There should be no duplicate dimension names in a cube
-        for cube in &self.cubes {
-            let set = cube.dimensions.iter()
-                .map(|dim| {
-                    &dim.name
-                })
-                .collect::<HashSet<_>>();
-
-            if set.len() != cube.dimensions.len() {
-                bail!("Duplicate dimension names not allowed");
-            }
-        };
-
-        // if there's multiple hierarchies in a dim, there must be a default hierarchy.
-        // also, the default hierarchy must match names with an actual hierarchy.
-        //
-        // Also, a single hierarchy should not have a default set
-        //
-        // This means that later, we can just check whether there is a default
-        // hierarchy only, instead of also checking for hierarchy cardinality during
-        // a request
-
+        // a single hierarchy should not have a default set
         for cube in self.cubes.iter_mut() {
             for dim in cube.dimensions.iter_mut() {
                 if dim.hierarchies.len() == 1 {
                     dim.default_hierarchy = None;
-                } else if !dim.hierarchies.is_empty() {
-                    let cube_name = &cube.name;
-                    // first, default_hierarchy must be assigned
-                    let default_hierarchy = dim.default_hierarchy
-                        .clone()
-                        .ok_or_else(|| format_err!("Default hierarchy required for multiple hierarchies in cube: {} dimension: {}", cube_name, &dim.name))?;
-
-                    // if default_hierarchy exists, then check that it's in one of the
-                    // hierarchies
-                    let contains_default = dim.hierarchies.iter()
-                        .map(|hier| &hier.name)
-                        .any(|hier_name| *hier_name == default_hierarchy);
-
-                    if !contains_default {
-                        bail!("Default hierarchy must exist in multiple hierarchies");
-                    }
                 }
             }
         }
 
-        Ok(())
+        let diagnostics = schema::validation::schema_diagnostics(self);
+
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemaValidationError { diagnostics }.into())
+        }
+    }
+
+    /// Checks that the tables and columns of a cube, or of every cube, exist
+    /// in the database with a type matching their use.
+    ///
+    /// Virtual cubes are skipped, their cubes are checked on their own.
+    pub fn check_database(&self, cube_name: Option<&str>, backend: &dyn Backend) -> Box<dyn Future<Item=Vec<SchemaDiagnostic>, Error=Error>> {
+        let cubes: Vec<Cube> = self.cubes.iter()
+            .filter(|cube| cube_name.map(|name| cube.name == name).unwrap_or(true))
+            .filter(|cube| cube.cube_usages.is_none())
+            .cloned()
+            .collect();
+
+        let mut tables: Vec<Table> = vec![];
+        for table in cubes.iter().flat_map(|cube| schema::database::cube_tables(cube)) {
+            if !tables.iter().any(|t| t.full_name() == table.full_name()) {
+                tables.push(table);
+            }
+        }
+
+        let table_columns = tables.iter()
+            .map(|table| {
+                let table_name = table.full_name();
+
+                backend.exec_sql(backend.table_columns_sql(table))
+                    .map(move |df| {
+                        let columns: HashMap<String, String> = schema::database::column_types(&df)
+                            .into_iter()
+                            .collect();
+                        (table_name, columns)
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        let diagnostics = future::join_all(table_columns)
+            .map(move |table_columns| {
+                let db_columns: schema::database::DbColumns = table_columns.into_iter().collect();
+
+                cubes.iter()
+                    .flat_map(|cube| schema::database::cube_db_diagnostics(cube, &db_columns))
+                    .collect()
+            });
+
+        Box::new(diagnostics)
     }
 
     pub fn cube_metadata(&self, cube_name: &str) -> Option<CubeMetadata> {
@@ -266,8 +305,6 @@ impl Schema {
             .find(|lvl| lvl.name == level_name.level)
             .ok_or(format_err!("could not find level for level name"))?;
 
-        let table = hier.table.clone().unwrap_or_else(|| cube.table.clone());
-
         let key_column = level.key_column.clone();
         let mut header = vec!["ID".into()];
         let mut name_columns: Vec<String> = vec![];
@@ -310,7 +347,7 @@ impl Schema {
                 }
             }
 
-            table.full_name()
+            hier.table_sql().unwrap_or_else(|| cube.table.full_name())
         };
 
         let sql = format!("select distinct {}{}{} from {} order by {}",
@@ -374,7 +411,11 @@ impl Schema {
             .filter(|dim| {
                 // filter out dims that have a drilldown or cut
                 let dim_contains_drill = query.drilldowns.iter()
-                    .any(|drill| dim.name == drill.0.dimension());
+                    .any(|drill| dim.name == drill.0.dimension()) ||
+                    query.property_drilldowns.iter()
+                    .any(|p| dim.name == p.level_name.dimension()) ||
+                    query.bins.iter()
+                    .any(|b| dim.name == b.level_name.dimension());
                 let dim_contains_cut = query.cuts.iter()
                     .any(|c| dim.name == c.level_name.dimension());
                 match negate {
@@ -405,12 +446,20 @@ impl Schema {
 
         // First do checks, like making sure there's a measure, and that there's
         // either a cut or drilldown
-        if query.measures.is_empty() && query.rca.is_none() {
+        if query.measures.is_empty() && query.rca.is_none() && !query.distinct {
             return Err(format_err!("No measure found; please specify at least one"));
         }
-        if query.drilldowns.is_empty() && query.cuts.is_empty(){
+        if query.drilldowns.is_empty() && query.property_drilldowns.is_empty() && query.bins.is_empty() && query.cuts.is_empty(){
             return Err(format_err!("Either a drilldown or cut is required"));
         }
+        // the denominator comes from another cube
+        if query.normalize.is_some() {
+            bail!("Normalize needs a multi-cube query");
+        }
+
+        if self.get_cube_by_name(cube)?.cube_usages.is_some() {
+            bail!("Cube {} is a virtual cube, which needs a virtual cube query", cube);
+        }
 
         // also check that properties have a matching drilldown
         if let Some(ref rca) = query.rca {
@@ -445,7 +494,11 @@ impl Schema {
             .filter(|dim| {
                 // filter out dims that have a drilldown or cut
                 let dim_contains_drill = query.drilldowns.iter()
-                    .any(|drill| dim.name == drill.0.dimension());
+                    .any(|drill| dim.name == drill.0.dimension()) ||
+                    query.property_drilldowns.iter()
+                    .any(|p| dim.name == p.level_name.dimension()) ||
+                    query.bins.iter()
+                    .any(|b| dim.name == b.level_name.dimension());
 
                 let dim_contains_cut = query.cuts.iter()
                     .any(|c| dim.name == c.level_name.dimension());
@@ -502,11 +555,140 @@ impl Schema {
             }
         }
 
+        // for rank, check that the ranked level is drilled down on. Ranking is done
+        // on final measure columns, which growth rearranges.
+        if let Some(ref rank) = query.rank {
+            let rank_in_drills = query.drilldowns.iter()
+                .chain(query.rca.iter().flat_map(|rca| vec![&rca.drill_1, &rca.drill_2]))
+                .any(|d| d.0 == rank.level_name);
+
+            if !rank_in_drills {
+                bail!("Rank level {} is not in drilldowns", rank.level_name);
+            }
+            if query.growth.is_some() {
+                bail!("Rank cannot be combined with growth");
+            }
+        }
+
+        // top_other re-aggregates the final measure columns, so the final columns
+        // must be exactly the drilldowns and measures.
+        if query.top_other {
+            if query.top.is_none() {
+                bail!("top_other requires a top query");
+            }
+            if query.rca.is_some() || query.growth.is_some() || query.rate.is_some() || query.rank.is_some() {
+                bail!("top_other cannot be combined with calculations");
+            }
+
+            // the "Other" row is rolled up from the final rows, which only
+            // works for aggregators that can be re-aggregated
+            for mea in &query.measures {
+                let schema_mea = schema_cube.measures.iter()
+                    .find(|m| m.name == mea.0)
+                    .ok_or_else(|| format_err!("Measure {} not found", mea))?;
+
+                match schema_mea.aggregator {
+                    Aggregator::Sum | Aggregator::Count | Aggregator::Average |
+                    Aggregator::Max | Aggregator::Min |
+                    Aggregator::WeightedSum { .. } | Aggregator::WeightedAverage { .. } => (),
+                    _ => bail!("top_other cannot aggregate measure {} with aggregator {:?}", mea, schema_mea.aggregator),
+                }
+            }
+        }
+
+        // totals roll up the final groupings, so they must be drilled down on,
+        // and the final columns must be exactly the drilldowns and measures.
+        for drill in &query.totals {
+            if !query.drilldowns.contains(drill) {
+                bail!("Totals drilldown {} is not in drilldowns", drill);
+            }
+        }
+        if !query.totals.is_empty() {
+            if query.rca.is_some() || query.growth.is_some() || query.rate.is_some() || query.rank.is_some() {
+                bail!("Totals cannot be combined with calculations");
+            }
+            if query.top.is_some() {
+                bail!("Totals cannot be combined with top");
+            }
+        }
+
+        // dense fills in the final drilldown columns, so the final columns
+        // must be exactly the drilldowns and measures (growth and rank are
+        // calculated on the filled in result).
+        if query.dense {
+            if query.sparse {
+                bail!("Dense and sparse cannot both be set");
+            }
+            if query.rca.is_some() || query.rate.is_some() || !query.totals.is_empty() {
+                bail!("Dense cannot be combined with rca, rate or totals");
+            }
+        }
+        if let Some(fill) = query.dense_fill {
+            if !query.dense {
+                bail!("dense_fill requires dense");
+            }
+            if !fill.is_finite() {
+                bail!("dense_fill must be a finite number");
+            }
+        }
+
+        // distinct only returns drilldown members, so anything on measures is invalid
+        if query.distinct {
+            if !query.measures.is_empty() {
+                bail!("Distinct queries cannot have measures");
+            }
+            if !query.filters.is_empty() || query.top.is_some() || query.top_where.is_some() {
+                bail!("Distinct queries cannot have filters or top");
+            }
+            if query.rca.is_some() || query.growth.is_some() || query.rate.is_some() || query.rank.is_some() {
+                bail!("Distinct queries cannot have calculations");
+            }
+            if !query.totals.is_empty() || query.dense || query.sparse || query.nonempty {
+                bail!("Distinct cannot be combined with totals, dense, sparse or nonempty");
+            }
+        }
+        if query.nonempty && query.dense {
+            bail!("Nonempty and dense cannot both be set");
+        }
+
+        // answer from the smallest aggregate table that has every level and
+        // measure of the query, before falling back to the fact table. Default
+        // members become explicit cuts, which the aggregate table must have.
+        if schema_cube.can_aggregate && default_hierarchy_cuts_query.is_empty() {
+            let mut agg_query = query.clone();
+            agg_query.cuts.extend(self.build_default_member_cuts(schema_cube, query, false)?);
+            if query.exclude_default_members {
+                agg_query.cuts.extend(self.build_default_member_cuts(schema_cube, query, true)?);
+                agg_query.exclude_default_members = false;
+            }
+
+            let agg_table = schema_cube.aggregate_tables.iter()
+                .find(|agg| aggregate_table_answers(schema_cube, agg, &agg_query));
+
+            if let Some(agg_table) = agg_table {
+                let agg_schema = Schema {
+                    name: self.name.clone(),
+                    cubes: vec![aggregate_cube(schema_cube, agg_table)],
+                    shared_dimensions: vec![],
+                    annotations: None,
+                    default_locale: self.default_locale.clone(),
+                };
+
+                return agg_schema.sql_query(cube, &agg_query, unique_header_map);
+            }
+        }
+
         // now get the database metadata
         let table = self.cube_table(&cube)
             .ok_or(format_err!("No table found for cube {}", cube))?;
 
-        let mut cut_cols = self.cube_cut_cols(&cube, &query.cuts)
+        // cuts on a binned level or property are on the bucket labels, so they
+        // are applied by the bin drilldown instead
+        let (bin_cuts, cuts): (Vec<Cut>, Vec<Cut>) = query.cuts.iter()
+            .cloned()
+            .partition(|cut| query.bins.iter().any(|bin| bin.is_cut_on(cut)));
+
+        let mut cut_cols = self.cube_cut_cols(&cube, &cuts)
             .map_err(|err| format_err!("Error getting cut cols: {}", err))?;
 
         let default_hierarchy_cut_cols = self.cube_cut_cols(&cube, &default_hierarchy_cuts_query)
@@ -527,9 +709,19 @@ impl Schema {
         }
 
 
-        let drill_cols = self.cube_drill_cols(&cube, &query.drilldowns, &query.properties, &query.captions, query.parents)
+        let mut drill_cols = self.cube_drill_cols(&cube, &query.drilldowns, &query.properties, &query.captions, query.parents)
             .map_err(|err| format_err!("Error getting drill cols: {}", err))?;
 
+        // property drilldowns come after level drilldowns
+        let property_drill_cols = self.cube_property_drill_cols(&cube, &query.property_drilldowns)
+            .map_err(|err| format_err!("Error getting property drill cols: {}", err))?;
+        drill_cols.extend_from_slice(&property_drill_cols);
+
+        // then bin drilldowns
+        let bin_drill_cols = self.cube_bin_drill_cols(&cube, &query.bins, &bin_cuts)
+            .map_err(|err| format_err!("Error getting bin drill cols: {}", err))?;
+        drill_cols.extend_from_slice(&bin_drill_cols);
+
         let mea_cols = self.cube_mea_cols(&cube, &query.measures)
             .map_err(|err| format_err!("Error getting mea cols: {}", err))?;
 
@@ -565,12 +757,9 @@ impl Schema {
         let top = if let Some(ref t) = query.top {
             // don't want the actual measure column,
             // want the index so that we can use `m0` etc.
-            let top_sort_columns: Result<Vec<_>, _> = t.sort_mea_or_calc.iter()
-                .map(|m_or_c| {
-                    mea_or_calc!(m_or_c, query)
-                })
-                .collect();
-            let top_sort_columns = top_sort_columns?;
+            let top_sort = t.sort.iter()
+                .map(|sort| self.sort_sql(&cube, sort, query))
+                .collect::<Result<Vec<_>, _>>()?;
 
             // check that by_dimension is in query.drilldowns
             // TODO check for rca drills too
@@ -592,8 +781,8 @@ impl Schema {
             Some(TopSql {
                 n: t.n,
                 by_column: self.get_dim_col_alias(&cube, &t.by_dimension)?,
-                sort_columns: top_sort_columns,
-                sort_direction: t.sort_direction.clone(),
+                sort: top_sort,
+                other: query.top_other,
             })
         } else {
             None
@@ -628,16 +817,13 @@ impl Schema {
             .collect::<Result<Vec<_>,_>>();
         let filters = filters?;
 
-        let sort = if let Some(ref s) = query.sort {
-            // sort column needs to be named by alias
-            let sort_column = mea_or_calc!(&s.measure, query)?;
-            Some(SortSql {
-                direction: s.direction.clone(),
-                column: sort_column,
-            })
-        } else {
-            None
-        };
+        let filter_exprs = query.filter_exprs.iter()
+            .map(|expr| self.filter_expr_sql(&cube, expr, query))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sort = query.sort.iter()
+            .map(|sort| self.sort_sql(&cube, sort, query))
+            .collect::<Result<Vec<_>, _>>()?;
 
         // TODO check that no overlapping dim or mea cols between rca and others
         let rca = if let Some(ref rca) = query.rca {
@@ -710,10 +896,56 @@ impl Schema {
             None
         };
 
+        let rank = if let Some(ref rank) = query.rank {
+            let drill = self.cube_drill_cols(
+                &cube, &[Drilldown(rank.level_name.clone())],
+                &query.properties, &query.captions, query.parents
+            )?
+                .get(0)
+                .ok_or(format_err!("no drilldown found for rank"))?
+                .clone();
+
+            let mea = mea_or_calc!(&rank.mea_or_calc, query)?;
+
+            Some(RankSql {
+                drill,
+                mea,
+                direction: rank.direction.clone(),
+                rank_type: rank.rank_type.clone(),
+            })
+        } else {
+            None
+        };
+
+        let dense = if query.dense {
+            Some(DenseSql {
+                fill: query.dense_fill,
+            })
+        } else {
+            None
+        };
+
+        let totals = if !query.totals.is_empty() {
+            let drills = self.cube_drill_cols(&cube, &query.totals, &query.properties, &query.captions, query.parents)?;
+
+            Some(TotalsSql {
+                drills,
+            })
+        } else {
+            None
+        };
+
         // getting headers, not for sql but needed for formatting
         let mut drill_headers = self.cube_drill_headers(&cube, &query.drilldowns, &query.properties, query.parents, unique_header_map)
             .map_err(|err| format_err!("Error getting drill headers: {}", err))?;
 
+        drill_headers.extend(query.property_drilldowns.iter().map(|p| p.property.clone()));
+
+        for bin in &query.bins {
+            drill_headers.push(format!("{} Bin ID", bin.name()));
+            drill_headers.push(format!("{} Bin", bin.name()));
+        }
+
         let mut mea_headers = self.cube_mea_headers(&cube, &query.measures)
             .map_err(|err| format_err!("Error getting mea headers: {}", err))?;
 
@@ -776,6 +1008,20 @@ impl Schema {
             headers.push("Rate".to_string());
         }
 
+        // Rank is joined onto the final result, so comes after everything else
+        if let Some(ref rank) = query.rank {
+            let rank_header = match rank.mea_or_calc {
+                MeaOrCalc::Mea(ref m) => format!("{} Rank", m.0),
+                MeaOrCalc::Calc(ref c) => format!("{} Rank", c.sql_string().to_uppercase()),
+            };
+            headers.push(rank_header);
+        }
+
+        // Total rows are flagged with the number of drilldowns rolled up
+        if totals.is_some() {
+            headers.push("Total".to_string());
+        }
+
         Ok((
             QueryIr {
                 table,
@@ -784,6 +1030,7 @@ impl Schema {
                 meas: mea_cols,
                 hidden_drills: hidden_drill_cols,
                 filters,
+                filter_exprs,
                 top,
                 top_where,
                 sort,
@@ -791,11 +1038,254 @@ impl Schema {
                 rca,
                 growth,
                 rate,
+                rank,
+                totals,
                 sparse: query.sparse,
+                dense,
+                nonempty: query.nonempty,
+                distinct: query.distinct,
             },
             headers,
         ))
     }
+
+    /// Plans a query on several cubes, joined on the drilldowns. Drilldowns and
+    /// cuts must be on dimensions shared by every cube, and each measure is taken
+    /// from the first cube that has it.
+    ///
+    /// Returns one `QueryIr` per cube, to be executed separately and joined with
+    /// `MultiCubeJoin::join`. Headers are the drilldown headers, then the measure
+    /// headers in the requested order.
+    pub fn multi_cube_sql_query(
+        &self,
+        cubes: &[String],
+        query: &Query,
+        unique_header_map: Option<&HashMap<String, String>>
+        ) -> Result<(MultiCubeQueryIr, Vec<String>), Error>
+    {
+        if cubes.is_empty() || (cubes.len() < 2 && query.normalize.is_none()) {
+            bail!("Multi-cube queries need at least two cubes, or one cube and normalize");
+        }
+        if query.drilldowns.is_empty() {
+            bail!("Multi-cube queries need at least one drilldown to join on");
+        }
+
+        // Anything that changes the final columns or rows of a single cube
+        // can't be joined
+        let has_unsupported = !query.property_drilldowns.is_empty() || !query.bins.is_empty() ||
+            !query.filters.is_empty() || !query.filter_exprs.is_empty() ||
+            query.top.is_some() || query.top_where.is_some() || !query.sort.is_empty() || query.limit.is_some() ||
+            query.rca.is_some() || query.growth.is_some() || query.rate.is_some() || query.rank.is_some() ||
+            !query.totals.is_empty() || query.dense || query.distinct;
+
+        if has_unsupported {
+            bail!("Multi-cube queries only support drilldowns, cuts, measures, properties, captions and parents");
+        }
+
+        let schema_cubes = cubes.iter()
+            .map(|cube| {
+                self.get_cube_by_name(cube)
+                    .map_err(|_| format_err!("Could not find cube {}", cube))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let normalize_cube = match query.normalize {
+            Some(ref normalize) => {
+                let cube = self.get_cube_by_name(&normalize.cube)
+                    .map_err(|_| format_err!("Could not find normalize cube {}", normalize.cube))?;
+
+                if !cube.measures.iter().any(|mea| mea.name == normalize.measure.0) {
+                    bail!("Measure {} not found in normalize cube {}", normalize.measure, cube.name);
+                }
+
+                Some(cube)
+            },
+            None => None,
+        };
+
+        // every cube must have the same shared dimension for each drilldown and cut
+        let level_names = query.drilldowns.iter()
+            .map(|drill| &drill.0)
+            .chain(query.cuts.iter().map(|cut| &cut.level_name));
+
+        for level_name in level_names {
+            let first_dim = schema_cubes[0].dimensions.iter()
+                .find(|dim| dim.name == level_name.dimension())
+                .ok_or_else(|| format_err!("Dimension of {} not found in cube {}", level_name, schema_cubes[0].name))?;
+
+            for cube in schema_cubes.iter().chain(normalize_cube.iter()) {
+                let is_shared = cube.dimensions.iter()
+                    .find(|dim| dim.name == level_name.dimension())
+                    .map(|dim| dim.is_shared && dim.hierarchies == first_dim.hierarchies)
+                    .unwrap_or(false);
+
+                if !is_shared {
+                    bail!("Dimension of {} is not shared by cube {}", level_name, cube.name);
+                }
+            }
+        }
+
+        let mut cube_measures: Vec<Vec<Measure>> = vec![vec![]; schema_cubes.len()];
+        // cube and position in that cube's query of each requested measure
+        let mut measure_positions = vec![];
+
+        for measure in &query.measures {
+            let cube_idx = schema_cubes.iter()
+                .position(|cube| cube.measures.iter().any(|mea| mea.name == measure.0))
+                .ok_or_else(|| format_err!("Measure {} not found in any of the cubes", measure))?;
+
+            measure_positions.push((cube_idx, cube_measures[cube_idx].len()));
+            cube_measures[cube_idx].push(measure.clone());
+        }
+
+        // measures are joined cube by cube, and put back in the requested order
+        let cube_offsets: Vec<usize> = cube_measures.iter()
+            .scan(0, |offset, measures| {
+                let cube_offset = *offset;
+                *offset += measures.len();
+                Some(cube_offset)
+            })
+            .collect();
+        let measure_order: Vec<usize> = measure_positions.iter()
+            .map(|&(cube_idx, pos)| cube_offsets[cube_idx] + pos)
+            .collect();
+
+        let mut query_irs = vec![];
+        let mut drill_headers = vec![];
+        let mut mea_headers = vec![];
+
+        for (cube, measures) in schema_cubes.iter().zip(cube_measures.into_iter()) {
+            if measures.is_empty() {
+                bail!("No measures requested from cube {}", cube.name);
+            }
+
+            let num_measures = measures.len();
+
+            let mut cube_query = query.clone();
+            cube_query.measures = measures;
+            cube_query.normalize = None;
+
+            let (query_ir, mut headers) = self.sql_query(&cube.name, &cube_query, unique_header_map)?;
+
+            let cube_mea_headers = headers.split_off(headers.len() - num_measures);
+            drill_headers = headers;
+            mea_headers.extend(cube_mea_headers);
+
+            query_irs.push(query_ir);
+        }
+
+        // the denominator is queried last, and removed from the results when joining
+        let mut normalize_scale = None;
+
+        if let (Some(cube), Some(normalize)) = (normalize_cube, &query.normalize) {
+            let mut cube_query = query.clone();
+            cube_query.measures = vec![normalize.measure.clone()];
+            cube_query.normalize = None;
+
+            let (query_ir, _) = self.sql_query(&cube.name, &cube_query, unique_header_map)?;
+            query_irs.push(query_ir);
+
+            let per = match normalize.scale {
+                Some(scale) => format!("per {} {}", scale, normalize.measure),
+                None => format!("per {}", normalize.measure),
+            };
+            mea_headers = mea_headers.into_iter()
+                .map(|header| format!("{} {}", header, per))
+                .collect();
+
+            normalize_scale = Some(normalize.scale.unwrap_or(1.0));
+        }
+
+        let num_key_cols = drill_headers.len();
+        let mea_headers: Vec<String> = measure_order.iter()
+            .map(|&idx| mea_headers[idx].clone())
+            .collect();
+
+        Ok((
+            MultiCubeQueryIr {
+                query_irs,
+                join: MultiCubeJoin {
+                    num_key_cols,
+                    normalize_scale,
+                    measure_order: Some(measure_order),
+                },
+            },
+            [&drill_headers[..], &mea_headers[..]].concat(),
+        ))
+    }
+
+    /// Plans a query on a virtual cube. Each measure is taken from the first
+    /// of the virtual cube's cubes that has it, and only the cubes with
+    /// requested measures are queried.
+    ///
+    /// If all measures are from one cube, the query supports everything a
+    /// single cube query does. Otherwise, the cubes are joined on the
+    /// drilldowns like a multi-cube query.
+    pub fn virtual_cube_sql_query(
+        &self,
+        cube_name: &str,
+        query: &Query,
+        unique_header_map: Option<&HashMap<String, String>>
+        ) -> Result<(MultiCubeQueryIr, Vec<String>), Error>
+    {
+        let cube = self.get_cube_by_name(cube_name)?;
+        let cube_usages = cube.cube_usages.as_ref()
+            .ok_or_else(|| format_err!("Cube {} is not a virtual cube", cube_name))?;
+
+        if query.measures.is_empty() {
+            bail!("No measure found; please specify at least one");
+        }
+
+        // the cubes of a virtual cube can have dimensions that it doesn't share
+        let level_names = query.drilldowns.iter()
+            .map(|drill| &drill.0)
+            .chain(query.cuts.iter().map(|cut| &cut.level_name));
+
+        for level_name in level_names {
+            if cube.get_level(level_name).is_none() {
+                bail!("Level {} not found in virtual cube {}", level_name, cube_name);
+            }
+        }
+
+        let mut cubes: Vec<String> = vec![];
+
+        for measure in &query.measures {
+            let usage = cube_usages.iter()
+                .find(|usage| {
+                    self.get_cube_by_name(usage)
+                        .map(|c| c.measures.iter().any(|mea| mea.name == measure.0))
+                        .unwrap_or(false)
+                })
+                .ok_or_else(|| format_err!("Measure {} not found in virtual cube {}", measure, cube_name))?;
+
+            if !cubes.contains(usage) {
+                cubes.push(usage.clone());
+            }
+        }
+
+        // keep the order of the cube usages, so that the multi-cube query
+        // takes each measure from the same cube. It returns the measures in the
+        // requested order.
+        cubes.sort_by_key(|c| cube_usages.iter().position(|usage| usage == c));
+
+        if cubes.len() == 1 && query.normalize.is_none() {
+            let (query_ir, headers) = self.sql_query(&cubes[0], query, unique_header_map)?;
+
+            return Ok((
+                MultiCubeQueryIr {
+                    query_irs: vec![query_ir],
+                    join: MultiCubeJoin {
+                        num_key_cols: 0,
+                        normalize_scale: None,
+                        measure_order: None,
+                    },
+                },
+                headers,
+            ));
+        }
+
+        self.multi_cube_sql_query(&cubes, query, unique_header_map)
+    }
 }
 
 impl Schema {
@@ -833,26 +1323,59 @@ impl Schema {
                 .clone()
                 .unwrap_or(cube.table.clone());
 
+            let parent_child = parent_child_sql(hier, false);
+
             // primary key is currently required in hierarchy. because inline dim is not yet
-            // allowed
-            let primary_key = hier.primary_key.clone();
+            // allowed. Parent-child hierarchies join on the derived descendant key.
+            let primary_key = match parent_child {
+                Some(_) => DESCENDANT_KEY.to_owned(),
+                None => hier.primary_key.clone(),
+            };
 
             let foreign_key = dim.foreign_key
                 .clone()
                 .ok_or(format_err!("No foreign key; it's required for now (until inline dim implemented)"))?;
 
-            let column = if cut.for_match {
-                level.name_column.clone().unwrap_or(level.key_column.clone())
-            } else {
-                level.key_column.clone()
-            };
+            // property cuts use the property column from the dimension table.
+            // Property values are assumed to be text.
+            let (column, member_type) = if let Some(ref property_name) = cut.property {
+                let (prop_dim, prop_hier, prop_level) = cube.identify_property(property_name.clone())?;
+                if LevelName::new(prop_dim, prop_hier, prop_level) != cut.level_name {
+                    bail!("Property {} is not a property of level {}", property_name, cut.level_name);
+                }
+
+                let property = level.properties.iter()
+                    .flat_map(|props| props.iter())
+                    .find(|prop| &prop.name == property_name)
+                    .ok_or(format_err!("could not find property for cut {}", cut))?;
 
-            let member_type = if cut.for_match {
-                MemberType::Text
+                (property.column.clone(), MemberType::Text)
+            } else if level.time_part.is_some() {
+                // virtual time levels cut on a part of the date column
+                (foreign_key.clone(), MemberType::NonText)
             } else {
-                level.key_type.clone().unwrap_or(MemberType::NonText)
+                let column = if cut.for_match {
+                    level.name_column.clone().unwrap_or(level.key_column.clone())
+                } else {
+                    level.key_column.clone()
+                };
+
+                let member_type = if cut.for_match {
+                    MemberType::Text
+                } else {
+                    level.key_type.clone().unwrap_or(MemberType::NonText)
+                };
+
+                (column, member_type)
             };
 
+            // non-text range ends are written into the SQL unquoted
+            if let (Some(ref range), MemberType::NonText) = (&cut.range, &member_type) {
+                if let Some(end) = range.ends().into_iter().find(|end| end.parse::<f64>().is_err()) {
+                    bail!("Range cut {} must have numeric ends, not {}", cut, end);
+                }
+            }
+
             res.push(CutSql {
                 table,
                 primary_key,
@@ -863,6 +1386,11 @@ impl Schema {
                 mask: cut.mask.clone(),
                 for_match: cut.for_match,
                 inline_table: hier.inline_table.clone(),
+                range: cut.range.clone(),
+                time_part: level.time_part.clone(),
+                fiscal_year_start_month: cube.fiscal_year_start_month,
+                parent_child,
+                snowflake_sql: hier.snowflake_sql.clone(),
             });
         }
 
@@ -964,9 +1492,14 @@ impl Schema {
                 .clone()
                 .unwrap_or(cube.table.clone());
 
+            let parent_child = parent_child_sql(hier, parents);
+
             // primary key is currently required in hierarchy. because inline dim is not yet
-            // allowed
-            let primary_key = hier.primary_key.clone();
+            // allowed. Parent-child hierarchies join on the derived descendant key.
+            let primary_key = match parent_child {
+                Some(_) => DESCENDANT_KEY.to_owned(),
+                None => hier.primary_key.clone(),
+            };
 
             let foreign_key = dim.foreign_key
                 .clone()
@@ -982,6 +1515,17 @@ impl Schema {
             let mut level_columns = vec![];
 
             if parents {
+                // parent-child members have their ancestors as parent columns,
+                // nearest first
+                if hier.parent_column.is_some() {
+                    for distance in 1..=parent_child_max_depth(hier) {
+                        level_columns.push(LevelColumn {
+                            key_column: ancestor_column(distance),
+                            name_column: None,
+                        });
+                    }
+                }
+
                 for i in 0..=level_idx {
                     // caption replaces name_column with the col from property.
                     let caption = if let Some(caption_col) = caption_cols.get(&levels[i].name) {
@@ -1008,6 +1552,22 @@ impl Schema {
                 });
             }
 
+            // virtual time levels (always the whole hierarchy) are generated
+            // from the date column, which is the foreign key
+            let time = if levels[level_idx].time_part.is_some() {
+                let first_idx = if parents { 0 } else { level_idx };
+
+                Some(TimeSql {
+                    column: foreign_key.clone(),
+                    parts: levels[first_idx..=level_idx].iter()
+                        .filter_map(|lvl| lvl.time_part.clone())
+                        .collect(),
+                    fiscal_year_start_month: cube.fiscal_year_start_month,
+                })
+            } else {
+                None
+            };
+
             let alias_postfix = dim.name.replace(" ", "_");
 
             res.push(DrilldownSql {
@@ -1017,33 +1577,191 @@ impl Schema {
                 foreign_key,
                 level_columns,
                 property_columns,
-                inline_table: hier.inline_table.clone()
+                inline_table: hier.inline_table.clone(),
+                bin: None,
+                time,
+                parent_child,
+                snowflake_sql: hier.snowflake_sql.clone(),
             });
         }
 
         Ok(res)
     }
 
-    fn cube_mea_cols(&self, cube_name: &str, meas: &[Measure]) -> Result<Vec<MeasureSql>, Error> {
+    /// A property drilldown groups by the property column instead of a level
+    /// key, so all members with the same property value are aggregated together.
+    fn cube_property_drill_cols(&self, cube_name: &str, property_drills: &[Property]) -> Result<Vec<DrilldownSql>, Error> {
         let cube = self.cubes.iter()
             .find(|cube| &cube.name == &cube_name)
             .ok_or(format_err!("Could not find cube"))?;
 
         let mut res = vec![];
 
-        for measure in meas {
-            let mea = cube.measures.iter()
-                .find(|m| m.name == measure.0)
-                .ok_or(format_err!("could not find measure for {}", measure.0))?;
+        for property_drill in property_drills {
+            let level_name = &property_drill.level_name;
 
-            res.push(MeasureSql {
-                column: mea.column.clone(),
-                aggregator: mea.aggregator.clone(),
-            });
-        }
+            let dim = cube.dimensions.iter()
+                .find(|dim| dim.name == level_name.dimension)
+                .ok_or(format_err!("could not find dimension for property drill {}", property_drill))?;
+            let hier = dim.hierarchies.iter()
+                .find(|hier| hier.name == level_name.hierarchy)
+                .ok_or(format_err!("could not find hierarchy for property drill {}", property_drill))?;
+            let level = hier.levels.iter()
+                .find(|lvl| lvl.name == level_name.level)
+                .ok_or(format_err!("could not find level for property drill {}", property_drill))?;
+            let property = level.properties.iter()
+                .flat_map(|props| props.iter())
+                .find(|p| p.name == property_drill.property)
+                .ok_or(format_err!("could not find property for property drill {}", property_drill))?;
 
-        Ok(res)
-    }
+            // No table (means inline table) will replace with fact table
+            let table = hier.table
+                .clone()
+                .unwrap_or(cube.table.clone());
+
+            let foreign_key = dim.foreign_key
+                .clone()
+                .ok_or(format_err!("No foreign key; it's required for now (until inline dim implemented)"))?;
+
+            // property name is in the alias so that it won't collide with a level
+            // drilldown on the same dimension
+            let alias_postfix = format!("{}_{}", dim.name, property.name).replace(" ", "_");
+
+            res.push(DrilldownSql {
+                alias_postfix,
+                table,
+                primary_key: hier.primary_key.clone(),
+                foreign_key,
+                level_columns: vec![LevelColumn {
+                    key_column: property.column.clone(),
+                    name_column: None,
+                }],
+                property_columns: vec![],
+                inline_table: hier.inline_table.clone(),
+                bin: None,
+                time: None,
+                parent_child: None,
+                snowflake_sql: hier.snowflake_sql.clone(),
+            });
+        }
+
+        Ok(res)
+    }
+
+    /// A bin drilldown groups by buckets of a level key or property column.
+    /// The bucket id and label columns are generated by the backend; cuts on
+    /// the bin are on the bucket labels.
+    fn cube_bin_drill_cols(&self, cube_name: &str, bins: &[BinQuery], bin_cuts: &[Cut]) -> Result<Vec<DrilldownSql>, Error> {
+        let cube = self.cubes.iter()
+            .find(|cube| &cube.name == &cube_name)
+            .ok_or(format_err!("Could not find cube"))?;
+
+        let mut res = vec![];
+
+        for bin in bins {
+            let level_name = &bin.level_name;
+
+            let dim = cube.dimensions.iter()
+                .find(|dim| dim.name == level_name.dimension)
+                .ok_or(format_err!("could not find dimension for bin {}", level_name))?;
+            let hier = dim.hierarchies.iter()
+                .find(|hier| hier.name == level_name.hierarchy)
+                .ok_or(format_err!("could not find hierarchy for bin {}", level_name))?;
+            let level = hier.levels.iter()
+                .find(|lvl| lvl.name == level_name.level)
+                .ok_or(format_err!("could not find level for bin {}", level_name))?;
+
+            // buckets are generated in a dimension subquery, which would
+            // duplicate fact table rows
+            if hier.table.is_none() && hier.inline_table.is_none() {
+                bail!("Bin on {} requires a dimension table", level_name);
+            }
+            if hier.parent_column.is_some() {
+                bail!("Bin on {} is not supported for parent-child hierarchies", level_name);
+            }
+
+            let column = match bin.property {
+                Some(ref property_name) => {
+                    level.properties.iter()
+                        .flat_map(|props| props.iter())
+                        .find(|p| &p.name == property_name)
+                        .map(|p| p.column.clone())
+                        .ok_or(format_err!("could not find property {} for bin {}", property_name, level_name))?
+                },
+                None => level.key_column.clone(),
+            };
+
+            let cuts: Vec<_> = bin_cuts.iter()
+                .filter(|cut| bin.is_cut_on(cut))
+                .collect();
+            if cuts.len() > 1 {
+                bail!("Only one cut allowed on bin {}", bin.name());
+            }
+            let (members, mask) = cuts.get(0)
+                .map(|cut| (cut.members.clone(), cut.mask.clone()))
+                .unwrap_or((vec![], Mask::Include));
+
+            if let Some(label) = members.iter().find(|m| !bin.bin_type.is_label(m)) {
+                bail!("{} is not a bucket of bin {}", label, bin.name());
+            }
+
+            // No table (means inline table) will replace with fact table
+            let table = hier.table
+                .clone()
+                .unwrap_or(cube.table.clone());
+
+            let foreign_key = dim.foreign_key
+                .clone()
+                .ok_or(format_err!("No foreign key; it's required for now (until inline dim implemented)"))?;
+
+            let alias_postfix = format!("{}_{}", dim.name, bin.name()).replace(" ", "_");
+
+            res.push(DrilldownSql {
+                alias_postfix,
+                table,
+                primary_key: hier.primary_key.clone(),
+                foreign_key,
+                level_columns: vec![LevelColumn {
+                    key_column: format!("{}_bin", column),
+                    name_column: Some(format!("{}_bin_label", column)),
+                }],
+                property_columns: vec![],
+                inline_table: hier.inline_table.clone(),
+                bin: Some(BinSql {
+                    column,
+                    bin_type: bin.bin_type.clone(),
+                    members,
+                    mask,
+                }),
+                time: None,
+                parent_child: None,
+                snowflake_sql: hier.snowflake_sql.clone(),
+            });
+        }
+
+        Ok(res)
+    }
+
+    fn cube_mea_cols(&self, cube_name: &str, meas: &[Measure]) -> Result<Vec<MeasureSql>, Error> {
+        let cube = self.cubes.iter()
+            .find(|cube| &cube.name == &cube_name)
+            .ok_or(format_err!("Could not find cube"))?;
+
+        let mut res = vec![];
+
+        for measure in meas {
+            let mea = cube.measures.iter()
+                .find(|m| m.name == measure.0)
+                .ok_or(format_err!("could not find measure for {}", measure.0))?;
+
+            res.push(MeasureSql {
+                column: mea.column.clone(),
+                aggregator: mea.aggregator.clone(),
+            });
+        }
+
+        Ok(res)
+    }
 
     /// order should mirror DrillSql col_string,
     /// which should be levels first and then properties after
@@ -1084,6 +1802,22 @@ impl Schema {
             // In this section, need to watch out for whether there's both a
             // key column and a name column and add ID to the first if necessary
             if parents {
+                if hier.parent_column.is_some() {
+                    let level_str = format!("{}.{}.{}", dim.name, hier.name, levels[level_idx].name).to_string();
+                    let unique_header = unique_header_map.and_then(|unique_header_map| unique_header_map.get(&level_str));
+
+                    for distance in 1..=parent_child_max_depth(hier) {
+                        let default_header_name = format!("Ancestor {} {} ID", distance, levels[level_idx].name);
+
+                        level_headers.push(default_header_name.clone());
+
+                        match unique_header {
+                            Some(unique_header) => unique_level_headers.push(format!("Ancestor {} {} ID", distance, unique_header)),
+                            None => unique_level_headers.push(default_header_name),
+                        }
+                    }
+                }
+
                 for i in 0..=level_idx {
                     let level_str = format!("{}.{}.{}", dim.name, hier.name, levels[i].name).to_string();
 
@@ -1221,14 +1955,12 @@ impl Schema {
             .find(|lvl| lvl.name == level_name.level)
             .ok_or(format_err!("could not find level for level name"))?;
 
-        let table = hier.table.clone().unwrap_or_else(|| cube.table.clone());
-
         // TODO: have a check that there can't be inline table and regular table at the same time.
         // Inline table has highest precedence.
         let table_sql = if let Some(ref inline) = hier.inline_table {
             format!("({})", inline.sql_string())
         } else {
-            table.full_name()
+            hier.table_sql().unwrap_or_else(|| cube.table.full_name())
         };
 
         let key_column = level.key_column.clone();
@@ -1262,6 +1994,85 @@ impl Schema {
         Ok(column)
     }
 
+    /// Sort columns need to be named by alias
+    fn sort_sql(&self, cube_name: &str, sort: &SortQuery, query: &Query) -> Result<SortSql, Error> {
+        Ok(SortSql {
+            direction: sort.direction.clone(),
+            column: self.key_column_alias(cube_name, &sort.key, query)?,
+        })
+    }
+
+    fn filter_expr_sql(&self, cube_name: &str, expr: &FilterExpr, query: &Query) -> Result<FilterExprSql, Error> {
+        let filter_exprs_sql = |exprs: &[FilterExpr]| {
+            exprs.iter()
+                .map(|e| self.filter_expr_sql(cube_name, e, query))
+                .collect::<Result<Vec<_>, Error>>()
+        };
+
+        Ok(match expr {
+            FilterExpr::And(exprs) => FilterExprSql::And(filter_exprs_sql(exprs)?),
+            FilterExpr::Or(exprs) => FilterExprSql::Or(filter_exprs_sql(exprs)?),
+            FilterExpr::Not(expr) => FilterExprSql::Not(Box::new(self.filter_expr_sql(cube_name, expr, query)?)),
+            FilterExpr::Condition(key, op) => {
+                FilterExprSql::Condition {
+                    column: self.key_column_alias(cube_name, key, query)?,
+                    op: op.clone(),
+                }
+            },
+        })
+    }
+
+    /// Alias of a result column (measure, calculation, level or property) in the
+    /// final aggregation.
+    fn key_column_alias(&self, cube_name: &str, key: &ColumnKey, query: &Query) -> Result<String, Error> {
+        let column = match key {
+            ColumnKey::MeaOrCalc(ref m_or_c) => mea_or_calc!(m_or_c, query)?,
+            ColumnKey::LevelId(ref level_name) | ColumnKey::LevelLabel(ref level_name) => {
+                let drill = Drilldown(level_name.clone());
+
+                let in_drills = query.drilldowns.contains(&drill) ||
+                    query.rca.iter().any(|rca| rca.drill_1 == drill || rca.drill_2 == drill);
+
+                if !in_drills {
+                    bail!("Level {} must be in drilldowns", level_name);
+                }
+
+                // Gets the drill cols again, so that the label column takes captions into account
+                let drill_col = self.cube_drill_cols(cube_name, &[drill], &[], &query.captions, query.parents)?
+                    .get(0)
+                    .ok_or(format_err!("no drilldown found for level {}", level_name))?
+                    .clone();
+
+                let level_col = drill_col.level_columns.last()
+                    .ok_or(format_err!("no columns found for level {}", level_name))?;
+
+                let column = match key {
+                    ColumnKey::LevelLabel(_) => level_col.name_column.as_ref().unwrap_or(&level_col.key_column),
+                    _ => &level_col.key_column,
+                };
+
+                format!("{}_{}", column, drill_col.alias_postfix)
+            },
+            ColumnKey::Property(ref property) => {
+                if !query.properties.contains(property) {
+                    bail!("Property {} must be in properties", property);
+                }
+
+                let cube = self.get_cube_by_name(cube_name)?;
+                let level = cube.get_level(&property.level_name)
+                    .ok_or(format_err!("could not find level for property {}", property))?;
+
+                level.properties.iter()
+                    .flat_map(|props| props.iter())
+                    .find(|p| p.name == property.property)
+                    .map(|p| p.column.clone())
+                    .ok_or(format_err!("could not find property {}", property))?
+            },
+        };
+
+        Ok(column)
+    }
+
     fn get_mea_col(&self, cube_name: &str, measure: &Measure) -> Result<String, Error> {
         let cube = self.cubes.iter()
             .find(|cube| &cube.name == &cube_name)
@@ -1281,6 +2092,197 @@ impl Schema {
             .find(|c| &c.name == &cube_name)
             .ok_or(format_err!("Could not find cube"))
     }
+
+    /// The auth level needed to query a cube. A virtual cube is queried
+    /// through its cubes, so it needs the highest level of all of them.
+    pub fn cube_auth_level(&self, cube_name: &str) -> Result<i32, Error> {
+        let cube = self.get_cube_by_name(cube_name)?;
+
+        let mut auth_level = cube.min_auth_level;
+        for usage in cube.cube_usages.iter().flat_map(|usages| usages.iter()) {
+            auth_level = auth_level.max(self.get_cube_by_name(usage)?.min_auth_level);
+        }
+
+        Ok(auth_level)
+    }
+}
+
+/// Derived table for drilldowns and cuts on a parent-child hierarchy, which
+/// has a single level. With `parents`, the ancestors of each member are
+/// selected, up to the max depth.
+fn parent_child_sql(hier: &Hierarchy, parents: bool) -> Option<ParentChildSql> {
+    let parent_column = hier.parent_column.clone()?;
+    let table = hier.table.clone()?;
+    let level = hier.levels.get(0)?;
+
+    let mut columns = vec![level.key_column.clone()];
+    columns.extend(level.name_column.clone());
+    columns.extend(level.properties.iter().flat_map(|props| props.iter()).map(|p| p.column.clone()));
+
+    let mut seen = HashSet::new();
+    columns.retain(|col| seen.insert(col.clone()));
+
+    Some(ParentChildSql {
+        table,
+        primary_key: hier.primary_key.clone(),
+        parent_column,
+        columns,
+        closure: hier.closure.clone(),
+        max_depth: parent_child_max_depth(hier),
+        ancestors: if parents { parent_child_max_depth(hier) } else { 0 },
+    })
+}
+
+fn parent_child_max_depth(hier: &Hierarchy) -> u32 {
+    hier.max_depth.unwrap_or(DEFAULT_PARENT_CHILD_MAX_DEPTH)
+}
+
+/// Whether an aggregate table has every level and measure that a query
+/// uses. Properties and bins are not stored in aggregate tables.
+fn aggregate_table_answers(cube: &Cube, agg: &AggregateTable, query: &Query) -> bool {
+    if !query.properties.is_empty() || !query.captions.is_empty() ||
+        !query.property_drilldowns.is_empty() || !query.bins.is_empty() ||
+        query.cuts.iter().any(|cut| cut.property.is_some())
+    {
+        return false;
+    }
+
+    let drills: Vec<&LevelName> = query.drilldowns.iter()
+        .chain(query.rca.iter().flat_map(|rca| vec![&rca.drill_1, &rca.drill_2]))
+        .map(|drill| &drill.0)
+        .collect();
+
+    let mut level_names: Vec<LevelName> = drills.iter().map(|&level_name| level_name.clone()).collect();
+    level_names.extend(query.cuts.iter().map(|cut| cut.level_name.clone()));
+    level_names.extend(query.rate.iter().map(|rate| rate.level_name.clone()));
+
+    for sort in query.sort.iter().chain(query.top.iter().flat_map(|top| top.sort.iter())) {
+        match sort.key {
+            ColumnKey::LevelId(ref level_name) | ColumnKey::LevelLabel(ref level_name) => level_names.push(level_name.clone()),
+            ColumnKey::Property(_) => return false,
+            ColumnKey::MeaOrCalc(_) => (),
+        }
+    }
+
+    // parents are selected for every level above the drilldown
+    if query.parents {
+        for level_name in drills {
+            let hier = cube.dimensions.iter()
+                .find(|dim| dim.name == level_name.dimension)
+                .and_then(|dim| dim.hierarchies.iter().find(|hier| hier.name == level_name.hierarchy));
+
+            if let Some(hier) = hier {
+                level_names.extend(hier.levels.iter()
+                    .take_while(|level| level.name != level_name.level)
+                    .map(|level| LevelName::new(level_name.dimension.clone(), level_name.hierarchy.clone(), level.name.clone()))
+                );
+            }
+        }
+    }
+
+    let has_levels = level_names.iter().all(|level_name| agg.get_level(level_name).is_some());
+    let has_measures = query.measures.iter()
+        .chain(query.rca.iter().map(|rca| &rca.mea))
+        .all(|mea| agg.get_measure(&mea.0).is_some());
+
+    has_levels && has_measures
+}
+
+/// Cube whose fact table is the aggregate table, and whose levels are
+/// columns of the aggregate table. Only the levels and measures of the
+/// aggregate table are kept.
+fn aggregate_cube(cube: &Cube, agg: &AggregateTable) -> Cube {
+    let dimensions = cube.dimensions.iter()
+        .filter_map(|dim| {
+            let hierarchies: Vec<_> = dim.hierarchies.iter()
+                .filter_map(|hier| {
+                    let levels: Vec<_> = hier.levels.iter()
+                        .filter_map(|level| {
+                            let level_name = LevelName::new(dim.name.clone(), hier.name.clone(), level.name.clone());
+
+                            agg.get_level(&level_name).map(|agg_level| {
+                                Level {
+                                    name: level.name.clone(),
+                                    key_column: agg_level.key_column.clone(),
+                                    name_column: agg_level.name_column.clone(),
+                                    properties: None,
+                                    key_type: level.key_type.clone(),
+                                    annotations: level.annotations.clone(),
+                                    time_part: None,
+                                    table: None,
+                                    primary_key: None,
+                                    foreign_key: None,
+                                }
+                            })
+                        })
+                        .collect();
+
+                    let primary_key = levels.last()?.key_column.clone();
+
+                    Some(Hierarchy {
+                        name: hier.name.clone(),
+                        table: None,
+                        primary_key,
+                        levels,
+                        annotations: hier.annotations.clone(),
+                        inline_table: None,
+                        default_member: None,
+                        parent_column: None,
+                        closure: None,
+                        max_depth: None,
+                        snowflake_sql: None,
+                    })
+                })
+                .collect();
+
+            let foreign_key = hierarchies.first()?.primary_key.clone();
+
+            Some(Dimension {
+                name: dim.name.clone(),
+                foreign_key: Some(foreign_key),
+                hierarchies,
+                default_hierarchy: None,
+                dim_type: dim.dim_type.clone(),
+                annotations: dim.annotations.clone(),
+                is_shared: dim.is_shared,
+                location: dim.location.clone(),
+            })
+        })
+        .collect();
+
+    // counts are summed from the counted rows of the aggregate table
+    let measures = cube.measures.iter()
+        .filter_map(|mea| {
+            agg.get_measure(&mea.name).map(|agg_mea| {
+                let aggregator = match mea.aggregator {
+                    Aggregator::Count => Aggregator::Sum,
+                    ref aggregator => aggregator.clone(),
+                };
+
+                schema::Measure {
+                    column: agg_mea.column.clone(),
+                    aggregator,
+                    ..mea.clone()
+                }
+            })
+        })
+        .collect();
+
+    Cube {
+        name: cube.name.clone(),
+        public: cube.public,
+        min_auth_level: cube.min_auth_level,
+        table: agg.table.clone(),
+        can_aggregate: false,
+        dimensions,
+        measures,
+        annotations: cube.annotations.clone(),
+        fiscal_year_start_month: cube.fiscal_year_start_month,
+        cube_usages: None,
+        aggregate_tables: vec![],
+        missing_shared_dimensions: vec![],
+        location: cube.location.clone(),
+    }
 }
 
 #[derive(Debug)]
@@ -1305,6 +2307,8 @@ mod test {
     use super::*;
     // use serde_json;
     use crate::query::*;
+    use crate::schema::{TimePart, HierarchyType};
+    use crate::names::CutRange;
 
     const SCHEMA_STR_MULTIPLE_HIER_NO_DEFAULT: &str = r#"{ "name": "test", "cubes": [ { "name": "sales", "table": { "name": "sales", "primary_key": "product_id" }, "dimensions": [{ "name": "Geography", "foreign_key": "customer_id", "hierarchies": [ { "name": "Tract", "table": { "name": "customer_geo" }, "primary_key": "customer_id", "levels": [ { "name": "State", "key_column": "state_id", "name_column": "state_name", "key_type": "text" }, { "name": "County", "key_column": "county_id", "name_column": "county_name", "key_type": "text" }, { "name": "Tract", "key_column": "tract_id", "name_column": "tract_name", "key_type": "text" } ] }, { "name": "Place", "table": { "name": "customer_geo" }, "primary_key": "customer_id", "levels": [ { "name": "Place", "key_column": "place_id", "name_column": "place_name", "key_type": "text" } ] } ] } ], "measures": [ { "name": "Quantity", "column": "quantity", "aggregator": "sum" } ] } ] }"#;
     const SCHEMA_STR_MULTIPLE_HIER_DEFAULT: &str = r#"{ "name": "test", "cubes": [ { "name": "sales", "table": { "name": "sales", "primary_key": "product_id" }, "dimensions": [{ "name": "Geography", "foreign_key": "customer_id", "default_hierarchy": "Tract", "hierarchies": [ { "name": "Tract", "table": { "name": "customer_geo" }, "primary_key": "customer_id", "levels": [ { "name": "State", "key_column": "state_id", "name_column": "state_name", "key_type": "text" }, { "name": "County", "key_column": "county_id", "name_column": "county_name", "key_type": "text" }, { "name": "Tract", "key_column": "tract_id", "name_column": "tract_name", "key_type": "text" } ] }, { "name": "Place", "table": { "name": "customer_geo" }, "primary_key": "customer_id", "levels": [ { "name": "Place", "key_column": "place_id", "name_column": "place_name", "key_type": "text" } ] } ] } ], "measures": [ { "name": "Quantity", "column": "quantity", "aggregator": "sum" } ] } ] }"#;
@@ -1517,18 +2521,22 @@ mod test {
                 hierarchy: "Year".to_string(),
                 level: "Year".to_string(),
             })].to_vec(),
+            property_drilldowns: vec![],
+            bins: vec![],
             cuts: vec![],
             measures: [Measure("Price Total".to_string())].to_vec(),
             properties: vec![],
             filters: vec![],
+            filter_exprs: vec![],
             captions: vec![],
             parents: false,
             top: None,
             top_where: None,
-            sort: Some(SortQuery{
+            top_other: false,
+            sort: vec![SortQuery{
                 direction: SortDirection::Asc,
-                measure: MeaOrCalc::Mea(Measure("Price Total".to_string()))
-            }),
+                key: ColumnKey::MeaOrCalc(MeaOrCalc::Mea(Measure("Price Total".to_string())))
+            }],
             limit: None,
             rca: Some(RcaQuery{
                 drill_1: Drilldown(LevelName{
@@ -1545,13 +2553,20 @@ mod test {
             }),
             growth: None,
             rate: None,
+            rank: None,
+            totals: vec![],
+            normalize: None,
             debug: false,
             sparse: false,
+            dense: false,
+            dense_fill: None,
+            nonempty: false,
+            distinct: false,
             exclude_default_members: false,
         };
         let query_ir_headers = Schema::from_xml(s).unwrap().sql_query("Sales", &query, None);
         let (query_ir, _headers) = query_ir_headers.unwrap();
-        assert_eq!(query_ir.sort, Some(SortSql{direction: SortDirection::Asc, column: "final_m0".to_string()}))
+        assert_eq!(query_ir.sort, vec![SortSql{direction: SortDirection::Asc, column: "final_m0".to_string()}])
     }
 
     #[test]
@@ -1648,6 +2663,8 @@ mod test {
                 hierarchy: "Year".to_string(),
                 level: "Year".to_string(),
             })].to_vec(),
+            property_drilldowns: vec![],
+            bins: vec![],
             cuts: vec![],
             measures: [Measure("Price Total".to_string()), Measure("Quantity".to_string())].to_vec(),
             properties: vec![],
@@ -1682,14 +2699,16 @@ mod test {
                 constraint2: None,
             }
             ].to_vec(),
+            filter_exprs: vec![],
             captions: vec![],
             parents: false,
             top: None,
             top_where: None,
-            sort: Some(SortQuery{
+            top_other: false,
+            sort: vec![SortQuery{
                 direction: SortDirection::Asc,
-                measure: MeaOrCalc::Mea(Measure("Price Total".to_string()))
-            }),
+                key: ColumnKey::MeaOrCalc(MeaOrCalc::Mea(Measure("Price Total".to_string())))
+            }],
             limit: None,
             rca: Some(RcaQuery{
                 drill_1: Drilldown(LevelName{
@@ -1706,8 +2725,15 @@ mod test {
             }),
             growth: None,
             rate: None,
+            rank: None,
+            totals: vec![],
+            normalize: None,
             debug: false,
             sparse: false,
+            dense: false,
+            dense_fill: None,
+            nonempty: false,
+            distinct: false,
             exclude_default_members: false,
         };
         let query_ir_headers = Schema::from_xml(s).unwrap().sql_query("Sales", &query, None);
@@ -1747,4 +2773,659 @@ mod test {
             constraint2: None,
         }].to_vec())
     }
+
+    #[test]
+    fn test_property_drilldown() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="my_cube">
+                    <Table name="my_table" />
+                    <Dimension foreign_key="industry_id" name="Industry">
+                        <Hierarchy name="Industry" primary_key="industry_id">
+                            <Table name="dim_industry" />
+                            <Level name="Industry" key_column="industry_id" name_column="industry_name">
+                                <Property name="Sector Type" column="sector_type" />
+                            </Level>
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="my_mea" column="mea" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema: Schema = Schema::from_xml(s).unwrap();
+
+        let mut query = Query::new();
+        query.measures = vec![Measure::new("my_mea")];
+        query.property_drilldowns = vec![Property::new("Industry", "Industry", "Industry", "Sector Type")];
+
+        let (query_ir, headers) = schema.sql_query("my_cube", &query, None).unwrap();
+
+        assert_eq!(query_ir.drills.len(), 1);
+        assert_eq!(query_ir.drills[0].col_alias_string(), "sector_type as sector_type_Industry_Sector_Type");
+        assert_eq!(headers, vec!["Sector Type".to_owned(), "my_mea".to_owned()]);
+    }
+
+    #[test]
+    fn test_distinct() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="my_cube">
+                    <Table name="my_table" />
+                    <Dimension foreign_key="industry_id" name="Industry">
+                        <Hierarchy name="Industry" primary_key="industry_id">
+                            <Table name="dim_industry" />
+                            <Level name="Industry" key_column="industry_id" name_column="industry_name" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="my_mea" column="mea" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema: Schema = Schema::from_xml(s).unwrap();
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Industry.Industry.Industry".parse().unwrap()];
+        query.distinct = true;
+
+        let (query_ir, headers) = schema.sql_query("my_cube", &query, None).unwrap();
+
+        assert!(query_ir.distinct);
+        assert!(query_ir.meas.is_empty());
+        assert_eq!(headers, vec!["Industry ID".to_owned(), "Industry".to_owned()]);
+
+        query.measures = vec![Measure::new("my_mea")];
+        assert!(schema.sql_query("my_cube", &query, None).is_err());
+    }
+
+    #[test]
+    fn test_standard_sql_unsupported() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <Dimension name="Geography" foreign_key="state_id">
+                        <Hierarchy name="Geography" primary_key="state_id">
+                            <Table name="dim_geo" />
+                            <Level name="State" key_column="state_id" name_column="state_name" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Dimension name="Year" foreign_key="year" type="time">
+                        <Hierarchy name="Year" primary_key="year">
+                            <Level name="Year" key_column="year" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema: Schema = Schema::from_xml(s).unwrap();
+
+        let mut base_query = Query::new();
+        base_query.drilldowns = vec![
+            "Geography.Geography.State".parse().unwrap(),
+            "Year.Year.Year".parse().unwrap(),
+        ];
+        base_query.measures = vec![Measure::new("Sales")];
+
+        let (query_ir, _) = schema.sql_query("sales", &base_query, None).unwrap();
+        assert!(sql::check_query_ir(&query_ir).is_ok());
+
+        // anything that standard sql would ignore is rejected
+        let cases: Vec<(&str, Box<dyn Fn(&mut Query)>)> = vec![
+            ("Bins", Box::new(|q| q.bins = vec!["Geography.Geography.State,width,10".parse().unwrap()])),
+            ("Filters", Box::new(|q| q.filters = vec!["Sales.gt.10".parse().unwrap()])),
+            ("Filter expressions", Box::new(|q| q.filter_exprs = vec!["Sales.gt.10".parse().unwrap()])),
+            ("Top", Box::new(|q| q.top = Some("1,Geography.Geography.State,Sales,desc".parse().unwrap()))),
+            ("Top where", Box::new(|q| q.top_where = Some("Sales,gt.10".parse().unwrap()))),
+            ("Sort", Box::new(|q| q.sort = vec!["Sales.desc".parse().unwrap()])),
+            ("Limit", Box::new(|q| q.limit = Some("10".parse().unwrap()))),
+            ("Rca", Box::new(|q| q.rca = Some("Geography.Geography.State,Year.Year.Year,Sales".parse().unwrap()))),
+            ("Growth", Box::new(|q| q.growth = Some("Year.Year.Year,Sales".parse().unwrap()))),
+            ("Rate", Box::new(|q| q.rate = Some("Geography.Geography.State.1".parse().unwrap()))),
+            ("Rank", Box::new(|q| q.rank = Some("Geography.Geography.State,Sales,desc".parse().unwrap()))),
+            ("Sparse", Box::new(|q| q.sparse = true)),
+            ("Dense", Box::new(|q| q.dense = true)),
+            ("Nonempty", Box::new(|q| q.nonempty = true)),
+        ];
+
+        for (feature, set_option) in cases {
+            let mut query = base_query.clone();
+            set_option(&mut query);
+
+            let (query_ir, _) = schema.sql_query("sales", &query, None).unwrap();
+            let err = sql::check_query_ir(&query_ir).unwrap_err();
+            assert_eq!(err.to_string(), format!("{} is not supported for this database", feature));
+        }
+    }
+    #[test]
+    fn test_multi_cube_query() {
+        let s = r##"
+            <Schema name="my_schema">
+                <SharedDimension name="Geography">
+                    <Hierarchy name="Geography" primary_key="state_id">
+                        <Table name="dim_geo" />
+                        <Level name="State" key_column="state_id" name_column="state_name" />
+                    </Hierarchy>
+                </SharedDimension>
+                <Cube name="income">
+                    <Table name="income_table" />
+                    <DimensionUsage foreign_key="state_id" name="Geography" source="Geography" />
+                    <Dimension name="Industry" foreign_key="industry_id">
+                        <Hierarchy name="Industry" primary_key="industry_id">
+                            <Table name="dim_industry" />
+                            <Level name="Industry" key_column="industry_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Income" column="income" aggregator="avg" />
+                </Cube>
+                <Cube name="population">
+                    <Table name="population_table" />
+                    <DimensionUsage foreign_key="state_id" name="Geography" source="Geography" />
+                    <Measure name="Population" column="population" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema: Schema = Schema::from_xml(s).unwrap();
+        let cubes = vec!["income".to_owned(), "population".to_owned()];
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geography.Geography.State".parse().unwrap()];
+        query.measures = vec![Measure::new("Population"), Measure::new("Income")];
+
+        let (multi_query_ir, headers) = schema.multi_cube_sql_query(&cubes, &query, None).unwrap();
+
+        assert_eq!(multi_query_ir.query_irs.len(), 2);
+        assert_eq!(multi_query_ir.join.num_key_cols, 2);
+        assert_eq!(multi_query_ir.query_irs[0].table.name, "income_table");
+        assert_eq!(headers, vec!["State ID".to_owned(), "State".to_owned(), "Population".to_owned(), "Income".to_owned()]);
+
+        // the cubes are joined in order, then the measures are put back in the
+        // requested order
+        let income = DataFrame::from_vec(vec![
+            Column::new("State ID".into(), ColumnData::Int32(vec![1, 2])),
+            Column::new("State".into(), ColumnData::Text(vec!["CA".into(), "NY".into()])),
+            Column::new("Income".into(), ColumnData::Float64(vec![59.5, 61.0])),
+        ]);
+        let population = DataFrame::from_vec(vec![
+            Column::new("State ID".into(), ColumnData::Int32(vec![2, 1])),
+            Column::new("State".into(), ColumnData::Text(vec!["NY".into(), "CA".into()])),
+            Column::new("Population".into(), ColumnData::UInt64(vec![19, 39])),
+        ]);
+        let df = multi_query_ir.join.join(vec![income, population]).unwrap();
+        assert_eq!(df.columns.len(), 4);
+        assert_eq!(df.columns[2].column_data, ColumnData::UInt64(vec![39, 19]));
+        assert_eq!(df.columns[3].column_data, ColumnData::Float64(vec![59.5, 61.0]));
+
+        // Industry is not in the population cube
+        query.drilldowns.push("Industry.Industry.Industry".parse().unwrap());
+        assert!(schema.multi_cube_sql_query(&cubes, &query, None).is_err());
+    }
+
+    #[test]
+    fn test_normalize_query() {
+        let s = r##"
+            <Schema name="my_schema">
+                <SharedDimension name="Geography">
+                    <Hierarchy name="Geography" primary_key="state_id">
+                        <Table name="dim_geo" />
+                        <Level name="State" key_column="state_id" name_column="state_name" />
+                    </Hierarchy>
+                </SharedDimension>
+                <Cube name="cases">
+                    <Table name="cases_table" />
+                    <DimensionUsage foreign_key="state_id" name="Geography" source="Geography" />
+                    <Measure name="Cases" column="cases" aggregator="sum" />
+                </Cube>
+                <Cube name="population">
+                    <Table name="population_table" />
+                    <DimensionUsage foreign_key="state_id" name="Geography" source="Geography" />
+                    <Measure name="Population" column="population" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema: Schema = Schema::from_xml(s).unwrap();
+        let cubes = vec!["cases".to_owned()];
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geography.Geography.State".parse().unwrap()];
+        query.measures = vec![Measure::new("Cases")];
+        query.normalize = Some("population.Population,100000".parse().unwrap());
+
+        // the denominator is in another cube
+        assert!(schema.sql_query("cases", &query, None).is_err());
+
+        let (multi_query_ir, headers) = schema.multi_cube_sql_query(&cubes, &query, None).unwrap();
+
+        assert_eq!(multi_query_ir.query_irs.len(), 2);
+        assert_eq!(multi_query_ir.query_irs[1].table.name, "population_table");
+        assert_eq!(multi_query_ir.join.num_key_cols, 2);
+        assert_eq!(multi_query_ir.join.normalize_scale, Some(100000.0));
+        assert_eq!(headers, vec!["State ID".to_owned(), "State".to_owned(), "Cases per 100000 Population".to_owned()]);
+
+        query.normalize = Some("population.Cases".parse().unwrap());
+        assert!(schema.multi_cube_sql_query(&cubes, &query, None).is_err());
+    }
+
+    #[test]
+    fn test_virtual_time_levels() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <Dimension name="Date" foreign_key="sale_date" type="time">
+                        <Hierarchy name="Date">
+                            <Level name="Year" key_column="year" time_part="year" />
+                            <Level name="Month" key_column="month" time_part="month" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema: Schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Date.Date.Month".parse().unwrap()];
+        query.cuts = vec!["Date.Date.Year.2019".parse().unwrap()];
+        query.measures = vec![Measure::new("Sales")];
+        query.parents = true;
+
+        let (query_ir, headers) = schema.sql_query("sales", &query, None).unwrap();
+
+        assert_eq!(query_ir.drills[0].table.name, "sales_table");
+        assert_eq!(query_ir.drills[0].time, Some(TimeSql {
+            column: "sale_date".into(),
+            parts: vec![TimePart::Year, TimePart::Month],
+            fiscal_year_start_month: None,
+        }));
+        assert_eq!(query_ir.cuts[0].column, "sale_date");
+        assert_eq!(query_ir.cuts[0].time_part, Some(TimePart::Year));
+        assert_eq!(headers, vec!["Year".to_owned(), "Month".to_owned(), "Sales".to_owned()]);
+
+        // fiscal years are shifted by the cube's start month
+        let fiscal = s
+            .replace(r#"<Cube name="sales">"#, r#"<Cube name="sales" fiscal_year_start_month="7">"#)
+            .replace(r#"time_part="year""#, r#"time_part="fiscal_year""#);
+        let mut fiscal_schema: Schema = Schema::from_xml(&fiscal).unwrap();
+        fiscal_schema.validate().unwrap();
+
+        let (query_ir, _) = fiscal_schema.sql_query("sales", &query, None).unwrap();
+        assert_eq!(query_ir.cuts[0].time_part, Some(TimePart::FiscalYear));
+        assert_eq!(query_ir.cuts[0].fiscal_year_start_month, Some(7));
+        assert_eq!(query_ir.drills[0].time.as_ref().unwrap().fiscal_year_start_month, Some(7));
+
+        let fiscal = fiscal.replace(r#"fiscal_year_start_month="7""#, r#"fiscal_year_start_month="13""#);
+        let mut fiscal_schema: Schema = Schema::from_xml(&fiscal).unwrap();
+        assert!(fiscal_schema.validate().is_err());
+
+        // virtual levels don't have a dimension table
+        let s = s.replace(r#"<Hierarchy name="Date">"#, r#"<Hierarchy name="Date"><Table name="dim_date" />"#);
+        let mut schema: Schema = Schema::from_xml(&s).unwrap();
+        assert!(schema.validate().is_err());
+    }
+
+    #[test]
+    fn test_range_cut_ends() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <Dimension name="Year" foreign_key="year_id">
+                        <Hierarchy name="Year" primary_key="year_id">
+                            <Table name="dim_year" />
+                            <Level name="Year" key_column="year" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema: Schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Year.Year.Year".parse().unwrap()];
+        query.measures = vec![Measure::new("Sales")];
+
+        query.cuts = vec![Cut::new_range("Year", "Year", "Year", CutRange::Between("2010".into(), "2019".into()), Mask::Include)];
+        assert!(schema.sql_query("sales", &query, None).is_ok());
+
+        // non-text keys are not quoted, so the range ends must be numbers
+        let injection = "2019; drop table sales_table; --";
+        query.cuts = vec![Cut::new_range("Year", "Year", "Year", CutRange::To(injection.into()), Mask::Include)];
+        assert!(schema.sql_query("sales", &query, None).is_err());
+    }
+
+    #[test]
+    fn test_parent_child_hierarchy() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="payroll">
+                    <Table name="payroll_table" />
+                    <Dimension name="Employee" foreign_key="employee_id">
+                        <Hierarchy name="Employee" primary_key="id" parent_column="manager_id" max_depth="3">
+                            <Table name="dim_employee" />
+                            <Level name="Employee" key_column="id" name_column="full_name" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Salary" column="salary" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema: Schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+
+        let hier_metadata = &schema.cube_metadata("payroll").unwrap().dimensions[0].hierarchies[0];
+        assert_eq!(hier_metadata.hierarchy_type, HierarchyType::ParentChild);
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Employee.Employee.Employee".parse().unwrap()];
+        query.cuts = vec!["Employee.Employee.Employee.1".parse().unwrap()];
+        query.measures = vec![Measure::new("Salary")];
+        query.parents = true;
+
+        let (query_ir, headers) = schema.sql_query("payroll", &query, None).unwrap();
+
+        // facts join on the descendants of each member
+        let parent_child = query_ir.drills[0].parent_child.clone().unwrap();
+        assert_eq!(query_ir.drills[0].primary_key, "descendant_key");
+        assert_eq!(parent_child.columns, vec!["id".to_owned(), "full_name".to_owned()]);
+        assert_eq!(parent_child.max_depth, 3);
+        assert_eq!(query_ir.cuts[0].primary_key, "descendant_key");
+        assert!(query_ir.cuts[0].parent_child.is_some());
+        assert_eq!(query_ir.cuts[0].parent_child.as_ref().unwrap().ancestors, 0);
+
+        // with parents, the ancestor path of each member is selected up to the
+        // max depth, nearest first
+        let key_columns: Vec<_> = query_ir.drills[0].level_columns.iter().map(|col| col.key_column.as_str()).collect();
+        assert_eq!(key_columns, vec!["ancestor_1", "ancestor_2", "ancestor_3", "id"]);
+        assert_eq!(headers, vec![
+            "Ancestor 1 Employee ID".to_owned(),
+            "Ancestor 2 Employee ID".to_owned(),
+            "Ancestor 3 Employee ID".to_owned(),
+            "Employee ID".to_owned(),
+            "Employee".to_owned(),
+            "Salary".to_owned(),
+        ]);
+
+        // for a tree of CEO > VP > Director > Manager, the manager's ancestors
+        // are found by joining up through the parent column
+        let members = "(select m.*, a1.id as ancestor_1, a2.id as ancestor_2, a3.id as ancestor_3 from dim_employee as m \
+            left join dim_employee as a1 on a1.id = m.manager_id \
+            left join dim_employee as a2 on a2.id = a1.manager_id \
+            left join dim_employee as a3 on a3.id = a2.manager_id)";
+        let columns = "{0}.id as id, {0}.full_name as full_name, {0}.ancestor_1 as ancestor_1, {0}.ancestor_2 as ancestor_2, {0}.ancestor_3 as ancestor_3";
+
+        let sql = parent_child.sql();
+        assert_eq!(sql.matches("union all").count(), 3);
+        assert!(sql.starts_with(&format!("select t0.id as descendant_key, {} from {} as t0 union all", columns.replace("{0}", "t0"), members)));
+        assert!(sql.ends_with(&format!("select t0.id as descendant_key, {} from dim_employee as t0 \
+            inner join dim_employee as t1 on t1.id = t0.manager_id \
+            inner join dim_employee as t2 on t2.id = t1.manager_id \
+            inner join {} as t3 on t3.id = t2.manager_id", columns.replace("{0}", "t3"), members)));
+
+        // a closure table replaces the self joins
+        let closure = s.replace(
+            r#"<Table name="dim_employee" />"#,
+            r#"<Table name="dim_employee" /><Closure parent_column="ancestor_id" child_column="employee_id"><Table name="employee_closure" /></Closure>"#,
+        );
+        let mut closure_schema: Schema = Schema::from_xml(&closure).unwrap();
+        closure_schema.validate().unwrap();
+
+        query.parents = false;
+        let (query_ir, _) = closure_schema.sql_query("payroll", &query, None).unwrap();
+        assert_eq!(
+            query_ir.drills[0].parent_child.as_ref().unwrap().sql(),
+            "select closure.employee_id as descendant_key, ancestor.id as id, ancestor.full_name as full_name from employee_closure as closure inner join dim_employee as ancestor on ancestor.id = closure.ancestor_id"
+        );
+
+        // and ancestors are ordered by their depth in the closure table
+        query.parents = true;
+        let (query_ir, _) = closure_schema.sql_query("payroll", &query, None).unwrap();
+        let depth = "(select employee_id as member_key, count(*) as depth from employee_closure group by employee_id)";
+        assert_eq!(
+            query_ir.drills[0].parent_child.as_ref().unwrap().sql(),
+            format!("select closure.employee_id as descendant_key, {} from employee_closure as closure \
+                inner join (select m.*, path.ancestor_1, path.ancestor_2, path.ancestor_3 from dim_employee as m left join (\
+                select closure.employee_id as member_key, \
+                max(case when member_depth.depth - ancestor_depth.depth = 1 then closure.ancestor_id end) as ancestor_1, \
+                max(case when member_depth.depth - ancestor_depth.depth = 2 then closure.ancestor_id end) as ancestor_2, \
+                max(case when member_depth.depth - ancestor_depth.depth = 3 then closure.ancestor_id end) as ancestor_3 \
+                from employee_closure as closure \
+                inner join {} as member_depth on member_depth.member_key = closure.employee_id \
+                inner join {} as ancestor_depth on ancestor_depth.member_key = closure.ancestor_id \
+                group by closure.employee_id) as path on path.member_key = m.id) as ancestor on ancestor.id = closure.ancestor_id",
+                columns.replace("{0}", "ancestor"), depth, depth),
+        );
+
+        // parent-child hierarchies have a single level from a dimension table
+        let s = s.replace(r#"<Table name="dim_employee" />"#, "");
+        let mut schema: Schema = Schema::from_xml(&s).unwrap();
+        assert!(schema.validate().is_err());
+    }
+
+    #[test]
+    fn test_snowflake_hierarchy() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <Dimension name="Product" foreign_key="product_id">
+                        <Hierarchy name="Product" primary_key="product_id">
+                            <Table name="dim_product" />
+                            <Level name="Category" key_column="category_id" name_column="name" foreign_key="category_id">
+                                <Table name="dim_category" />
+                            </Level>
+                            <Level name="Subcategory" key_column="subcategory_id" name_column="name" foreign_key="subcategory_id">
+                                <Table name="dim_subcategory" />
+                            </Level>
+                            <Level name="Product" key_column="product_id" name_column="name" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema: Schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+
+        let table_sql = "(select dim_product.product_id as product_id, dim_product.name as name, \
+            dim_subcategory.subcategory_id as dim_subcategory_subcategory_id, dim_subcategory.name as dim_subcategory_name, \
+            dim_category.category_id as dim_category_category_id, dim_category.name as dim_category_name \
+            from dim_product as dim_product \
+            left join dim_subcategory as dim_subcategory on dim_subcategory.subcategory_id = dim_product.subcategory_id \
+            left join dim_category as dim_category on dim_category.category_id = dim_subcategory.category_id) as dim_product";
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Product.Product.Category".parse().unwrap()];
+        query.cuts = vec!["Product.Product.Subcategory.1".parse().unwrap()];
+        query.measures = vec![Measure::new("Sales")];
+
+        let (query_ir, _) = schema.sql_query("sales", &query, None).unwrap();
+
+        assert_eq!(query_ir.drills[0].table_sql(), table_sql);
+        assert_eq!(query_ir.drills[0].level_columns[0].key_column, "dim_category_category_id");
+        assert_eq!(query_ir.drills[0].level_columns[0].name_column, Some("dim_category_name".to_owned()));
+        assert_eq!(query_ir.cuts[0].table_sql(), table_sql);
+        assert_eq!(query_ir.cuts[0].column, "dim_subcategory_subcategory_id");
+
+        let (members_sql, _) = schema.members_sql("sales", &"Product.Product.Category".parse().unwrap()).unwrap();
+        assert_eq!(members_sql, format!("select distinct dim_category_category_id, dim_category_name from {}", table_sql));
+
+        // level tables are joined to the hierarchy table
+        let s = s.replace(r#"<Table name="dim_product" />"#, "");
+        let mut schema: Schema = Schema::from_xml(&s).unwrap();
+        assert!(schema.validate().is_err());
+    }
+
+    #[test]
+    fn test_virtual_cube() {
+        let s = r##"
+            <Schema name="my_schema">
+                <SharedDimension name="Geography">
+                    <Hierarchy name="Geography" primary_key="state_id">
+                        <Table name="dim_geo" />
+                        <Level name="State" key_column="state_id" name_column="state_name" />
+                    </Hierarchy>
+                </SharedDimension>
+                <Cube name="income" min_auth_level="2">
+                    <Table name="income_table" />
+                    <DimensionUsage foreign_key="state_id" name="Geography" source="Geography" />
+                    <Dimension name="Industry" foreign_key="industry_id">
+                        <Hierarchy name="Industry" primary_key="industry_id">
+                            <Table name="dim_industry" />
+                            <Level name="Industry" key_column="industry_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Income" column="income" aggregator="avg" />
+                </Cube>
+                <Cube name="population">
+                    <Table name="population_table" />
+                    <DimensionUsage foreign_key="state_id" name="Geography" source="Geography" />
+                    <Measure name="Population" column="population" aggregator="sum" />
+                </Cube>
+                <VirtualCube name="income_population">
+                    <CubeUsage cube_name="income" />
+                    <CubeUsage cube_name="population" />
+                </VirtualCube>
+            </Schema>
+        "##;
+        let mut schema: Schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+
+        // only the shared dimensions common to all cubes
+        let cube = schema.get_cube_by_name("income_population").unwrap();
+        assert_eq!(cube.dimensions.len(), 1);
+        assert_eq!(cube.dimensions[0].name, "Geography");
+        assert_eq!(cube.measures.len(), 2);
+        assert_eq!(cube.min_auth_level, 2);
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geography.Geography.State".parse().unwrap()];
+        query.measures = vec![Measure::new("Population"), Measure::new("Income")];
+
+        // measures are in the requested order, though the cubes are queried
+        // in the order of the cube usages
+        let (multi_query_ir, headers) = schema.virtual_cube_sql_query("income_population", &query, None).unwrap();
+        assert_eq!(multi_query_ir.query_irs.len(), 2);
+        assert_eq!(multi_query_ir.query_irs[0].table.name, "income_table");
+        assert_eq!(multi_query_ir.join.measure_order, Some(vec![1, 0]));
+        assert_eq!(headers, vec!["State ID".to_owned(), "State".to_owned(), "Population".to_owned(), "Income".to_owned()]);
+
+        // measures from one cube only query that cube
+        query.measures = vec![Measure::new("Population")];
+        query.limit = Some("10".parse().unwrap());
+
+        let (multi_query_ir, _) = schema.virtual_cube_sql_query("income_population", &query, None).unwrap();
+        assert_eq!(multi_query_ir.query_irs.len(), 1);
+        assert_eq!(multi_query_ir.query_irs[0].table.name, "population_table");
+
+        assert!(schema.sql_query("income_population", &query, None).is_err());
+
+        // dimensions that aren't shared by the virtual cube can't be used,
+        // even if the cube queried has them
+        query.measures = vec![Measure::new("Income")];
+        query.drilldowns = vec!["Industry.Industry.Industry".parse().unwrap()];
+        assert!(schema.sql_query("income", &query, None).is_ok());
+        assert!(schema.virtual_cube_sql_query("income_population", &query, None).is_err());
+
+        query.drilldowns = vec!["Geography.Geography.State".parse().unwrap()];
+        query.cuts = vec!["Industry.Industry.Industry.1".parse().unwrap()];
+        assert!(schema.virtual_cube_sql_query("income_population", &query, None).is_err());
+
+        // querying a virtual cube needs access to all of its cubes, even if
+        // its own auth level is lower
+        assert_eq!(schema.cube_auth_level("income_population").unwrap(), 2);
+        assert_eq!(schema.cube_auth_level("population").unwrap(), 0);
+
+        let public_s = s.replace(r#"<VirtualCube name="income_population">"#, r#"<VirtualCube name="income_population" min_auth_level="0">"#);
+        let public_schema: Schema = Schema::from_xml(&public_s).unwrap();
+        assert_eq!(public_schema.get_cube_by_name("income_population").unwrap().min_auth_level, 0);
+        assert_eq!(public_schema.cube_auth_level("income_population").unwrap(), 2);
+
+        let s = s.replace(r#"<CubeUsage cube_name="population" />"#, r#"<CubeUsage cube_name="missing" />"#);
+        let mut schema: Schema = Schema::from_xml(&s).unwrap();
+        assert!(schema.validate().is_err());
+    }
+
+    #[test]
+    fn test_aggregate_tables() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <Dimension name="Geography" foreign_key="tract_id">
+                        <Hierarchy name="Geography" primary_key="tract_id">
+                            <Table name="dim_geo" />
+                            <Level name="State" key_column="state_id" name_column="state_name" />
+                            <Level name="Tract" key_column="tract_id" name_column="tract_name" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Dimension name="Year" foreign_key="year">
+                        <Hierarchy name="Year" primary_key="year">
+                            <Level name="Year" key_column="year" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                    <Measure name="Orders" column="order_id" aggregator="count" />
+                    <AggregateTable>
+                        <Table name="sales_agg_state" />
+                        <AggregateLevel dimension="Geography" hierarchy="Geography" level="State" key_column="state_id" name_column="state_name" />
+                        <AggregateMeasure name="Sales" column="sales" />
+                        <AggregateMeasure name="Orders" column="orders" />
+                    </AggregateTable>
+                    <AggregateTable rows="500">
+                        <Table name="sales_agg_year_state" />
+                        <AggregateLevel dimension="Geography" hierarchy="Geography" level="State" key_column="state_id" name_column="state_name" />
+                        <AggregateLevel dimension="Year" hierarchy="Year" level="Year" key_column="year" />
+                        <AggregateMeasure name="Sales" column="sales_total" />
+                    </AggregateTable>
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema: Schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+        assert!(schema.cubes[0].can_aggregate);
+
+        // the smallest table with the levels and measures is used
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geography.Geography.State".parse().unwrap()];
+        query.cuts = vec!["Year.Year.Year.2019".parse().unwrap()];
+        query.measures = vec![Measure::new("Sales")];
+
+        let (query_ir, headers) = schema.sql_query("sales", &query, None).unwrap();
+        assert_eq!(query_ir.table.name, "sales_agg_year_state");
+        assert_eq!(query_ir.drills[0].table.name, "sales_agg_year_state");
+        assert_eq!(query_ir.drills[0].level_columns[0].name_column, Some("state_name".to_owned()));
+        assert_eq!(query_ir.cuts[0].table.name, "sales_agg_year_state");
+        assert_eq!(query_ir.meas[0].column, "sales_total");
+        assert_eq!(headers, vec!["State ID".to_owned(), "State".to_owned(), "Sales".to_owned()]);
+
+        // counts are summed from the aggregate table
+        query.cuts = vec![];
+        query.measures = vec![Measure::new("Orders")];
+        let (query_ir, _) = schema.sql_query("sales", &query, None).unwrap();
+        assert_eq!(query_ir.table.name, "sales_agg_state");
+        assert_eq!(query_ir.meas[0].column, "orders");
+        assert_eq!(query_ir.meas[0].aggregator, Aggregator::Sum);
+
+        // levels that aren't aggregated fall back to the fact table
+        query.drilldowns = vec!["Geography.Geography.Tract".parse().unwrap()];
+        let (query_ir, _) = schema.sql_query("sales", &query, None).unwrap();
+        assert_eq!(query_ir.table.name, "sales_table");
+        assert_eq!(query_ir.drills[0].table.name, "dim_geo");
+
+        // and so do cuts and properties that aren't aggregated
+        query.drilldowns = vec!["Year.Year.Year".parse().unwrap()];
+        query.cuts = vec!["Geography.Geography.Tract.1".parse().unwrap()];
+        query.measures = vec![Measure::new("Sales")];
+        let (query_ir, _) = schema.sql_query("sales", &query, None).unwrap();
+        assert_eq!(query_ir.table.name, "sales_table");
+
+        // averages can't be re-aggregated
+        let s = s.replace(r#"aggregator="count""#, r#"aggregator="avg""#);
+        let mut schema: Schema = Schema::from_xml(&s).unwrap();
+        assert!(schema.validate().is_err());
+    }
 }
@@ -175,6 +175,15 @@ impl FromStr for Measure {
 /// Note: FromStr impl aggressively left trims ampersands
 /// from the beginning of member list and from the
 /// beginning of each member
+///
+/// A range of members can be cut instead of a member list,
+/// e.g. `Year.2010..2020` or the open-ended `Year.2015..`.
+/// A range cut has no members.
+///
+/// A cut can also be on a property of the level instead of
+/// its key, with members after a colon, e.g.
+/// `[Geography].[State].[Region]:South`. The property name
+/// must be in brackets.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Cut {
     // if mask is include, includes indicated members in the cut.
@@ -183,6 +192,9 @@ pub struct Cut {
     pub members: Vec<String>,
     pub mask: Mask,
     pub for_match: bool,
+    pub range: Option<CutRange>,
+    // if property, cuts on the property column instead of the level key.
+    pub property: Option<String>,
 }
 
 impl Cut {
@@ -200,6 +212,26 @@ impl Cut {
             members: members.into_iter().map(|s| s.into()).collect(),
             mask,
             for_match,
+            range: None,
+            property: None,
+        }
+    }
+
+    pub fn new_range<S: Into<String>>(
+        dimension: S,
+        hierarchy: S,
+        level: S,
+        range: CutRange,
+        mask: Mask,
+        ) -> Self
+    {
+        Cut {
+            level_name: LevelName::new(dimension, hierarchy, level),
+            members: vec![],
+            mask,
+            for_match: false,
+            range: Some(range),
+            property: None,
         }
     }
 
@@ -216,6 +248,8 @@ impl Cut {
                     members: members.clone().into_iter().map(|s| s.into()).collect(),
                     mask,
                     for_match,
+                    range: None,
+                    property: None,
                 }
             })
             .map_err(|err| {
@@ -260,6 +294,14 @@ impl Cut {
 // TODO fix this, it only displays "keys" and not "labels"
 impl fmt::Display for Cut {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref range) = self.range {
+            return write!(f, "{}{}.&[{}]", self.mask, self.level_name, range);
+        }
+
+        if let Some(ref property) = self.property {
+            return write!(f, "{}{}.[{}]:{}", self.mask, self.level_name, property, self.members.join(","));
+        }
+
         // members must be more than 0, checked by assert on serialization
         if self.members.len() == 1 {
             write!(f, "{}{}.&[{}]", self.mask, self.level_name, self.members[0])
@@ -316,6 +358,54 @@ impl FromStr for Cut {
             s
         };
 
+        // property cuts have members after a colon, which follows the
+        // closing bracket of the property name. Brackets are required, so
+        // that a member containing a colon (e.g. `Time.Time.Hour.12:00`)
+        // isn't read as a property cut.
+        let property_idx = if s.chars().nth(0).unwrap() == '[' {
+            s.find("]:")
+                .filter(|&idx| !s[..idx].contains('&'))
+                .map(|idx| idx + 1)
+        } else {
+            None
+        };
+
+        if let Some(property_idx) = property_idx {
+            let property = s[..property_idx].parse::<Property>()?;
+            let members = s[property_idx+1..]
+                .trim_start_matches('&')
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .map(|s| s.trim_start_matches('&').to_owned())
+                .collect();
+
+            return Ok(Cut {
+                level_name: property.level_name,
+                members,
+                mask,
+                for_match,
+                range: None,
+                property: Some(property.property),
+            });
+        }
+
+        // a range's ".." would be split apart as name separators, so
+        // the range is cut off first and the rest processed normally
+        let (s, range) = if s.chars().nth(0).unwrap() != '[' && s.contains("..") {
+            // for `..end` ranges, the first of three dots is the separator
+            let mut range_idx = s.find("..").unwrap();
+            if s[range_idx+2..].starts_with('.') {
+                range_idx += 1;
+            }
+            let range_start_idx = s[..range_idx].rfind('.')
+                .ok_or_else(|| format_err!("Could not find level for range cut {}", s))?;
+
+            (&s[..range_start_idx+1], Some(s[range_start_idx+1..].parse::<CutRange>()?))
+        } else {
+            (s, None)
+        };
+
         // then do rest of processing normally
         let name_vec: Vec<_> = if s.chars().nth(0).unwrap() == '[' {
             // check if starts with '[', then assume
@@ -340,23 +430,94 @@ impl FromStr for Cut {
                 .collect()
         };
 
-        let members: Vec<_> = name_vec[name_vec.len()-1]
+        let member_str = name_vec[name_vec.len()-1]
             .trim_start_matches('&')
-            .trim_start_matches('[')
-            .split(',')
-            .map(|s| s.trim_start_matches('&').to_owned())
-            .collect();
+            .trim_start_matches('[');
+
+        let (members, range) = if range.is_some() {
+            (vec![], range)
+        } else if member_str.contains("..") {
+            (vec![], Some(member_str.parse::<CutRange>()?))
+        } else {
+            let members = member_str
+                .split(',')
+                .map(|s| s.trim_start_matches('&').to_owned())
+                .collect();
+
+            (members, None)
+        };
+
+        if for_match && range.is_some() {
+            bail!("Cannot match (*) on a range cut");
+        }
 
         Ok(Cut {
             level_name: LevelName::from_vec(name_vec[0..name_vec.len()-1].to_vec())?,
             members,
             mask,
             for_match,
+            range,
+            property: None,
         })
     }
 }
 
 
+/// Range of members for a cut, inclusive on both ends. Members are compared
+/// by key, so this is only meaningful for levels with ordered keys (e.g. years).
+///
+/// Parses from `start..end`, `start..` or `..end`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum CutRange {
+    Between(String, String),
+    From(String),
+    To(String),
+}
+
+impl CutRange {
+    /// The start and/or end of the range.
+    pub fn ends(&self) -> Vec<&str> {
+        match self {
+            CutRange::Between(start, end) => vec![start, end],
+            CutRange::From(start) => vec![start],
+            CutRange::To(end) => vec![end],
+        }
+    }
+}
+
+impl fmt::Display for CutRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CutRange::Between(start, end) => write!(f, "{}..{}", start, end),
+            CutRange::From(start) => write!(f, "{}..", start),
+            CutRange::To(end) => write!(f, "..{}", end),
+        }
+    }
+}
+
+impl FromStr for CutRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let range_idx = s.find("..")
+            .ok_or_else(|| format_err!("Range {} must contain '..'", s))?;
+
+        let start = s[..range_idx].trim_start_matches('&');
+        let end = s[range_idx+2..].trim_start_matches('&');
+
+        if end.contains("..") {
+            bail!("Range {} must contain only one '..'", s);
+        }
+
+        match (start.is_empty(), end.is_empty()) {
+            (false, false) => Ok(CutRange::Between(start.to_owned(), end.to_owned())),
+            (false, true) => Ok(CutRange::From(start.to_owned())),
+            (true, false) => Ok(CutRange::To(end.to_owned())),
+            (true, true) => bail!("Range {} must have a start or an end", s),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Mask {
     Include,
@@ -585,6 +746,36 @@ mod test {
         let cut2_test_1_not = "~Geography.Geography.County.1,2".parse::<Cut>().unwrap();
         assert_eq!(cut2_not, cut2_test_1_not);
 
+        // test range cuts
+        let range = Cut::new_range("Year", "Year", "Year", CutRange::Between("2010".into(), "2020".into()), Mask::Include);
+        let range_from = Cut::new_range("Year", "Year", "Year", CutRange::From("2015".into()), Mask::Include);
+        let range_to_not = Cut::new_range("Year", "Year", "Year", CutRange::To("2015".into()), Mask::Exclude);
+
+        assert_eq!(range, "Year.Year.2010..2020".parse::<Cut>().unwrap());
+        assert_eq!(range, "[Year].[Year].[Year].&[2010..2020]".parse::<Cut>().unwrap());
+        assert_eq!(range_from, "Year.Year.Year.2015..".parse::<Cut>().unwrap());
+        assert_eq!(range_from, "[Year].[Year].&[2015..]".parse::<Cut>().unwrap());
+        assert_eq!(range_to_not, "~Year.Year...2015".parse::<Cut>().unwrap());
+        assert_eq!(range_to_not, "~[Year].[Year].&[..2015]".parse::<Cut>().unwrap());
+
+        assert!("Year.Year...".parse::<Cut>().is_err());
+        assert!("Year.Year.2010..2015..2020".parse::<Cut>().is_err());
+        assert!("*Year.Year.2010..2020".parse::<Cut>().is_err());
+
+        // test property cuts
+        let mut property_cut = Cut::new("Geography", "Geography", "State", vec!["South", "West"], Mask::Include, false);
+        property_cut.property = Some("Region".into());
+
+        assert_eq!(property_cut, "[Geography].[State].[Region]:South,West".parse::<Cut>().unwrap());
+        assert_eq!(property_cut, "[Geography].[Geography].[State].[Region]:South,West".parse::<Cut>().unwrap());
+        assert_eq!(property_cut, property_cut.to_string().parse::<Cut>().unwrap());
+
+        // a colon in a member is not a property cut
+        let time_cut = Cut::new("Time", "Time", "Hour", vec!["12:00"], Mask::Include, false);
+        assert_eq!(time_cut, "Time.Time.Hour.12:00".parse::<Cut>().unwrap());
+        assert_eq!(time_cut, "[Time].[Time].[Hour].&[12:00]".parse::<Cut>().unwrap());
+        assert_eq!(time_cut, time_cut.to_string().parse::<Cut>().unwrap());
+
         // test property
         let property_test_1 = "Geography.Geography.County.name_en".parse::<Property>().unwrap();
         let property_test_2 = "[Geography].[Geography].[County].[name_en]".parse::<Property>().unwrap();
@@ -15,20 +15,38 @@ use crate::names::{
 pub struct Query {
     pub cuts: Vec<Cut>,
     pub drilldowns: Vec<Drilldown>,
+    // groups by property value instead of level member
+    pub property_drilldowns: Vec<Property>,
+    // groups by buckets of a numeric level key or property
+    pub bins: Vec<BinQuery>,
     pub measures: Vec<Measure>,
     pub properties: Vec<Property>,
     pub filters: Vec<FilterQuery>,
+    pub filter_exprs: Vec<FilterExpr>,
     pub captions: Vec<Property>,
     pub parents: bool,
     pub top: Option<TopQuery>,
     pub top_where: Option<TopWhereQuery>,
-    pub sort: Option<SortQuery>,
+    pub top_other: bool,
+    pub sort: Vec<SortQuery>,
     pub limit: Option<LimitQuery>,
     pub rca: Option<RcaQuery>,
     pub growth: Option<GrowthQuery>,
     pub rate: Option<RateQuery>,
+    pub rank: Option<RankQuery>,
+    // drilldowns rolled up into subtotal rows, in rollup order
+    pub totals: Vec<Drilldown>,
+    // divides measures by a measure from another cube
+    pub normalize: Option<NormalizeQuery>,
     pub debug: bool,
     pub sparse: bool,
+    // fills in every combination of drilldown members
+    pub dense: bool,
+    pub dense_fill: Option<f64>,
+    // only rows where at least one measure has a value
+    pub nonempty: bool,
+    // only the distinct drilldown member combinations, without measures
+    pub distinct: bool,
     pub exclude_default_members: bool,
 }
 
@@ -36,21 +54,32 @@ impl Query {
     pub fn new() -> Self {
         Query {
             drilldowns: vec![],
+            property_drilldowns: vec![],
+            bins: vec![],
             cuts: vec![],
             measures: vec![],
             properties: vec![],
             filters: vec![],
+            filter_exprs: vec![],
             captions: vec![],
             parents: false,
             top: None,
             top_where: None,
-            sort: None,
+            top_other: false,
+            sort: vec![],
             limit: None,
             rca: None,
             growth: None,
             rate: None,
+            rank: None,
+            totals: vec![],
+            normalize: None,
             debug: false,
             sparse: false,
+            dense: false,
+            dense_fill: None,
+            nonempty: false,
+            distinct: false,
             exclude_default_members: false,
         }
     }
@@ -59,56 +88,63 @@ impl Query {
 // TODO: Move ClickHouse specific queries away from ts-core
 
 /// ClickHouse:
-/// select * from table_name order by sort_measures sort_direction
+/// select * from table_name order by sort_keys
 /// limit n by by_dimension
 #[derive(Debug, Clone)]
 pub struct TopQuery {
     pub n: u64,
     pub by_dimension: LevelName,
-    pub sort_mea_or_calc: Vec<MeaOrCalc>,
-    pub sort_direction: SortDirection,
+    pub sort: Vec<SortQuery>,
 }
 
 impl TopQuery  {
-    pub fn new(
-        n: u64, by_dimension: LevelName, sort_mea_or_calc: Vec<MeaOrCalc>,
-        sort_direction: SortDirection
-    ) -> Self {
+    pub fn new(n: u64, by_dimension: LevelName, sort: Vec<SortQuery>) -> Self {
         TopQuery {
             n,
             by_dimension,
-            sort_mea_or_calc,
-            sort_direction
+            sort,
         }
     }
 }
 
-// Currently only allows one sort_measure
+/// Format: `n,by_dimension,sort_key.direction[,sort_key.direction...]`
+///
+/// The single key format `n,by_dimension,sort_key,direction` is also accepted.
 impl FromStr for TopQuery {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match &s.split(",").collect::<Vec<_>>()[..] {
-            [n, by_dimension, sort_measure, sort_direction] => {
+        let top_split: Vec<_> = s.split(",").collect();
 
-                let n = n.parse::<u64>()?;
-                let by_dimension = by_dimension.parse::<LevelName>()?;
-                let sort_mea_or_calc = vec![sort_measure.parse::<MeaOrCalc>()?];
-                let sort_direction = sort_direction.parse::<SortDirection>()?;
+        if top_split.len() < 3 {
+            bail!("Could not parse a top query");
+        }
 
-                Ok(TopQuery {
-                    n,
-                    by_dimension,
-                    sort_mea_or_calc,
-                    sort_direction,
-                })
+        let n = top_split[0].parse::<u64>()?;
+        let by_dimension = top_split[1].parse::<LevelName>()?;
+
+        let sort = match &top_split[2..] {
+            [sort_key, sort_direction] if sort_direction.parse::<SortDirection>().is_ok() => {
+                vec![SortQuery {
+                    direction: sort_direction.parse::<SortDirection>()?,
+                    key: sort_key.parse::<ColumnKey>()?,
+                }]
             },
-            _ => bail!("Could not parse a top query"),
-        }
+            sort_queries => {
+                sort_queries.iter()
+                    .map(|sq| sq.parse::<SortQuery>())
+                    .collect::<Result<Vec<_>, _>>()?
+            },
+        };
+
+        Ok(TopQuery {
+            n,
+            by_dimension,
+            sort,
+        })
     }
 }
 
-// Just for TopQuery
 /// Currently rca and growth will be reserved keywords. This may be changed in the future,
 /// to allow measures that are named rca and growth
 #[derive(Debug, Clone, PartialEq)]
@@ -288,23 +324,24 @@ impl FromStr for LimitQuery {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Format: `sort_key.direction`
+#[derive(Debug, Clone, PartialEq)]
 pub struct SortQuery {
     pub direction: SortDirection,
-    pub measure: MeaOrCalc,
+    pub key: ColumnKey,
 }
 
 impl FromStr for SortQuery {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match &s.split(".").collect::<Vec<_>>()[..] {
-            [measure, direction] => {
-                let measure = measure.parse::<MeaOrCalc>()?;
+        match &s.rsplitn(2, ".").collect::<Vec<_>>()[..] {
+            [direction, key] => {
+                let key = key.parse::<ColumnKey>()?;
                 let direction = direction.parse::<SortDirection>()?;
                 Ok(SortQuery {
                     direction,
-                    measure,
+                    key,
                 })
             },
             _ => bail!("Could not parse a sort query"),
@@ -313,6 +350,48 @@ impl FromStr for SortQuery {
     }
 }
 
+/// A column of the query result, which can be sorted or filtered on:
+/// - a measure or calculation: `Measure`, `rca`
+/// - a drilldown ID: `[Dimension].[Hierarchy].[Level]`
+/// - a drilldown label: `[Dimension].[Hierarchy].[Level].label`
+/// - a property: `[Dimension].[Hierarchy].[Level].[Property]`
+///
+/// Levels and properties must be in the query drilldowns and properties.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnKey {
+    MeaOrCalc(MeaOrCalc),
+    LevelId(LevelName),
+    LevelLabel(LevelName),
+    Property(Property),
+}
+
+impl FromStr for ColumnKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.ends_with(".label") {
+            let level_name = s.trim_end_matches(".label").parse::<LevelName>()?;
+            return Ok(ColumnKey::LevelLabel(level_name));
+        }
+
+        if !s.starts_with("[") && !s.contains(".") {
+            return Ok(ColumnKey::MeaOrCalc(s.parse::<MeaOrCalc>()?));
+        }
+
+        let num_names = if s.starts_with("[") {
+            s.matches("].[").count() + 1
+        } else {
+            s.matches(".").count() + 1
+        };
+
+        match num_names {
+            2 | 3 => Ok(ColumnKey::LevelId(s.parse::<LevelName>()?)),
+            4 => Ok(ColumnKey::Property(s.parse::<Property>()?)),
+            _ => bail!("Could not parse '{}' to a measure, calculation, level or property column", s),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum SortDirection {
     Asc,
@@ -521,6 +600,174 @@ impl FromStr for FilterQuery {
 }
 
 
+/// A filter expression tree, applied to the final result (after aggregation).
+///
+/// Format:
+/// - `and(expr,expr,...)`, `or(expr,expr,...)`, `not(expr)`
+/// - a condition, `column_key.op.value`, where op is one of:
+///   - `eq`, `neq`, `lt`, `lte`, `gt`, `gte` for numeric comparisons
+///   - `equals`, `contains`, `startswith` for strings
+///   - `in`, for a list of strings separated by `|`
+///   - `isnull`, `isnotnull`, which take no value
+///
+/// The column key can be a measure, calculation, level or property; see `ColumnKey`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Condition(ColumnKey, FilterOp),
+}
+
+impl FromStr for FilterExpr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        for (prefix, is_not) in &[("and(", false), ("or(", false), ("not(", true)] {
+            if s.starts_with(prefix) {
+                if !s.ends_with(")") {
+                    bail!("Unbalanced parentheses in filter '{}'", s);
+                }
+
+                let inner = &s[prefix.len()..s.len()-1];
+                let children = split_top_level(inner)?.iter()
+                    .map(|child| child.parse::<FilterExpr>())
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if *is_not {
+                    if children.len() != 1 {
+                        bail!("not() filter takes exactly one expression");
+                    }
+                    return Ok(FilterExpr::Not(Box::new(children[0].clone())));
+                }
+
+                if children.is_empty() {
+                    bail!("{}) filter requires at least one expression", prefix);
+                }
+
+                return Ok(match *prefix {
+                    "and(" => FilterExpr::And(children),
+                    _ => FilterExpr::Or(children),
+                });
+            }
+        }
+
+        // Column keys and values may both contain periods, so the op is the
+        // first segment after the key that is a known op.
+        let segments: Vec<_> = s.split(".").collect();
+
+        let op_idx = segments.iter()
+            .skip(1)
+            .position(|seg| FilterOp::is_op(seg))
+            .map(|idx| idx + 1)
+            .ok_or_else(|| format_err!("Could not find a filter op in '{}'", s))?;
+
+        let key = join(&segments[..op_idx], ".").parse::<ColumnKey>()?;
+        let op = segments[op_idx];
+        let value = join(&segments[op_idx+1..], ".");
+
+        Ok(FilterExpr::Condition(key, FilterOp::from_parts(op, value)?))
+    }
+}
+
+/// Splits on commas that are not inside parentheses
+fn split_top_level(s: &str) -> Result<Vec<String>, Error> {
+    let mut res = vec![];
+    let mut depth = 0;
+    let mut curr = String::new();
+
+    for c in s.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                if depth == 0 {
+                    bail!("Unbalanced parentheses in filter '{}'", s);
+                }
+                depth -= 1;
+            },
+            ',' if depth == 0 => {
+                res.push(curr.clone());
+                curr.clear();
+                continue;
+            },
+            _ => (),
+        }
+        curr.push(c);
+    }
+
+    if depth != 0 {
+        bail!("Unbalanced parentheses in filter '{}'", s);
+    }
+    if !curr.is_empty() {
+        res.push(curr);
+    }
+
+    Ok(res)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOp {
+    Compare(Constraint),
+    Equals(String),
+    Contains(String),
+    StartsWith(String),
+    In(Vec<String>),
+    IsNull,
+    IsNotNull,
+}
+
+impl FilterOp {
+    fn is_op(s: &str) -> bool {
+        s.parse::<Comparison>().is_ok() ||
+            ["equals", "contains", "startswith", "in", "isnull", "isnotnull"].contains(&s)
+    }
+
+    fn from_parts(op: &str, value: String) -> Result<Self, Error> {
+        let filter_op = match op {
+            "equals" => FilterOp::Equals(value),
+            "contains" => FilterOp::Contains(value),
+            "startswith" => FilterOp::StartsWith(value),
+            "in" => FilterOp::In(value.split("|").map(|v| v.to_owned()).collect()),
+            "isnull" | "isnotnull" => {
+                if !value.is_empty() {
+                    bail!("Filter op {} does not take a value", op);
+                }
+                if op == "isnull" { FilterOp::IsNull } else { FilterOp::IsNotNull }
+            },
+            _ => FilterOp::Compare(format!("{}.{}", op, value).parse::<Constraint>()?),
+        };
+
+        Ok(filter_op)
+    }
+
+    /// sql for the op applied to `column`
+    pub fn sql_string(&self, column: &str) -> String {
+        match self {
+            FilterOp::Compare(constraint) => format!("{} {}", column, constraint.sql_string()),
+            FilterOp::Equals(v) => format!("{} = '{}'", column, escape_string(v)),
+            FilterOp::Contains(v) => format!("{} like '%{}%'", column, escape_like(v)),
+            FilterOp::StartsWith(v) => format!("{} like '{}%'", column, escape_like(v)),
+            FilterOp::In(vs) => {
+                let quoted = vs.iter().map(|v| format!("'{}'", escape_string(v)));
+                format!("{} in ({})", column, join(quoted, ", "))
+            },
+            FilterOp::IsNull => format!("{} is null", column),
+            FilterOp::IsNotNull => format!("{} is not null", column),
+        }
+    }
+}
+
+fn escape_string(s: &str) -> String {
+    s.replace("\\", "\\\\").replace("'", "\\'")
+}
+
+fn escape_like(s: &str) -> String {
+    escape_string(s).replace("%", "\\%").replace("_", "\\_")
+}
+
+
 #[derive(Debug, Clone)]
 pub struct RateQuery {
     pub level_name: LevelName,
@@ -558,6 +805,280 @@ impl FromStr for RateQuery {
     }
 }
 
+/// Adds a rank column for each member of `level_name`, ranked by a measure
+/// within each group of the other drilldowns.
+///
+/// Format: `level,measure,direction[,rank_type]`
+#[derive(Debug, Clone)]
+pub struct RankQuery {
+    pub level_name: LevelName,
+    pub mea_or_calc: MeaOrCalc,
+    pub direction: SortDirection,
+    pub rank_type: RankType,
+}
+
+impl RankQuery {
+    pub fn new(
+        level_name: LevelName, mea_or_calc: MeaOrCalc,
+        direction: SortDirection, rank_type: RankType
+    ) -> Self {
+        RankQuery {
+            level_name,
+            mea_or_calc,
+            direction,
+            rank_type,
+        }
+    }
+}
+
+impl FromStr for RankQuery {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rank_split: Vec<_> = s.split(",").collect();
+
+        let rank_type = match &rank_split[..] {
+            [_, _, _] => RankType::Standard,
+            [_, _, _, rank_type] => rank_type.parse::<RankType>()?,
+            _ => bail!("Could not parse a rank query, wrong number of args"),
+        };
+
+        Ok(RankQuery {
+            level_name: rank_split[0].parse::<LevelName>()?,
+            mea_or_calc: rank_split[1].parse::<MeaOrCalc>()?,
+            direction: rank_split[2].parse::<SortDirection>()?,
+            rank_type,
+        })
+    }
+}
+
+/// Divides each measure by `measure` from another cube, at the same drilldown
+/// granularity, then multiplies by `scale` (e.g. 100000 for "per 100k").
+///
+/// Format: `cube.measure[,scale]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizeQuery {
+    pub cube: String,
+    pub measure: Measure,
+    pub scale: Option<f64>,
+}
+
+impl NormalizeQuery {
+    pub fn new(cube: String, measure: Measure, scale: Option<f64>) -> Self {
+        NormalizeQuery {
+            cube,
+            measure,
+            scale,
+        }
+    }
+}
+
+impl FromStr for NormalizeQuery {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalize_split: Vec<_> = s.splitn(2, ",").collect();
+
+        let scale = match normalize_split.get(1) {
+            Some(scale) => {
+                let scale = scale.parse::<f64>()?;
+                if !scale.is_finite() || scale == 0.0 {
+                    bail!("Normalize scale must be a non-zero number");
+                }
+                Some(scale)
+            },
+            None => None,
+        };
+
+        let cube_measure: Vec<_> = normalize_split[0].splitn(2, ".").collect();
+
+        if cube_measure.len() != 2 || cube_measure[0].is_empty() || cube_measure[1].is_empty() {
+            bail!("Could not parse a normalize query, must be `cube.measure[,scale]`");
+        }
+
+        Ok(NormalizeQuery {
+            cube: cube_measure[0].to_owned(),
+            measure: Measure::new(cube_measure[1]),
+            scale,
+        })
+    }
+}
+
+/// Standard rank leaves gaps after ties (1, 1, 3); dense rank does not (1, 1, 2).
+#[derive(Debug, Clone, PartialEq)]
+pub enum RankType {
+    Standard,
+    Dense,
+}
+
+impl FromStr for RankType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "standard" => RankType::Standard,
+            "dense" => RankType::Dense,
+            _ => bail!("Could not parse rank type, must be 'standard' or 'dense'"),
+        })
+    }
+}
+
+/// Groups by buckets of a numeric level key or property instead of by member.
+/// Buckets are returned like a drilldown, with a bucket id and a generated
+/// label (e.g. `1000-4999`). Cuts on a binned level or property are on the
+/// bucket labels.
+///
+/// Format: `name,bin_type,args...`, where name is a level or property, e.g.
+/// - `Geography.Municipality.Municipality.Population,width,1000`
+/// - `Geography.Municipality.Municipality.Population,breaks,0,1000,5000`
+/// - `Geography.Municipality.Municipality.Population,quantiles,4`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinQuery {
+    pub level_name: LevelName,
+    // if property, bins the property column instead of the level key
+    pub property: Option<String>,
+    pub bin_type: BinType,
+}
+
+impl BinQuery {
+    pub fn new(level_name: LevelName, property: Option<String>, bin_type: BinType) -> Self {
+        BinQuery {
+            level_name,
+            property,
+            bin_type,
+        }
+    }
+
+    /// Name of the binned level or property, used for headers.
+    pub fn name(&self) -> String {
+        self.property.clone().unwrap_or(self.level_name.level.clone())
+    }
+
+    /// Whether a cut is on the bucket labels of this bin. Range and match
+    /// cuts are on the underlying values instead.
+    pub fn is_cut_on(&self, cut: &Cut) -> bool {
+        cut.level_name == self.level_name &&
+            cut.property == self.property &&
+            cut.range.is_none() &&
+            !cut.for_match
+    }
+}
+
+impl FromStr for BinQuery {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bin_split: Vec<_> = s.splitn(2, ",").collect();
+
+        if bin_split.len() != 2 {
+            bail!("Could not parse a bin query, must be `name,bin_type,args`");
+        }
+
+        let (level_name, property) = match bin_split[0].parse::<ColumnKey>()? {
+            ColumnKey::LevelId(level_name) => (level_name, None),
+            ColumnKey::Property(property) => (property.level_name, Some(property.property)),
+            _ => bail!("Bins must be on a level or a property"),
+        };
+
+        Ok(BinQuery {
+            level_name,
+            property,
+            bin_type: bin_split[1].parse::<BinType>()?,
+        })
+    }
+}
+
+/// - Width: equal-width buckets, starting from 0
+/// - Breaks: buckets between ascending breakpoints, plus one below the first
+/// and one above the last
+/// - Quantiles: n buckets with (about) the same number of members each
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinType {
+    Width(i64),
+    Breaks(Vec<i64>),
+    Quantiles(u32),
+}
+
+impl BinType {
+    /// Labels for the buckets below the first break, between each break, and
+    /// above the last break, e.g. `<0`, `0-999`, `1000-4999`, `5000+`.
+    pub fn break_labels(breaks: &[i64]) -> Vec<String> {
+        let mut labels = vec![format!("<{}", breaks[0])];
+
+        for pair in breaks.windows(2) {
+            labels.push(format!("{}-{}", pair[0], pair[1] - 1));
+        }
+
+        labels.push(format!("{}+", breaks[breaks.len() - 1]));
+
+        labels
+    }
+
+    /// Whether a label could be generated for one of the buckets. Cuts on a
+    /// bin are on labels, which can't be checked against cached members.
+    pub fn is_label(&self, label: &str) -> bool {
+        match self {
+            BinType::Width(width) => {
+                match label_bounds::<i64>(label) {
+                    Some((start, end)) => start % width == 0 && end == start + width - 1,
+                    None => false,
+                }
+            },
+            BinType::Breaks(breaks) => BinType::break_labels(breaks).iter().any(|l| l == label),
+            // quantile edges depend on the data
+            BinType::Quantiles(_) => label_bounds::<f64>(label).is_some(),
+        }
+    }
+}
+
+/// Start and end of a `start-end` bucket label; either can be negative.
+fn label_bounds<T: FromStr>(label: &str) -> Option<(T, T)> {
+    label.char_indices()
+        .filter(|&(idx, c)| c == '-' && idx > 0)
+        .filter_map(|(idx, _)| {
+            match (label[..idx].parse::<T>(), label[idx+1..].parse::<T>()) {
+                (Ok(start), Ok(end)) => Some((start, end)),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+impl FromStr for BinType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bin_split: Vec<_> = s.split(",").collect();
+
+        match bin_split[0] {
+            "width" if bin_split.len() == 2 => {
+                let width = bin_split[1].parse::<i64>()?;
+                if width <= 0 {
+                    bail!("Bin width must be positive");
+                }
+                Ok(BinType::Width(width))
+            },
+            "breaks" if bin_split.len() > 1 => {
+                let breaks = bin_split[1..].iter()
+                    .map(|b| b.parse::<i64>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                if breaks.windows(2).any(|w| w[0] >= w[1]) {
+                    bail!("Bin breaks must be ascending");
+                }
+                Ok(BinType::Breaks(breaks))
+            },
+            "quantiles" if bin_split.len() == 2 => {
+                let n = bin_split[1].parse::<u32>()?;
+                if n < 2 {
+                    bail!("Quantile bins need at least 2 buckets");
+                }
+                Ok(BinType::Quantiles(n))
+            },
+            _ => bail!("Could not parse bin type, must be `width,n`, `breaks,b1,b2...` or `quantiles,n`"),
+        }
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -565,6 +1086,12 @@ mod tests {
     use super::Measure;
     use crate::query::MeaOrCalc;
     use crate::query::{Constraint, Comparison};
+    use crate::query::{RankQuery, RankType, SortDirection};
+    use crate::query::{SortQuery, ColumnKey, TopQuery};
+    use crate::query::{FilterExpr, FilterOp};
+    use crate::query::{BinQuery, BinType};
+    use crate::query::NormalizeQuery;
+    use crate::names::{LevelName, Property};
     use std::str::FromStr;
 
     #[test]
@@ -600,4 +1127,167 @@ mod tests {
         };
         assert_eq!(filter, target);
     }
+
+    #[test]
+    fn test_rank() {
+        let rank = RankQuery::from_str("Geography.Geography.State,Wages,desc").unwrap();
+        assert_eq!(rank.level_name.level, "State");
+        assert_eq!(rank.mea_or_calc, MeaOrCalc::Mea(Measure("Wages".to_owned())));
+        assert_eq!(rank.direction, SortDirection::Desc);
+        assert_eq!(rank.rank_type, RankType::Standard);
+
+        let rank = RankQuery::from_str("Geography.Geography.State,Wages,asc,dense").unwrap();
+        assert_eq!(rank.rank_type, RankType::Dense);
+
+        assert!(RankQuery::from_str("Geography.Geography.State,Wages").is_err());
+        assert!(RankQuery::from_str("Geography.Geography.State,Wages,asc,olympic").is_err());
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let sort = SortQuery::from_str("Exports.asc").unwrap();
+        assert_eq!(sort.key, ColumnKey::MeaOrCalc(MeaOrCalc::Mea(Measure("Exports".to_owned()))));
+        assert_eq!(sort.direction, SortDirection::Asc);
+
+        let sort = SortQuery::from_str("[Year].[Year].[Year].desc").unwrap();
+        assert_eq!(sort.key, ColumnKey::LevelId(LevelName::new("Year", "Year", "Year")));
+        assert_eq!(sort.direction, SortDirection::Desc);
+
+        let sort = SortQuery::from_str("Geography.State.label.asc").unwrap();
+        assert_eq!(sort.key, ColumnKey::LevelLabel(LevelName::new("Geography", "Geography", "State")));
+
+        let sort = SortQuery::from_str("[Geography].[Geography].[State].[Region].asc").unwrap();
+        assert_eq!(sort.key, ColumnKey::Property(Property::new("Geography", "Geography", "State", "Region")));
+
+        assert!(SortQuery::from_str("Exports").is_err());
+    }
+
+    #[test]
+    fn test_top_sort_keys() {
+        let top = TopQuery::from_str("10,Year.Year,Exports,desc").unwrap();
+        assert_eq!(top.sort.len(), 1);
+        assert_eq!(top.sort[0].direction, SortDirection::Desc);
+
+        let top = TopQuery::from_str("10,Year.Year,Exports.desc,Geography.State.label.asc").unwrap();
+        assert_eq!(top.sort.len(), 2);
+        assert_eq!(top.sort[1].key, ColumnKey::LevelLabel(LevelName::new("Geography", "Geography", "State")));
+        assert_eq!(top.sort[1].direction, SortDirection::Asc);
+    }
+
+    #[test]
+    fn test_filter_expr() {
+        let filter = FilterExpr::from_str("Exports.gt.100.5").unwrap();
+        assert_eq!(
+            filter,
+            FilterExpr::Condition(
+                ColumnKey::MeaOrCalc(MeaOrCalc::Mea(Measure("Exports".to_owned()))),
+                FilterOp::Compare(Constraint { comparison: Comparison::GreaterThan, n: 100.5 }),
+            )
+        );
+
+        let filter = FilterExpr::from_str("and(or(Exports.gt.100,[Geography].[Geography].[State].[Region].in.South|West),not(Geography.State.label.startswith.New))").unwrap();
+        let target = FilterExpr::And(vec![
+            FilterExpr::Or(vec![
+                FilterExpr::Condition(
+                    ColumnKey::MeaOrCalc(MeaOrCalc::Mea(Measure("Exports".to_owned()))),
+                    FilterOp::Compare(Constraint { comparison: Comparison::GreaterThan, n: 100.0 }),
+                ),
+                FilterExpr::Condition(
+                    ColumnKey::Property(Property::new("Geography", "Geography", "State", "Region")),
+                    FilterOp::In(vec!["South".to_owned(), "West".to_owned()]),
+                ),
+            ]),
+            FilterExpr::Not(Box::new(
+                FilterExpr::Condition(
+                    ColumnKey::LevelLabel(LevelName::new("Geography", "Geography", "State")),
+                    FilterOp::StartsWith("New".to_owned()),
+                ),
+            )),
+        ]);
+        assert_eq!(filter, target);
+
+        let filter = FilterExpr::from_str("Geography.State.label.isnotnull").unwrap();
+        assert_eq!(
+            filter,
+            FilterExpr::Condition(
+                ColumnKey::LevelLabel(LevelName::new("Geography", "Geography", "State")),
+                FilterOp::IsNotNull,
+            )
+        );
+
+        assert!(FilterExpr::from_str("and(Exports.gt.100").is_err());
+        assert!(FilterExpr::from_str("not(Exports.gt.1,Exports.lt.5)").is_err());
+        assert!(FilterExpr::from_str("Exports.isnull.5").is_err());
+    }
+
+    #[test]
+    fn test_filter_op_sql() {
+        assert_eq!(FilterOp::Contains("50%_o'k".to_owned()).sql_string("col"), "col like '%50\\%\\_o\\'k%'");
+        assert_eq!(FilterOp::In(vec!["a".to_owned(), "b".to_owned()]).sql_string("col"), "col in ('a', 'b')");
+        assert_eq!(FilterOp::IsNull.sql_string("col"), "col is null");
+    }
+
+    #[test]
+    fn test_bin() {
+        let bin = BinQuery::from_str("Geography.Municipality.Municipality.Population,breaks,0,1000,5000").unwrap();
+        assert_eq!(
+            bin,
+            BinQuery::new(
+                LevelName::new("Geography", "Municipality", "Municipality"),
+                Some("Population".to_owned()),
+                BinType::Breaks(vec![0, 1000, 5000]),
+            )
+        );
+        assert_eq!(bin.name(), "Population");
+
+        let bin = BinQuery::from_str("[Geography].[Municipality],width,1000").unwrap();
+        assert_eq!(bin.level_name, LevelName::new("Geography", "Geography", "Municipality"));
+        assert_eq!(bin.property, None);
+        assert_eq!(bin.bin_type, BinType::Width(1000));
+        assert_eq!(bin.name(), "Municipality");
+
+        assert_eq!(BinType::from_str("quantiles,4").unwrap(), BinType::Quantiles(4));
+
+        assert!(BinType::from_str("breaks").is_err());
+        assert!(BinType::from_str("breaks,10,5").is_err());
+        assert!(BinType::from_str("width,0").is_err());
+        assert!(BinQuery::from_str("Population,width,10").is_err());
+    }
+
+    #[test]
+    fn test_bin_labels() {
+        let breaks = BinType::Breaks(vec![0, 1000, 5000]);
+        assert_eq!(BinType::break_labels(&[0, 1000, 5000]), vec!["<0", "0-999", "1000-4999", "5000+"]);
+        assert!(breaks.is_label("1000-4999"));
+        assert!(!breaks.is_label("1000-5000"));
+
+        let width = BinType::Width(1000);
+        assert!(width.is_label("2000-2999"));
+        assert!(width.is_label("-1000--1"));
+        assert!(!width.is_label("2500-3499"));
+
+        let quantiles = BinType::Quantiles(4);
+        assert!(quantiles.is_label("1.5-2000"));
+        assert!(!quantiles.is_label("1.5"));
+
+        for bin_type in &[breaks, width, quantiles] {
+            assert!(!bin_type.is_label("0-999'; drop table sales; --"));
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            NormalizeQuery::from_str("population.Population,100000").unwrap(),
+            NormalizeQuery::new("population".into(), Measure::new("Population"), Some(100000.0)),
+        );
+        assert_eq!(
+            NormalizeQuery::from_str("acs_population.Population.Total").unwrap(),
+            NormalizeQuery::new("acs_population".into(), Measure::new("Population.Total"), None),
+        );
+
+        assert!(NormalizeQuery::from_str("Population").is_err());
+        assert!(NormalizeQuery::from_str("population.Population,0").is_err());
+        assert!(NormalizeQuery::from_str("population.Population,lots").is_err());
+    }
 }
@@ -1,9 +1,11 @@
+use failure::{Error, format_err};
 use itertools::join;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::names::Mask;
-use crate::query::{LimitQuery, SortDirection, Constraint, Operator};
-use crate::schema::{Table, InlineTable};
+use crate::dataframe::DataFrame;
+use crate::names::{CutRange, Mask};
+use crate::query::{LimitQuery, SortDirection, Constraint, Operator, RankType, FilterOp, BinType};
+use crate::schema::{Table, InlineTable, TimePart, Closure};
 use crate::schema::aggregator::Aggregator;
 
 
@@ -15,15 +17,72 @@ pub struct QueryIr {
     pub meas: Vec<MeasureSql>,
     pub hidden_drills: Vec<HiddenDrilldownSql>,
     pub filters: Vec<FilterSql>,
+    pub filter_exprs: Vec<FilterExprSql>,
     // TODO put Filters and Calculations into own structs
     pub top: Option<TopSql>,
     pub top_where: Option<TopWhereSql>,
-    pub sort: Option<SortSql>,
+    pub sort: Vec<SortSql>,
     pub limit: Option<LimitSql>,
     pub rca: Option<RcaSql>,
     pub growth: Option<GrowthSql>,
     pub rate: Option<RateSql>,
+    pub rank: Option<RankSql>,
+    pub totals: Option<TotalsSql>,
     pub sparse: bool,
+    pub dense: Option<DenseSql>,
+    pub nonempty: bool,
+    pub distinct: bool,
+}
+
+/// A query on several cubes, one `QueryIr` per cube. The results are
+/// combined by `join`.
+#[derive(Debug)]
+pub struct MultiCubeQueryIr {
+    pub query_irs: Vec<QueryIr>,
+    pub join: MultiCubeJoin,
+}
+
+/// Joins the results of a multi-cube query on their first `num_key_cols`
+/// columns, which are the drilldowns.
+///
+/// If normalizing, the last result is the denominator measure, which divides
+/// all the other measures and is then multiplied by the scale.
+///
+/// The measures of each result follow each other, so they are put back in
+/// the requested order by `measure_order`, the index among the joined
+/// measures of each requested measure.
+#[derive(Debug, Clone)]
+pub struct MultiCubeJoin {
+    pub num_key_cols: usize,
+    pub normalize_scale: Option<f64>,
+    pub measure_order: Option<Vec<usize>>,
+}
+
+impl MultiCubeJoin {
+    pub fn join(&self, dfs: Vec<DataFrame>) -> Result<DataFrame, Error> {
+        let mut df = DataFrame::inner_join(dfs, self.num_key_cols)?;
+
+        if let Some(scale) = self.normalize_scale {
+            df = df.normalize(self.num_key_cols, scale)?;
+        }
+
+        if let Some(ref measure_order) = self.measure_order {
+            let mut measure_cols: Vec<_> = df.columns.split_off(self.num_key_cols)
+                .into_iter()
+                .map(Some)
+                .collect();
+
+            for &idx in measure_order {
+                let col = measure_cols.get_mut(idx)
+                    .and_then(|col| col.take())
+                    .ok_or_else(|| format_err!("Joined results have no measure column {}", idx))?;
+
+                df.columns.push(col);
+            }
+        }
+
+        Ok(df)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -41,9 +100,30 @@ pub struct DrilldownSql {
     pub level_columns: Vec<LevelColumn>,
     pub property_columns: Vec<String>,
     pub inline_table: Option<InlineTable>,
+    // if bin, the level column is generated from buckets of the bin column
+    pub bin: Option<BinSql>,
+    // if virtual time levels, the level columns are generated from a date column
+    pub time: Option<TimeSql>,
+    // if parent-child hierarchy, the table is derived from the hierarchy table
+    pub parent_child: Option<ParentChildSql>,
+    // if snowflake hierarchy, the table is derived from the level tables
+    pub snowflake_sql: Option<String>,
 }
 
 impl DrilldownSql {
+    /// Table (or derived table) that the drilldown columns are selected from.
+    pub fn table_sql(&self) -> String {
+        if let Some(ref it) = self.inline_table {
+            format!("({}) as {}", it.sql_string(), it.alias)
+        } else if let Some(ref parent_child) = self.parent_child {
+            format!("({}) as {}", parent_child.sql(), self.table.name)
+        } else if let Some(ref snowflake_sql) = self.snowflake_sql {
+            format!("({}) as {}", snowflake_sql, self.table.name)
+        } else {
+            self.table.full_name()
+        }
+    }
+
     pub fn col_alias_string(&self) -> String {
         let cols = self.col_alias_vec();
         join(cols, ", ")
@@ -118,6 +198,22 @@ impl DrilldownSql {
         cols
     }
 
+    /// For virtual time drilldowns, selects each level key as the backend's
+    /// `part_sql` expression over the date column, with the usual alias.
+    pub fn time_col_alias_string(&self, time: &TimeSql, part_sql: fn(&str, &TimePart, Option<u32>) -> String) -> String {
+        let cols = self.level_columns.iter()
+            .zip(time.parts.iter())
+            .map(|(l, part)| {
+                format!("{} as {}_{}",
+                    part_sql(&time.column, part, time.fiscal_year_start_month),
+                    l.key_column,
+                    self.alias_postfix,
+                )
+            });
+
+        join(cols, ", ")
+    }
+
     pub fn col_qual_string(&self) -> String {
         let cols = self.col_qual_vec();
         join(cols, ", ")
@@ -148,6 +244,181 @@ impl DrilldownSql {
     }
 }
 
+/// Buckets for a bin drilldown. The backend generates the drilldown's
+/// level column (bucket id and label) from `column`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinSql {
+    pub column: String,
+    pub bin_type: BinType,
+    // cut on bucket labels; no cut if empty
+    pub members: Vec<String>,
+    pub mask: Mask,
+}
+
+/// Virtual time levels of a drilldown, generated by the backend from a date
+/// column of the fact table. There's one time part for each level column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSql {
+    pub column: String,
+    pub parts: Vec<TimePart>,
+    pub fiscal_year_start_month: Option<u32>,
+}
+
+/// Column of the parent-child derived table that the fact table joins on.
+pub const DESCENDANT_KEY: &str = "descendant_key";
+
+/// Column of the parent-child derived table with the ancestor of each member
+/// at a distance, where the parent is at distance 1.
+pub fn ancestor_column(distance: u32) -> String {
+    format!("ancestor_{}", distance)
+}
+
+/// Derived table for a parent-child hierarchy, which pairs each member with
+/// itself and each of its descendants. Joining the fact table on the
+/// descendant key aggregates all descendants into each member.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentChildSql {
+    pub table: Table,
+    pub primary_key: String,
+    pub parent_column: String,
+    // member columns that are selected from the derived table
+    pub columns: Vec<String>,
+    pub closure: Option<Closure>,
+    pub max_depth: u32,
+    // number of ancestors of each member that are selected, nearest first
+    pub ancestors: u32,
+}
+
+impl ParentChildSql {
+    pub fn sql(&self) -> String {
+        let member_table = self.member_table_sql();
+
+        match self.closure {
+            Some(ref closure) => {
+                format!("select closure.{} as {}, {} from {} as closure inner join {} as ancestor on ancestor.{} = closure.{}",
+                    closure.child_column,
+                    DESCENDANT_KEY,
+                    self.columns_sql("ancestor"),
+                    closure.table.full_name(),
+                    member_table,
+                    self.primary_key,
+                    closure.parent_column,
+                )
+            },
+            None => {
+                // Without a closure table, the descendants at each depth are
+                // found by joining the table to itself up to the max depth.
+                // The last table joined is the member.
+                let table_at = |i, depth| {
+                    if i == depth { member_table.clone() } else { self.table.full_name() }
+                };
+
+                let depth_sqls = (0..=self.max_depth)
+                    .map(|depth| {
+                        let joins: String = (1..=depth)
+                            .map(|i| {
+                                format!(" inner join {} as t{} on t{}.{} = t{}.{}",
+                                    table_at(i, depth),
+                                    i,
+                                    i,
+                                    self.primary_key,
+                                    i - 1,
+                                    self.parent_column,
+                                )
+                            })
+                            .collect();
+
+                        format!("select t0.{} as {}, {} from {} as t0{}",
+                            self.primary_key,
+                            DESCENDANT_KEY,
+                            self.columns_sql(&format!("t{}", depth)),
+                            table_at(0, depth),
+                            joins,
+                        )
+                    });
+
+                join(depth_sqls, " union all ")
+            },
+        }
+    }
+
+    /// Table of members, with their ancestors if any are selected. Ancestors
+    /// are ordered by depth through the closure table if there is one, which
+    /// is the number of rows of each member in the closure table.
+    fn member_table_sql(&self) -> String {
+        if self.ancestors == 0 {
+            return self.table.full_name();
+        }
+
+        match self.closure {
+            Some(ref closure) => {
+                let depth_sql = format!("(select {} as member_key, count(*) as depth from {} group by {})",
+                    closure.child_column,
+                    closure.table.full_name(),
+                    closure.child_column,
+                );
+                let path_cols = (1..=self.ancestors)
+                    .map(|distance| {
+                        format!("max(case when member_depth.depth - ancestor_depth.depth = {} then closure.{} end) as {}",
+                            distance,
+                            closure.parent_column,
+                            ancestor_column(distance),
+                        )
+                    });
+                let path_sql = format!("select closure.{} as member_key, {} from {} as closure \
+                    inner join {} as member_depth on member_depth.member_key = closure.{} \
+                    inner join {} as ancestor_depth on ancestor_depth.member_key = closure.{} \
+                    group by closure.{}",
+                    closure.child_column,
+                    join(path_cols, ", "),
+                    closure.table.full_name(),
+                    depth_sql,
+                    closure.child_column,
+                    depth_sql,
+                    closure.parent_column,
+                    closure.child_column,
+                );
+
+                format!("(select m.*, {} from {} as m left join ({}) as path on path.member_key = m.{})",
+                    join((1..=self.ancestors).map(|distance| format!("path.{}", ancestor_column(distance))), ", "),
+                    self.table.full_name(),
+                    path_sql,
+                    self.primary_key,
+                )
+            },
+            None => {
+                let joins: String = (1..=self.ancestors)
+                    .map(|distance| {
+                        let child = if distance == 1 { "m".to_owned() } else { format!("a{}", distance - 1) };
+                        format!(" left join {} as a{} on a{}.{} = {}.{}",
+                            self.table.full_name(),
+                            distance,
+                            distance,
+                            self.primary_key,
+                            child,
+                            self.parent_column,
+                        )
+                    })
+                    .collect();
+
+                format!("(select m.*, {} from {} as m{})",
+                    join((1..=self.ancestors).map(|distance| format!("a{}.{} as {}", distance, self.primary_key, ancestor_column(distance))), ", "),
+                    self.table.full_name(),
+                    joins,
+                )
+            },
+        }
+    }
+
+    fn columns_sql(&self, ancestor_alias: &str) -> String {
+        let columns = self.columns.iter()
+            .cloned()
+            .chain((1..=self.ancestors).map(ancestor_column));
+
+        join(columns.map(|col| format!("{}.{} as {}", ancestor_alias, col, col)), ", ")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HiddenDrilldownSql {
     pub drilldown_sql: DrilldownSql,
@@ -174,15 +445,39 @@ pub struct CutSql {
     // if for_match, then use LIKE syntax
     pub for_match: bool,
     pub inline_table: Option<InlineTable>,
+    // if range, then members are empty and use comparison syntax
+    pub range: Option<CutRange>,
+    // if virtual time level, the cut is on this part of the date column
+    pub time_part: Option<TimePart>,
+    pub fiscal_year_start_month: Option<u32>,
+    // if parent-child hierarchy, the cut includes all descendants of the members
+    pub parent_child: Option<ParentChildSql>,
+    // if snowflake hierarchy, the table is derived from the level tables
+    pub snowflake_sql: Option<String>,
 }
 
 impl CutSql {
-    pub fn members_string(&self) -> String {
+    /// Table (or derived table) that the cut column is selected from.
+    pub fn table_sql(&self) -> String {
+        if let Some(ref it) = self.inline_table {
+            format!("({}) as {}", it.sql_string(), self.table.full_name())
+        } else if let Some(ref parent_child) = self.parent_child {
+            format!("({}) as {}", parent_child.sql(), self.table.name)
+        } else if let Some(ref snowflake_sql) = self.snowflake_sql {
+            format!("({}) as {}", snowflake_sql, self.table.name)
+        } else {
+            self.table.full_name()
+        }
+    }
+
+    /// Text members are quoted with `quote`, which depends on the backend;
+    /// see `quote_text` and `quote_standard_text`.
+    pub fn members_string(&self, quote: &dyn Fn(&str) -> String) -> String {
         let members = match self.member_type {
             MemberType::NonText => join(&self.members, ", "),
             MemberType::Text => {
                 let quoted = self.members.iter()
-                .map(|m| format!("'{}'", m));
+                .map(|m| quote(m));
                 join(quoted, ", ")
             }
         };
@@ -190,7 +485,33 @@ impl CutSql {
         format!("{}", members)
     }
 
-    pub fn members_like_string(&self) -> String {
+    /// For range cuts: `col between start and end`, or `>=`/`<=` for
+    /// open-ended ranges. Exclude mask negates the range.
+    pub fn range_string(&self, column: &str, range: &CutRange, quote: &dyn Fn(&str) -> String) -> String {
+        let member_sql = |m: &str| {
+            match self.member_type {
+                MemberType::NonText => m.to_owned(),
+                MemberType::Text => quote(m),
+            }
+        };
+
+        let range_sql = match range {
+            CutRange::Between(start, end) => {
+                format!("{} between {} and {}", column, member_sql(start), member_sql(end))
+            },
+            CutRange::From(start) => format!("{} >= {}", column, member_sql(start)),
+            CutRange::To(end) => format!("{} <= {}", column, member_sql(end)),
+        };
+
+        match self.mask {
+            Mask::Include => range_sql,
+            Mask::Exclude => format!("not ({})", range_sql),
+        }
+    }
+
+    /// Members are matched anywhere in the column. Wildcards and backslashes
+    /// in a member are matched literally.
+    pub fn members_like_string(&self, quote: &dyn Fn(&str) -> String) -> String {
         match self.member_type {
             MemberType::NonText => {
                 // this behavior doesn't really make sense; it should be for
@@ -205,7 +526,7 @@ impl CutSql {
             },
             MemberType::Text => {
                 let quoted = self.members.iter()
-                    .map(|m| format!("{} {} '%{}%'", self.column, self.mask_sql_like_string(), m));
+                    .map(|m| format!("{} {} {}", self.column, self.mask_sql_like_string(), quote(&format!("%{}%", like_pattern_text(m)))));
 
                 match self.mask {
                     Mask::Include => format!("({})", join(quoted, " or ")),
@@ -234,6 +555,26 @@ impl CutSql {
     }
 }
 
+/// A text value as a SQL string literal, for MySQL and ClickHouse. Quotes are
+/// doubled, and backslashes are doubled too, since they escape the next
+/// character in a string literal.
+pub fn quote_text(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+/// A text value as a standard SQL string literal, where only quotes are
+/// doubled. Postgres reads backslashes literally, since `standard_conforming_strings`
+/// is on by default.
+pub fn quote_standard_text(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Escapes the wildcards of a `like` pattern, and backslash, which is its
+/// escape character on all backends.
+fn like_pattern_text(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum MemberType {
     #[serde(rename="text")]
@@ -260,8 +601,9 @@ pub struct MeasureSql {
 pub struct TopSql {
     pub n: u64,
     pub by_column: String,
-    pub sort_columns: Vec<String>,
-    pub sort_direction: SortDirection,
+    pub sort: Vec<SortSql>,
+    // if other, members below the top n are aggregated into an "Other" row
+    pub other: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -280,6 +622,34 @@ pub struct FilterSql {
 }
 
 
+/// Filter expression tree, with column keys resolved to column aliases
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExprSql {
+    And(Vec<FilterExprSql>),
+    Or(Vec<FilterExprSql>),
+    Not(Box<FilterExprSql>),
+    Condition {
+        column: String,
+        op: FilterOp,
+    },
+}
+
+impl FilterExprSql {
+    pub fn sql_string(&self) -> String {
+        match self {
+            FilterExprSql::And(exprs) => {
+                format!("({})", join(exprs.iter().map(|e| e.sql_string()), " and "))
+            },
+            FilterExprSql::Or(exprs) => {
+                format!("({})", join(exprs.iter().map(|e| e.sql_string()), " or "))
+            },
+            FilterExprSql::Not(expr) => format!("not {}", expr.sql_string()),
+            FilterExprSql::Condition { column, op } => format!("({})", op.sql_string(column)),
+        }
+    }
+}
+
+
 #[derive(Debug, Clone)]
 pub struct LimitSql {
     pub offset: Option<u64>,
@@ -301,6 +671,12 @@ pub struct SortSql {
     pub column: String,
 }
 
+impl SortSql {
+    pub fn sql_string(&self) -> String {
+        format!("{} {}", self.column, self.direction.sql_string())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RcaSql {
     // level col for dim 1
@@ -323,6 +699,41 @@ pub struct RateSql {
     pub members: Vec<String>,
 }
 
+#[derive(Debug, Clone)]
+pub struct RankSql {
+    pub drill: DrilldownSql,
+    pub mea: String,
+    pub direction: SortDirection,
+    pub rank_type: RankType,
+}
+
+impl RankSql {
+    /// Alias of the key column of the ranked level, used to match
+    /// ranks back to rows.
+    pub fn key_col_alias(&self) -> String {
+        let key_column = self.drill.level_columns.last()
+            .map(|l| l.key_column.clone())
+            .unwrap_or_else(|| self.drill.primary_key.clone());
+
+        format!("{}_{}", key_column, self.drill.alias_postfix)
+    }
+}
+
+/// Drilldowns rolled up into subtotal rows, in rollup order: the first
+/// subtotal rolls up the last drilldown, and the grand total rolls up all
+/// of them. Other drilldowns are kept in every grouping.
+#[derive(Debug, Clone)]
+pub struct TotalsSql {
+    pub drills: Vec<DrilldownSql>,
+}
+
+/// Fills in every combination of drilldown members, with `fill` (or null)
+/// as the measure values of combinations that have no data.
+#[derive(Debug, Clone)]
+pub struct DenseSql {
+    pub fill: Option<f64>,
+}
+
 #[derive(Debug, Clone)]
 pub struct DimSubquery {
     pub sql: String,
@@ -338,14 +749,6 @@ pub struct DimSubquery {
 pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSubquery {
     match drill {
         Some(drill) => {
-            let drill_table = match &drill.inline_table {
-                Some(it) => {
-                    let inline_table_sql = it.sql_string();
-                    format!("({}) as {}", inline_table_sql, it.alias)
-                },
-                None => drill.table.full_name()
-            };
-
             // TODO
             // - oops, primary key is mandatory in schema, if not in
             // schema-config, then it takes the lowest level's key_column
@@ -359,7 +762,7 @@ pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSu
                 drill.col_alias_string(),
                 drill.primary_key.clone(),
                 drill.foreign_key.clone(),
-                drill_table,
+                drill.table_sql(),
             );
             // TODO can I delete this cut?
 //            if let Some(cut) = cut {
@@ -380,9 +783,9 @@ pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSu
                 let sql = format!("select {} as {} from {} where {} in ({})",
                     cut.primary_key.clone(),
                     cut.foreign_key.clone(),
-                    cut.table.full_name(),
+                    cut.table_sql(),
                     cut.column.clone(),
-                    cut.members_string(),
+                    cut.members_string(&quote_text),
                 );
 
                 return DimSubquery {
@@ -3,8 +3,14 @@ use std::convert::From;
 use failure::{Error, format_err};
 
 pub mod aggregator;
+pub mod database;
 pub mod metadata;
+pub mod skeleton;
+pub mod validation;
+pub mod write;
+mod include;
 mod json;
+mod mondrian;
 mod xml;
 
 const DEFAULT_LOCALE_STR: &str = "en";
@@ -20,15 +26,26 @@ pub use crate::schema::{
     json::PropertyConfigJson,
     json::AnnotationConfigJson,
     json::InlineTableJson,
+    json::ClosureConfigJson,
+    json::AggregateTableConfigJson,
+    json::AggregateLevelConfigJson,
+    json::AggregateMeasureConfigJson,
     json::InlineTableColumnDefinitionJson,
     json::InlineTableRowJson,
     json::InlineTableRowValueJson,
+    include::read_schema_config,
+    include::parse_schema_config,
+    mondrian::read_mondrian_schema,
     xml::SchemaConfigXML,
     xml::DimensionConfigXML,
     xml::HierarchyConfigXML,
     xml::LevelConfigXML,
     xml::MeasureConfigXML,
     xml::TableConfigXML,
+    xml::ClosureConfigXML,
+    xml::AggregateTableConfigXML,
+    xml::AggregateLevelConfigXML,
+    xml::AggregateMeasureConfigXML,
     xml::PropertyConfigXML,
 };
 use crate::names::{LevelName, Measure as MeasureName, Property as TsProperty};
@@ -40,16 +57,17 @@ use crate::DEFAULT_ALLOWED_ACCESS;
 pub struct Schema {
     pub name: String,
     pub cubes: Vec<Cube>,
+    // as defined, without a foreign key. Cubes have their own copies, from
+    // their dimension usages.
+    pub shared_dimensions: Vec<Dimension>,
     pub annotations: Option<Vec<Annotation>>,
     pub default_locale: String,
 }
 
 impl From<SchemaConfigJson> for Schema {
     fn from(schema_config: SchemaConfigJson) -> Self {
-        // TODO
-        // check for:
-        // - duplicate cube names,
-        // - duplicate dim names
+        // duplicate names and missing shared dimensions are checked in the
+        // validate method
 
         let mut cubes = vec![];
 
@@ -75,8 +93,18 @@ impl From<SchemaConfigJson> for Schema {
             // if optional name field present, use that for name.
             // validation that all dimensions have different names will happen
             // in validate method
+            let mut missing_shared_dimensions = vec![];
+
             if let Some(dim_usages) = cube_config.dimension_usages {
                 for dim_usage in dim_usages {
+                    // reported in validate method
+                    let has_source = schema_config.shared_dimensions.iter()
+                        .flat_map(|shared_dims| shared_dims.iter())
+                        .any(|shared_dim| shared_dim.name == dim_usage.source);
+                    if !has_source {
+                        missing_shared_dimensions.push(dim_usage.source.clone());
+                    }
+
                     // prep annotations to be merged with shared dim annotations
                     let dim_usage_annotations: Option<Vec<Annotation>> = dim_usage.annotations
                         .map(|anns| {
@@ -117,7 +145,8 @@ impl From<SchemaConfigJson> for Schema {
                                     default_hierarchy: shared_dim_config.default_hierarchy.clone(),
                                     dim_type,
                                     annotations: dim_annotations,
-                                    is_shared: true
+                                    is_shared: true,
+                                    location: None,
                                 });
                             }
                         }
@@ -133,18 +162,114 @@ impl From<SchemaConfigJson> for Schema {
 
             let min_auth_level = cube_config.min_auth_level.unwrap_or(DEFAULT_ALLOWED_ACCESS);
 
+            // smallest aggregate tables first, so the first one that can answer
+            // a query is used. Tables without a row count keep their order, last.
+            let mut aggregate_tables: Vec<AggregateTable> = cube_config.aggregate_tables
+                .unwrap_or(vec![])
+                .into_iter()
+                .map(|agg| agg.into())
+                .collect();
+            aggregate_tables.sort_by_key(|agg| agg.rows.unwrap_or(u64::max_value()));
+
             cubes.push(Cube {
                 name: cube_config.name,
                 public,
                 min_auth_level,
                 table: cube_config.table.into(),
-                can_aggregate: false,
+                can_aggregate: !aggregate_tables.is_empty(),
                 dimensions,
                 measures,
                 annotations: cube_annotations,
+                fiscal_year_start_month: cube_config.fiscal_year_start_month,
+                cube_usages: None,
+                aggregate_tables,
+                missing_shared_dimensions,
+                location: cube_config.location,
             });
         }
 
+        // Virtual cubes have the shared dimensions that are common to all of
+        // their cubes, and the measures of all of their cubes. Missing cubes
+        // are caught in validation.
+        for virtual_config in schema_config.virtual_cubes.unwrap_or(vec![]) {
+            let cube_usages: Vec<String> = virtual_config.cube_usages.into_iter()
+                .map(|usage| usage.cube_name)
+                .collect();
+
+            let virtual_cube = {
+                let usage_cubes: Vec<&Cube> = cube_usages.iter()
+                    .filter_map(|name| cubes.iter().find(|cube| &cube.name == name))
+                    .collect();
+
+                let dimensions = usage_cubes.first()
+                    .map(|first_cube| {
+                        first_cube.dimensions.iter()
+                            .filter(|dim| {
+                                dim.is_shared && usage_cubes[1..].iter().all(|cube| {
+                                    cube.dimensions.iter()
+                                        .any(|d| d.is_shared && d.name == dim.name && d.hierarchies == dim.hierarchies)
+                                })
+                            })
+                            .cloned()
+                            .collect()
+                    })
+                    .unwrap_or(vec![]);
+
+                let mut measures: Vec<Measure> = vec![];
+                for mea in usage_cubes.iter().flat_map(|cube| cube.measures.iter()) {
+                    if !measures.iter().any(|m| m.name == mea.name) {
+                        measures.push(mea.clone());
+                    }
+                }
+
+                let annotations = virtual_config.annotations
+                    .map(|anns| {
+                        anns.into_iter()
+                            .map(|ann| ann.into())
+                            .collect()
+                    });
+
+                let public = match virtual_config.public {
+                    Some(public) => public != "false",
+                    None => true
+                };
+
+                // by default, as restricted as the most restricted cube
+                let min_auth_level = virtual_config.min_auth_level
+                    .unwrap_or_else(|| {
+                        usage_cubes.iter()
+                            .map(|cube| cube.min_auth_level)
+                            .max()
+                            .unwrap_or(DEFAULT_ALLOWED_ACCESS)
+                    });
+
+                // the table of the first cube is only used for the members of
+                // dimensions without a dimension table
+                let table = match usage_cubes.first() {
+                    Some(cube) => cube.table.clone(),
+                    None => Table { name: virtual_config.name.clone(), schema: None, primary_key: None },
+                };
+
+                Cube {
+                    name: virtual_config.name,
+                    public,
+                    min_auth_level,
+                    table,
+                    can_aggregate: false,
+                    dimensions,
+                    measures,
+                    annotations,
+                    fiscal_year_start_month: usage_cubes.first().and_then(|cube| cube.fiscal_year_start_month),
+                    cube_usages: Some(cube_usages.clone()),
+                    aggregate_tables: vec![],
+                    missing_shared_dimensions: vec![],
+                    location: virtual_config.location,
+                }
+            };
+
+            cubes.push(virtual_cube);
+        }
+
         let schema_annotations = schema_config.annotations
             .map(|anns| {
                 anns.into_iter()
@@ -152,9 +277,28 @@ impl From<SchemaConfigJson> for Schema {
                     .collect()
             });
 
+        let shared_dimensions = schema_config.shared_dimensions
+            .unwrap_or(vec![])
+            .into_iter()
+            .map(|shared_dim_config| {
+                Dimension {
+                    name: shared_dim_config.name,
+                    foreign_key: None,
+                    hierarchies: shared_dim_config.hierarchies.into_iter().map(|h| h.into()).collect(),
+                    default_hierarchy: shared_dim_config.default_hierarchy,
+                    dim_type: shared_dim_config.dim_type.unwrap_or(DimensionType::default()),
+                    annotations: shared_dim_config.annotations
+                        .map(|anns| anns.into_iter().map(|ann| ann.into()).collect()),
+                    is_shared: true,
+                    location: shared_dim_config.location,
+                }
+            })
+            .collect();
+
         Schema {
             name: schema_config.name,
             cubes,
+            shared_dimensions,
             annotations: schema_annotations,
             default_locale: schema_config.default_locale.unwrap_or_else(|| DEFAULT_LOCALE_STR.to_owned()),
         }
@@ -173,6 +317,17 @@ pub struct Cube {
     pub dimensions: Vec<Dimension>,
     pub measures: Vec<Measure>,
     pub annotations: Option<Vec<Annotation>>,
+    /// first month (1-12) of the fiscal year, for fiscal virtual time levels
+    pub fiscal_year_start_month: Option<u32>,
+    /// for virtual cubes, the cubes whose measures are combined
+    pub cube_usages: Option<Vec<String>>,
+    /// pre-aggregated tables, smallest first
+    pub aggregate_tables: Vec<AggregateTable>,
+    /// sources of dimension usages that aren't shared dimensions, which
+    /// are left out of the dimensions
+    pub missing_shared_dimensions: Vec<String>,
+    /// file and line the cube is defined at, when read from files
+    pub location: Option<String>,
 }
 
 impl Cube {
@@ -349,6 +504,9 @@ pub struct Dimension {
     pub dim_type: DimensionType,
     pub annotations: Option<Vec<Annotation>>,
     pub is_shared: bool,
+    /// for shared dimensions, the file and line they're defined at, when
+    /// read from files
+    pub location: Option<String>,
 }
 
 impl From<DimensionConfigJson> for Dimension {
@@ -372,7 +530,8 @@ impl From<DimensionConfigJson> for Dimension {
             hierarchies,
             dim_type,
             annotations,
-            is_shared: false
+            is_shared: false,
+            location: None,
         }
     }
 }
@@ -401,11 +560,39 @@ pub struct Hierarchy {
     pub annotations: Option<Vec<Annotation>>,
     pub inline_table: Option<InlineTable>,
     pub default_member: Option<String>,
+    // parent-child hierarchy, where each member references its parent
+    pub parent_column: Option<String>,
+    pub closure: Option<Closure>,
+    pub max_depth: Option<u32>,
+    // snowflake hierarchy, whose level tables are joined into a derived
+    // table. Level columns refer to the columns of the derived table.
+    pub snowflake_sql: Option<String>,
+}
+
+impl Hierarchy {
+    pub fn hierarchy_type(&self) -> HierarchyType {
+        if self.parent_column.is_some() {
+            HierarchyType::ParentChild
+        } else {
+            HierarchyType::Level
+        }
+    }
+
+    /// Table that the level columns are selected from, which for snowflake
+    /// hierarchies is derived from the level tables.
+    pub fn table_sql(&self) -> Option<String> {
+        self.table.as_ref().map(|table| {
+            match self.snowflake_sql {
+                Some(ref snowflake_sql) => format!("({}) as {}", snowflake_sql, table.name),
+                None => table.full_name(),
+            }
+        })
+    }
 }
 
 impl From<HierarchyConfigJson> for Hierarchy {
     fn from(hierarchy_config: HierarchyConfigJson) -> Self {
-        let levels: Vec<Level> = hierarchy_config.levels.into_iter()
+        let mut levels: Vec<Level> = hierarchy_config.levels.into_iter()
             .map(|l| l.into())
             .collect();
 
@@ -425,18 +612,189 @@ impl From<HierarchyConfigJson> for Hierarchy {
                     .clone()
             });
 
+        let table: Option<Table> = hierarchy_config.table.map(|t| t.into());
+
+        let snowflake_sql = table.as_ref()
+            .and_then(|table| flatten_snowflake(table, &primary_key, &mut levels));
+
         Hierarchy {
             name: hierarchy_config.name,
-            table: hierarchy_config.table.map(|t| t.into()),
+            table,
             primary_key,
             levels,
             annotations,
             inline_table: hierarchy_config.inline_table.map(|t| t.into()),
-            default_member: hierarchy_config.default_member
+            default_member: hierarchy_config.default_member,
+            parent_column: hierarchy_config.parent_column,
+            closure: hierarchy_config.closure.map(|c| c.into()),
+            max_depth: hierarchy_config.max_depth,
+            snowflake_sql,
+        }
+    }
+}
+
+/// Joins the level tables of a snowflake hierarchy, starting from the
+/// hierarchy table and going up the levels, into a single derived table.
+/// A level without a table is in the same table as the level below it.
+///
+/// Columns from level tables are renamed to `{table}_{column}` in the derived
+/// table, so that e.g. the `name` columns of two tables don't collide. The
+/// level columns are updated to the derived table's columns.
+fn flatten_snowflake(table: &Table, primary_key: &str, levels: &mut [Level]) -> Option<String> {
+    if levels.iter().all(|level| level.table.is_none()) {
+        return None;
+    }
+
+    let mut columns = vec![format!("{}.{} as {}", table.name, primary_key, primary_key)];
+    let mut joins = vec![];
+    let mut below = table.name.clone();
+
+    for level in levels.iter_mut().rev() {
+        if let Some(ref level_table) = level.table {
+            let level_primary_key = level.primary_key.clone()
+                .unwrap_or_else(|| level.key_column.clone());
+            let level_foreign_key = level.foreign_key.clone()
+                .unwrap_or_else(|| level_primary_key.clone());
+
+            joins.push(format!("left join {} as {} on {}.{} = {}.{}",
+                level_table.full_name(),
+                level_table.name,
+                level_table.name,
+                level_primary_key,
+                below,
+                level_foreign_key,
+            ));
+            below = level_table.name.clone();
+        }
+
+        let mut rename = |column: &mut String| {
+            let alias = if below == table.name {
+                column.clone()
+            } else {
+                format!("{}_{}", below, column)
+            };
+
+            let column_sql = format!("{}.{} as {}", below, column, alias);
+            if !columns.contains(&column_sql) {
+                columns.push(column_sql);
+            }
+            *column = alias;
+        };
+
+        rename(&mut level.key_column);
+        if let Some(ref mut name_column) = level.name_column {
+            rename(name_column);
+        }
+        for property in level.properties.iter_mut().flat_map(|props| props.iter_mut()) {
+            rename(&mut property.column);
+        }
+    }
+
+    Some(format!("select {} from {} as {} {}",
+        columns.join(", "),
+        table.full_name(),
+        table.name,
+        joins.join(" "),
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HierarchyType {
+    #[serde(rename="level")]
+    Level,
+    #[serde(rename="parent_child")]
+    ParentChild,
+}
+
+/// Closure table of a parent-child hierarchy, with a row for every pair of
+/// a member and one of its ancestors, including the member itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Closure {
+    pub table: Table,
+    pub parent_column: String,
+    pub child_column: String,
+}
+
+impl From<ClosureConfigJson> for Closure {
+    fn from(closure_config: ClosureConfigJson) -> Self {
+        Closure {
+            table: closure_config.table.into(),
+            parent_column: closure_config.parent_column,
+            child_column: closure_config.child_column,
+        }
+    }
+}
+
+/// Pre-aggregated table of a cube, with a column for each of its levels and
+/// measures. Levels are stored in the aggregate table itself, so no dimension
+/// tables are joined.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateTable {
+    pub table: Table,
+    /// approximate number of rows, used to pick the smallest table
+    pub rows: Option<u64>,
+    pub levels: Vec<AggregateLevel>,
+    pub measures: Vec<AggregateMeasure>,
+}
+
+impl AggregateTable {
+    pub fn get_level(&self, level_name: &LevelName) -> Option<&AggregateLevel> {
+        self.levels.iter()
+            .find(|lvl| {
+                lvl.dimension == level_name.dimension &&
+                lvl.hierarchy == level_name.hierarchy &&
+                lvl.level == level_name.level
+            })
+    }
+
+    pub fn get_measure(&self, measure_name: &str) -> Option<&AggregateMeasure> {
+        self.measures.iter()
+            .find(|mea| mea.name == measure_name)
+    }
+}
+
+impl From<AggregateTableConfigJson> for AggregateTable {
+    fn from(agg_config: AggregateTableConfigJson) -> Self {
+        AggregateTable {
+            table: agg_config.table.into(),
+            rows: agg_config.rows,
+            levels: agg_config.levels.into_iter()
+                .map(|lvl| {
+                    AggregateLevel {
+                        dimension: lvl.dimension,
+                        hierarchy: lvl.hierarchy,
+                        level: lvl.level,
+                        key_column: lvl.key_column,
+                        name_column: lvl.name_column,
+                    }
+                })
+                .collect(),
+            measures: agg_config.measures.into_iter()
+                .map(|mea| {
+                    AggregateMeasure {
+                        name: mea.name,
+                        column: mea.column,
+                    }
+                })
+                .collect(),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateLevel {
+    pub dimension: String,
+    pub hierarchy: String,
+    pub level: String,
+    pub key_column: String,
+    pub name_column: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateMeasure {
+    pub name: String,
+    pub column: String,
+}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InlineTable {
@@ -560,6 +918,13 @@ pub struct Level {
     pub properties: Option<Vec<Property>>,
     pub key_type: Option<MemberType>,
     pub annotations: Option<Vec<Annotation>>,
+    // virtual time level, derived from the dimension's date column
+    pub time_part: Option<TimePart>,
+    // snowflaked level in its own table, joined on `primary_key` to the
+    // `foreign_key` column of the level below's table
+    pub table: Option<Table>,
+    pub primary_key: Option<String>,
+    pub foreign_key: Option<String>,
 }
 
 impl Level {
@@ -611,6 +976,55 @@ impl From<LevelConfigJson> for Level {
             properties,
             key_type: level_config.key_type,
             annotations,
+            time_part: level_config.time_part,
+            table: level_config.table.map(|t| t.into()),
+            primary_key: level_config.primary_key,
+            foreign_key: level_config.foreign_key,
+        }
+    }
+}
+
+/// The part of a date that a virtual time level is derived from. Time
+/// dimensions with virtual levels have no dimension table; the levels are
+/// generated by the backend from the date column that is the dimension's
+/// foreign key.
+///
+/// Members are integer ids, like the usual time dimension tables:
+/// `2019` (year), `20191` (quarter), `201901` (month), `201901` (ISO week,
+/// with the ISO year) and `20190115` (day).
+///
+/// Fiscal years start in the cube's `fiscal_year_start_month`, and are named
+/// after the calendar year they end in. ISO years are the years of ISO weeks,
+/// so that they can be the parent level of weeks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TimePart {
+    #[serde(rename="year")]
+    Year,
+    #[serde(rename="quarter")]
+    Quarter,
+    #[serde(rename="month")]
+    Month,
+    #[serde(rename="week")]
+    Week,
+    #[serde(rename="day")]
+    Day,
+    #[serde(rename="isoyear")]
+    IsoYear,
+    #[serde(rename="fiscal_year")]
+    FiscalYear,
+    #[serde(rename="fiscal_quarter")]
+    FiscalQuarter,
+}
+
+impl TimePart {
+    /// Months to add to a date so that its calendar year and quarter are the
+    /// fiscal ones. Only fiscal parts are shifted.
+    pub fn month_offset(&self, fiscal_year_start_month: Option<u32>) -> u32 {
+        match self {
+            TimePart::FiscalYear | TimePart::FiscalQuarter => {
+                (13 - fiscal_year_start_month.unwrap_or(1)) % 12
+            },
+            _ => 0,
         }
     }
 }
@@ -751,7 +1165,9 @@ mod test {
     fn test_dimension_usage() {
         let schema_config = SchemaConfigJson {
             default_locale: Some(DEFAULT_LOCALE_STR.into()),
+            includes: None,
             name: "test".into(),
+            virtual_cubes: None,
             shared_dimensions: Some(vec![
                 SharedDimensionConfigJson {
                     name: "geo".into(),
@@ -772,16 +1188,24 @@ mod test {
                                     properties: None,
                                     key_type: None,
                                     annotations: None,
+                                    time_part: None,
+                                    table: None,
+                                    primary_key: None,
+                                    foreign_key: None,
                                 },
                             ],
                             annotations: None,
                             inline_table: None,
                             default_member: None,
+                            parent_column: None,
+                            closure: None,
+                            max_depth: None,
                         },
                     ],
                     default_hierarchy: None,
                     annotations: None,
                     dim_type: None,
+                    location: None,
                 }
             ]),
             cubes: vec![
@@ -795,6 +1219,8 @@ mod test {
                         primary_key: None,
                     },
                     dimensions: Some(vec![]),
+                    fiscal_year_start_month: None,
+                    aggregate_tables: None,
                     dimension_usages: Some(vec![
                         DimensionUsageJson {
                             source: "geo".into(),
@@ -805,6 +1231,7 @@ mod test {
                     ]),
                     measures: vec![],
                     annotations: None,
+                    location: None,
                 }
             ],
             annotations: None,
@@ -0,0 +1,361 @@
+//! Checks a cube's tables and columns against the columns that the database
+//! has, which are queried with `Backend::table_columns_sql`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::dataframe::DataFrame;
+use crate::query_ir::MemberType;
+use super::{Aggregator, Cube, Table};
+use super::validation::SchemaDiagnostic;
+
+
+/// Columns of database tables by table full name, each with its database
+/// type by column name.
+pub type DbColumns = HashMap<String, HashMap<String, String>>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum ColumnUse {
+    Any,
+    Numeric,
+    /// level key, whose values are quoted in cuts only if it's text
+    Key(MemberType),
+}
+
+/// A column that the schema selects from a table.
+#[derive(Debug, Clone)]
+struct ColumnRef {
+    path: String,
+    table: Table,
+    column: String,
+    column_use: ColumnUse,
+}
+
+/// Tables that a cube's columns are selected from.
+pub fn cube_tables(cube: &Cube) -> Vec<Table> {
+    let mut tables: Vec<Table> = vec![];
+
+    for column_ref in cube_column_refs(cube) {
+        if !tables.iter().any(|table| table.full_name() == column_ref.table.full_name()) {
+            tables.push(column_ref.table);
+        }
+    }
+
+    tables
+}
+
+/// Missing tables and columns, and columns whose type doesn't match their
+/// use. A missing table is only reported once.
+pub fn cube_db_diagnostics(cube: &Cube, db_columns: &DbColumns) -> Vec<SchemaDiagnostic> {
+    let mut diagnostics = vec![];
+    let mut missing_tables = HashSet::new();
+
+    for column_ref in cube_column_refs(cube) {
+        let table_name = column_ref.table.full_name();
+
+        let table_columns = match db_columns.get(&table_name) {
+            Some(columns) if !columns.is_empty() => columns,
+            _ => {
+                if missing_tables.insert(table_name.clone()) {
+                    diagnostics.push(SchemaDiagnostic {
+                        location: cube.location.clone(),
+                        path: column_ref.path,
+                        message: format!("Table {} not found", table_name),
+                    });
+                }
+                continue;
+            },
+        };
+
+        let message = match table_columns.get(&column_ref.column) {
+            None => Some(format!("Column {} not found in table {}", column_ref.column, table_name)),
+            Some(db_type) => match column_ref.column_use {
+                ColumnUse::Numeric if !is_numeric_type(db_type) => {
+                    Some(format!("Column {} of table {} is {}, which is not numeric", column_ref.column, table_name, db_type))
+                },
+                ColumnUse::Key(MemberType::NonText) if is_text_type(db_type) => {
+                    Some(format!("Key column {} of table {} is {}, so the level needs key_type text", column_ref.column, table_name, db_type))
+                },
+                _ => None,
+            },
+        };
+
+        if let Some(message) = message {
+            diagnostics.push(SchemaDiagnostic {
+                location: cube.location.clone(),
+                path: column_ref.path,
+                message,
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Column names and types from the result of `Backend::table_columns_sql`.
+pub(crate) fn column_types(df: &DataFrame) -> Vec<(String, String)> {
+    match (df.columns.get(0), df.columns.get(1)) {
+        (Some(names), Some(types)) => {
+            names.stringify_column_data().into_iter()
+                .zip(types.stringify_column_data())
+                .collect()
+        },
+        _ => vec![],
+    }
+}
+
+fn cube_column_refs(cube: &Cube) -> Vec<ColumnRef> {
+    let cube_path = format!("cubes[{}]", cube.name);
+    let mut refs = vec![];
+
+    let mut push = |path: &str, table: &Table, column: &str, column_use: ColumnUse| {
+        refs.push(ColumnRef {
+            path: path.to_owned(),
+            table: table.clone(),
+            column: column.to_owned(),
+            column_use,
+        });
+    };
+
+    for mea in &cube.measures {
+        let path = format!("{}.measures[{}]", cube_path, mea.name);
+
+        let column_use = match mea.aggregator {
+            Aggregator::Count | Aggregator::Custom(_) => ColumnUse::Any,
+            _ => ColumnUse::Numeric,
+        };
+        push(&path, &cube.table, &mea.column, column_use);
+
+        for weight_column in weight_columns(&mea.aggregator) {
+            push(&path, &cube.table, weight_column, ColumnUse::Numeric);
+        }
+    }
+
+    for dim in &cube.dimensions {
+        let dim_path = format!("{}.dimensions[{}]", cube_path, dim.name);
+
+        if let Some(ref foreign_key) = dim.foreign_key {
+            push(&dim_path, &cube.table, foreign_key, ColumnUse::Any);
+        }
+
+        for hier in &dim.hierarchies {
+            let hier_path = format!("{}.hierarchies[{}]", dim_path, hier.name);
+
+            // inline tables aren't in the database, and virtual time levels
+            // are derived from the foreign key
+            if hier.inline_table.is_some() || hier.levels.iter().any(|level| level.time_part.is_some()) {
+                continue;
+            }
+
+            // no table means the levels are in the fact table
+            let hier_table = hier.table.as_ref().unwrap_or(&cube.table);
+
+            if hier.table.is_some() {
+                push(&hier_path, hier_table, &hier.primary_key, ColumnUse::Any);
+            }
+            if let Some(ref parent_column) = hier.parent_column {
+                push(&hier_path, hier_table, parent_column, ColumnUse::Any);
+            }
+            if let Some(ref closure) = hier.closure {
+                push(&hier_path, &closure.table, &closure.parent_column, ColumnUse::Any);
+                push(&hier_path, &closure.table, &closure.child_column, ColumnUse::Any);
+            }
+
+            // snowflake levels are in the table of the closest level table
+            // below them, and their columns are prefixed by its name
+            let mut table = hier_table;
+
+            for level in hier.levels.iter().rev() {
+                let level_path = format!("{}.levels[{}]", hier_path, level.name);
+
+                if let Some(ref level_table) = level.table {
+                    let level_primary_key = level.primary_key.clone()
+                        .unwrap_or_else(|| unprefixed(&level.key_column, level_table).to_owned());
+                    let level_foreign_key = level.foreign_key.clone()
+                        .unwrap_or_else(|| level_primary_key.clone());
+
+                    push(&level_path, table, &level_foreign_key, ColumnUse::Any);
+                    push(&level_path, level_table, &level_primary_key, ColumnUse::Any);
+                    table = level_table;
+                }
+
+                let column = |column: &str| {
+                    if table.name == hier_table.name {
+                        column.to_owned()
+                    } else {
+                        unprefixed(column, table).to_owned()
+                    }
+                };
+
+                let key_type = level.key_type.clone().unwrap_or(MemberType::NonText);
+                push(&level_path, table, &column(&level.key_column), ColumnUse::Key(key_type));
+
+                if let Some(ref name_column) = level.name_column {
+                    push(&level_path, table, &column(name_column), ColumnUse::Any);
+                }
+                for property in level.properties.iter().flat_map(|props| props.iter()) {
+                    push(&level_path, table, &column(&property.column), ColumnUse::Any);
+                }
+            }
+        }
+    }
+
+    for agg in &cube.aggregate_tables {
+        let agg_path = format!("{}.aggregate_tables[{}]", cube_path, agg.table.name);
+
+        for agg_level in &agg.levels {
+            let key_type = cube.dimensions.iter()
+                .find(|dim| dim.name == agg_level.dimension)
+                .and_then(|dim| dim.hierarchies.iter().find(|hier| hier.name == agg_level.hierarchy))
+                .and_then(|hier| hier.levels.iter().find(|level| level.name == agg_level.level))
+                .and_then(|level| level.key_type.clone())
+                .unwrap_or(MemberType::NonText);
+
+            push(&agg_path, &agg.table, &agg_level.key_column, ColumnUse::Key(key_type));
+            if let Some(ref name_column) = agg_level.name_column {
+                push(&agg_path, &agg.table, name_column, ColumnUse::Any);
+            }
+        }
+        for agg_mea in &agg.measures {
+            push(&agg_path, &agg.table, &agg_mea.column, ColumnUse::Numeric);
+        }
+    }
+
+    refs
+}
+
+/// Columns that an aggregator uses besides the measure column.
+fn weight_columns(aggregator: &Aggregator) -> Vec<&String> {
+    match aggregator {
+        Aggregator::WeightedSum { weight_column } => vec![weight_column],
+        Aggregator::WeightedAverage { weight_column } => vec![weight_column],
+        Aggregator::ReplicateWeightMoe { secondary_columns, .. } => secondary_columns.iter().collect(),
+        Aggregator::WeightedAverageMoe { primary_weight, secondary_weight_columns, .. } => {
+            let mut columns = vec![primary_weight];
+            columns.extend(secondary_weight_columns);
+            columns
+        },
+        _ => vec![],
+    }
+}
+
+/// Snowflake level columns are prefixed by their table name.
+fn unprefixed<'a>(column: &'a str, table: &Table) -> &'a str {
+    let prefix = format!("{}_", table.name);
+
+    if column.starts_with(&prefix) {
+        &column[prefix.len()..]
+    } else {
+        column
+    }
+}
+
+/// Database type without ClickHouse's `Nullable(..)` and `LowCardinality(..)`.
+fn base_type(db_type: &str) -> String {
+    let mut db_type = db_type.trim().to_lowercase();
+
+    while let Some(wrapper) = ["nullable(", "lowcardinality("].iter()
+        .find(|wrapper| db_type.starts_with(*wrapper) && db_type.ends_with(')'))
+    {
+        db_type = db_type[wrapper.len()..db_type.len() - 1].to_owned();
+    }
+
+    db_type
+}
+
+pub(crate) fn is_numeric_type(db_type: &str) -> bool {
+    let db_type = base_type(db_type);
+
+    let numeric_prefixes = ["int", "uint", "smallint", "bigint", "tinyint", "mediumint",
+        "float", "double", "decimal", "numeric", "real", "money", "serial", "smallserial", "bigserial"];
+
+    !db_type.starts_with("interval") &&
+        numeric_prefixes.iter().any(|prefix| db_type.starts_with(prefix))
+}
+
+pub(crate) fn is_text_type(db_type: &str) -> bool {
+    let db_type = base_type(db_type);
+
+    let text_prefixes = ["string", "fixedstring", "char", "varchar", "character", "text",
+        "tinytext", "mediumtext", "longtext", "enum"];
+
+    text_prefixes.iter().any(|prefix| db_type.starts_with(prefix))
+}
+
+pub(crate) fn is_date_type(db_type: &str) -> bool {
+    let db_type = base_type(db_type);
+
+    ["date", "datetime", "timestamp"].iter().any(|prefix| db_type.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Schema;
+
+    #[test]
+    fn test_db_diagnostics() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <Dimension name="Geography" foreign_key="state_id">
+                        <Hierarchy name="Geography" primary_key="state_id">
+                            <Table name="dim_geo" />
+                            <Level name="State" key_column="state_id" name_column="state_nam" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Dimension name="Product" foreign_key="product_id">
+                        <Hierarchy name="Product" primary_key="product_id">
+                            <Table name="dim_product" />
+                            <Level name="Product" key_column="product_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                    <Measure name="Orders" column="order_id" aggregator="count" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema = Schema::from_xml(s).unwrap();
+        let cube = &schema.cubes[0];
+
+        let table_names: Vec<_> = cube_tables(cube).iter().map(|table| table.full_name()).collect();
+        assert_eq!(table_names, vec!["sales_table", "dim_geo", "dim_product"]);
+
+        let mut db_columns = DbColumns::new();
+        db_columns.insert("sales_table".to_owned(), vec![
+            ("state_id", "UInt8"),
+            ("product_id", "UInt32"),
+            ("sales", "Nullable(String)"),
+            ("order_id", "String"),
+        ].into_iter().map(|(col, ty)| (col.to_owned(), ty.to_owned())).collect());
+        db_columns.insert("dim_geo".to_owned(), vec![
+            ("state_id", "LowCardinality(String)"),
+            ("state_name", "String"),
+        ].into_iter().map(|(col, ty)| (col.to_owned(), ty.to_owned())).collect());
+
+        let diagnostics: Vec<_> = cube_db_diagnostics(cube, &db_columns).iter()
+            .map(|d| d.to_string())
+            .collect();
+
+        assert_eq!(diagnostics, vec![
+            "cubes[sales].measures[Sales]: Column sales of table sales_table is Nullable(String), which is not numeric",
+            "cubes[sales].dimensions[Geography].hierarchies[Geography].levels[State]: Key column state_id of table dim_geo is LowCardinality(String), so the level needs key_type text",
+            "cubes[sales].dimensions[Geography].hierarchies[Geography].levels[State]: Column state_nam not found in table dim_geo",
+            "cubes[sales].dimensions[Product].hierarchies[Product]: Table dim_product not found",
+        ]);
+    }
+
+    #[test]
+    fn test_types() {
+        assert!(is_numeric_type("Nullable(Float64)"));
+        assert!(is_numeric_type("double precision"));
+        assert!(is_numeric_type("Decimal(18, 2)"));
+        assert!(!is_numeric_type("interval"));
+        assert!(!is_numeric_type("Date"));
+        assert!(is_text_type("LowCardinality(Nullable(String))"));
+        assert!(is_text_type("character varying"));
+        assert!(!is_text_type("integer"));
+        assert!(is_date_type("timestamp without time zone"));
+        assert!(is_date_type("Nullable(Date)"));
+    }
+}
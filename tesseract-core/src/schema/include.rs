@@ -0,0 +1,471 @@
+//! Schemas split over several files are read one file at a time, following
+//! their includes, and then merged into a single `SchemaConfigJson`.
+//!
+//! Included files are schemas too. Their cubes, shared dimensions, virtual
+//! cubes and annotations are merged into the schema; the name and default
+//! locale come from the first file.
+
+use failure::{Error, format_err, bail};
+use serde_xml_rs::from_reader;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+use super::json::SchemaConfigJson;
+use super::xml::SchemaConfigXML;
+
+
+/// A schema file, with its path kept to find includes.
+struct SchemaFile {
+    path: String,
+    config: SchemaConfigJson,
+}
+
+/// Reads the schema files at `paths` and every file that they include, and
+/// merges them. Include paths are relative to the including file, and are
+/// normalized so that a file is recognized however it's included.
+///
+/// `read` returns the contents of the file at a path.
+pub fn read_schema_config<F>(paths: &[String], read: F) -> Result<SchemaConfigJson, Error>
+    where F: Fn(&str) -> Result<String, Error>
+{
+    let mut files = vec![];
+
+    for path in paths {
+        read_schema_file(&normalize_path(Path::new(path)), &read, &mut vec![], &mut files)?;
+    }
+
+    merge_schema_files(files)
+}
+
+fn read_schema_file<F>(
+    path: &str,
+    read: &F,
+    including: &mut Vec<String>,
+    files: &mut Vec<SchemaFile>,
+    ) -> Result<(), Error>
+    where F: Fn(&str) -> Result<String, Error>
+{
+    if including.iter().any(|p| p == path) {
+        bail!("Schema {} includes itself (through {})", path, including.join(" -> "));
+    }
+    // a file included twice (e.g. shared dimensions) is only merged once
+    if files.iter().any(|file| file.path == path) {
+        return Ok(());
+    }
+
+    let raw = read(path)?;
+    let mut config = parse_schema_config(path, &raw)?;
+    set_locations(path, &raw, &mut config);
+
+    let includes: Vec<String> = config.includes.iter()
+        .flat_map(|includes| includes.iter())
+        .map(|include| {
+            let include_path = Path::new(path)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(&include.path);
+
+            normalize_path(&include_path)
+        })
+        .collect();
+
+    files.push(SchemaFile {
+        path: path.to_owned(),
+        config,
+    });
+
+    including.push(path.to_owned());
+    for include in includes {
+        read_schema_file(&include, read, including, files)?;
+    }
+    including.pop();
+
+    Ok(())
+}
+
+/// Removes `.` and `..` components from a path, without reading the file
+/// system. Leading `..` components are kept.
+fn normalize_path(path: &Path) -> String {
+    let mut components: Vec<Component> = vec![];
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => { components.pop(); },
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => (),
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+
+    components.iter()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reads a single XML or JSON schema file, without following its includes.
+pub fn parse_schema_config(path: &str, raw: &str) -> Result<SchemaConfigJson, Error> {
+    if path.ends_with("xml") {
+        let schema_config: SchemaConfigXML = from_reader(raw.as_bytes())
+            .map_err(|err| format_err!("Error reading XML schema {}: {}", path, err))?;
+
+        // Serialize XML to JSON as intermediary step
+        let serialized = serde_json::to_string(&schema_config)?;
+        serde_json::from_str(&serialized)
+            .map_err(|err| format_err!("Error reading XML schema {}: {}", path, err))
+    } else if path.ends_with("json") {
+        serde_json::from_str(raw)
+            .map_err(|err| format_err!("Error reading JSON schema {}: {}", path, err))
+    } else {
+        bail!("Schema format not supported: {}", path);
+    }
+}
+
+/// Sets the file and line of each cube, virtual cube and shared dimension
+/// of a schema file, e.g. `schema/sales.xml:12`. The line is left out if it
+/// can't be found.
+fn set_locations(path: &str, raw: &str, config: &mut SchemaConfigJson) {
+    let lines = if path.ends_with("xml") {
+        xml_element_lines(raw)
+    } else {
+        json_element_lines(raw)
+    };
+
+    let location = |list: &str, idx: usize| {
+        match lines.get(list).and_then(|list_lines| list_lines.get(idx)) {
+            Some(line) => format!("{}:{}", path, line),
+            None => path.to_owned(),
+        }
+    };
+
+    for (idx, cube) in config.cubes.iter_mut().enumerate() {
+        cube.location = Some(location("cubes", idx));
+    }
+    for (idx, cube) in config.virtual_cubes.iter_mut().flat_map(|cubes| cubes.iter_mut()).enumerate() {
+        cube.location = Some(location("virtual_cubes", idx));
+    }
+    for (idx, dim) in config.shared_dimensions.iter_mut().flat_map(|dims| dims.iter_mut()).enumerate() {
+        dim.location = Some(location("shared_dimensions", idx));
+    }
+}
+
+/// The line of each element directly in the `Schema` element, by the name of
+/// its list in `SchemaConfigJson`, in the order of the file.
+fn xml_element_lines(raw: &str) -> HashMap<String, Vec<usize>> {
+    let mut lines: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut reader = EventReader::new(raw.as_bytes());
+    let mut depth = 0;
+
+    loop {
+        match reader.next() {
+            Ok(XmlEvent::StartElement { name, .. }) => {
+                let list = match name.local_name.as_str() {
+                    "Cube" => Some("cubes"),
+                    "VirtualCube" => Some("virtual_cubes"),
+                    "SharedDimension" => Some("shared_dimensions"),
+                    _ => None,
+                };
+
+                if let (1, Some(list)) = (depth, list) {
+                    // rows are counted from 0
+                    let line = reader.position().row as usize + 1;
+                    lines.entry(list.to_owned()).or_default().push(line);
+                }
+                depth += 1;
+            },
+            Ok(XmlEvent::EndElement { .. }) => depth -= 1,
+            Ok(XmlEvent::EndDocument) | Err(_) => break,
+            _ => (),
+        }
+    }
+
+    lines
+}
+
+/// The line of each object in a list of the top-level object, by the name
+/// of the list, e.g. each cube in `cubes`.
+fn json_element_lines(raw: &str) -> HashMap<String, Vec<usize>> {
+    let mut lines: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut line = 1;
+    let mut depth = 0;
+
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut string = String::new();
+
+    // the last key of the top-level object, and the list that is its value
+    let mut key: Option<String> = None;
+    let mut list: Option<String> = None;
+
+    for c in raw.chars() {
+        if c == '\n' {
+            line += 1;
+        }
+
+        if in_string {
+            match c {
+                _ if escaped => { escaped = false; string.push(c); },
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => string.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                string.clear();
+            },
+            ':' if depth == 1 => key = Some(string.clone()),
+            '[' => {
+                depth += 1;
+                if depth == 2 {
+                    list = key.take();
+                }
+            },
+            '{' => {
+                depth += 1;
+                if let (3, Some(ref list)) = (depth, &list) {
+                    lines.entry(list.clone()).or_default().push(line);
+                }
+            },
+            ']' | '}' => {
+                depth -= 1;
+                if depth == 1 {
+                    list = None;
+                }
+            },
+            _ => (),
+        }
+    }
+
+    lines
+}
+
+fn merge_schema_files(files: Vec<SchemaFile>) -> Result<SchemaConfigJson, Error> {
+    // cubes and virtual cubes share names, shared dimensions have their own
+    let mut cube_locations: HashMap<String, String> = HashMap::new();
+    let mut shared_dim_locations: HashMap<String, String> = HashMap::new();
+
+    for file in &files {
+        let cubes = file.config.cubes.iter()
+            .map(|cube| (&cube.name, &cube.location))
+            .chain(file.config.virtual_cubes.iter().flat_map(|cubes| cubes.iter()).map(|cube| (&cube.name, &cube.location)));
+
+        for (name, location) in cubes {
+            let location = location.clone().unwrap_or_else(|| file.path.clone());
+            if let Some(first) = cube_locations.get(name) {
+                bail!("Cube {} at {} is already defined at {}", name, location, first);
+            }
+            cube_locations.insert(name.clone(), location);
+        }
+
+        for shared_dim in file.config.shared_dimensions.iter().flat_map(|dims| dims.iter()) {
+            let location = shared_dim.location.clone().unwrap_or_else(|| file.path.clone());
+            if let Some(first) = shared_dim_locations.get(&shared_dim.name) {
+                bail!("Shared dimension {} at {} is already defined at {}", shared_dim.name, location, first);
+            }
+            shared_dim_locations.insert(shared_dim.name.clone(), location);
+        }
+    }
+
+    // dimension usages can use shared dimensions from any file
+    for file in &files {
+        for cube in &file.config.cubes {
+            for dim_usage in cube.dimension_usages.iter().flat_map(|usages| usages.iter()) {
+                if !shared_dim_locations.contains_key(&dim_usage.source) {
+                    bail!("Shared dimension {} used by cube {} at {} not found",
+                        dim_usage.source,
+                        cube.name,
+                        cube.location.as_ref().unwrap_or(&file.path),
+                    );
+                }
+            }
+        }
+    }
+
+    let mut files = files.into_iter();
+    let mut schema_config = files.next()
+        .ok_or_else(|| format_err!("No schema files found"))?
+        .config;
+    schema_config.includes = None;
+
+    for file in files {
+        let config = file.config;
+
+        schema_config.cubes.extend(config.cubes);
+        extend_option(&mut schema_config.shared_dimensions, config.shared_dimensions);
+        extend_option(&mut schema_config.virtual_cubes, config.virtual_cubes);
+        extend_option(&mut schema_config.annotations, config.annotations);
+    }
+
+    Ok(schema_config)
+}
+
+fn extend_option<T>(target: &mut Option<Vec<T>>, items: Option<Vec<T>>) {
+    if let Some(items) = items {
+        target.get_or_insert_with(Vec::new).extend(items);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Schema, SchemaValidationError};
+
+    fn read_from(files: Vec<(&'static str, &'static str)>) -> impl Fn(&str) -> Result<String, Error> {
+        let files: HashMap<_, _> = files.into_iter().collect();
+
+        move |path| {
+            files.get(path)
+                .map(|raw| raw.to_string())
+                .ok_or_else(|| format_err!("Schema file not found at {}", path))
+        }
+    }
+
+    const GEO_XML: &str = r#"<Schema name="geo">
+    <SharedDimension name="Geography">
+        <Hierarchy name="Geography" primary_key="state_id">
+            <Table name="dim_geo" />
+            <Level name="State" key_column="state_id" />
+        </Hierarchy>
+    </SharedDimension>
+</Schema>"#;
+
+    const SALES_JSON: &str = r#"{
+    "name": "sales",
+    "cubes": [{
+        "name": "sales",
+        "table": { "name": "sales_table" },
+        "dimension_usages": [{ "source": "Geography", "foreign_key": "state_id" }],
+        "measures": [{ "name": "Sales", "column": "sales", "aggregator": "sum" }]
+    }]
+}"#;
+
+    #[test]
+    fn test_includes() {
+        let read = read_from(vec![
+            ("schema/main.xml", r#"<Schema name="main">
+    <Include path="dims/geo.xml" />
+    <Include path="cubes/sales.json" />
+</Schema>"#),
+            ("schema/dims/geo.xml", GEO_XML),
+            ("schema/cubes/sales.json", SALES_JSON),
+        ]);
+
+        let schema_config = read_schema_config(&["schema/main.xml".to_owned()], &read).unwrap();
+
+        assert_eq!(schema_config.name, "main");
+        assert_eq!(schema_config.includes, None);
+        assert_eq!(schema_config.cubes[0].name, "sales");
+        assert_eq!(schema_config.shared_dimensions.unwrap()[0].name, "Geography");
+    }
+
+    #[test]
+    fn test_include_paths() {
+        // the same file included through different paths is only read once
+        let read = read_from(vec![
+            ("schema/main.xml", r#"<Schema name="main">
+    <Include path="./dims/geo.xml" />
+    <Include path="cubes/sales.xml" />
+</Schema>"#),
+            ("schema/cubes/sales.xml", r#"<Schema name="sales">
+    <Include path="../dims/geo.xml" />
+</Schema>"#),
+            ("schema/dims/geo.xml", GEO_XML),
+        ]);
+
+        let schema_config = read_schema_config(&["./schema/main.xml".to_owned()], &read).unwrap();
+        assert_eq!(schema_config.shared_dimensions.unwrap().len(), 1);
+
+        assert_eq!(normalize_path(Path::new("a/./b/../c.xml")), "a/c.xml");
+        assert_eq!(normalize_path(Path::new("../a/../../b.xml")), "../../b.xml");
+        assert_eq!(normalize_path(Path::new("/a/../../b.xml")), "/b.xml");
+    }
+
+    #[test]
+    fn test_includes_errors() {
+        // a directory of fragments, where a cube is defined twice
+        let read = read_from(vec![
+            ("a.json", SALES_JSON),
+            ("b.xml", GEO_XML),
+            ("c.json", SALES_JSON),
+        ]);
+        let paths = vec!["a.json".to_owned(), "b.xml".to_owned(), "c.json".to_owned()];
+        let err = read_schema_config(&paths, &read).unwrap_err();
+        assert_eq!(err.to_string(), "Cube sales at c.json:3 is already defined at a.json:3");
+
+        // shared dimensions must be defined in some file
+        let err = read_schema_config(&paths[..1], &read).unwrap_err();
+        assert_eq!(err.to_string(), "Shared dimension Geography used by cube sales at a.json:3 not found");
+
+        let read = read_from(vec![
+            ("a.xml", r#"<Schema name="a"><Include path="b.xml" /></Schema>"#),
+            ("b.xml", r#"<Schema name="b"><Include path="a.xml" /></Schema>"#),
+        ]);
+        assert!(read_schema_config(&["a.xml".to_owned()], &read).is_err());
+    }
+
+    #[test]
+    fn test_include_locations() {
+        let read = read_from(vec![
+            ("main.xml", r#"<Schema name="main">
+    <Include path="geo.xml" />
+
+    <Include path="sales.json" />
+</Schema>"#),
+            ("geo.xml", r#"<Schema name="geo">
+
+    <SharedDimension name="Geography">
+        <Hierarchy name="Geography" primary_key="state_id">
+            <Table name="dim_geo" />
+            <Level name="State" key_column="state_id" />
+            <Level name="State" key_column="state_name" />
+        </Hierarchy>
+    </SharedDimension>
+</Schema>"#),
+            ("sales.json", r#"{
+    "name": "sales",
+    "shared_dimensions": [],
+    "cubes": [
+        {
+            "name": "sales {[\"",
+            "table": { "name": "sales_table" },
+            "dimension_usages": [{ "source": "Geography", "foreign_key": "state_id" }],
+            "measures": [{ "name": "Sales", "column": "sales", "aggregator": "sum" }]
+        },
+        {
+            "name": "returns",
+            "table": { "name": "returns_table" },
+            "dimension_usages": [{ "source": "Geography", "foreign_key": "state_id" }],
+            "measures": [
+                { "name": "Returns", "column": "returns", "aggregator": "sum" },
+                { "name": "Returns", "column": "returns_total", "aggregator": "sum" }
+            ]
+        }
+    ]
+}"#),
+        ]);
+
+        let schema_config = read_schema_config(&["main.xml".to_owned()], &read).unwrap();
+        assert_eq!(schema_config.shared_dimensions.unwrap()[0].location, Some("geo.xml:3".to_owned()));
+        assert_eq!(schema_config.cubes[0].location, Some("sales.json:5".to_owned()));
+        assert_eq!(schema_config.cubes[1].location, Some("sales.json:11".to_owned()));
+
+        // diagnostics are prefixed with the file and line of their element
+        let mut schema = Schema::from_files(&["main.xml".to_owned()], &read).unwrap();
+        let err = schema.validate().unwrap_err();
+        let diagnostics = err.downcast::<SchemaValidationError>().unwrap().diagnostics;
+        let diagnostics: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(diagnostics, vec![
+            "geo.xml:3 shared_dimensions[Geography].hierarchies[Geography]: Duplicate level name State".to_owned(),
+            "sales.json:11 cubes[returns]: Duplicate measure name Returns".to_owned(),
+        ]);
+    }
+}
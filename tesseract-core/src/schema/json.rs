@@ -1,20 +1,45 @@
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
 use crate::query_ir::MemberType;
 use super::aggregator::Aggregator;
-use super::{DimensionType, MeasureType};
+use super::{DimensionType, MeasureType, TimePart};
 
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct SchemaConfigJson {
     pub name: String,
+    pub includes: Option<Vec<IncludeConfigJson>>,
     pub shared_dimensions: Option<Vec<SharedDimensionConfigJson>>,
+    #[serde(default)]
     pub cubes: Vec<CubeConfigJson>,
+    pub virtual_cubes: Option<Vec<VirtualCubeConfigJson>>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
     pub default_locale: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct IncludeConfigJson {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct VirtualCubeConfigJson {
+    pub name: String,
+    pub public: Option<String>,
+    pub min_auth_level: Option<i32>,
+    pub cube_usages: Vec<CubeUsageJson>,
+    pub annotations: Option<Vec<AnnotationConfigJson>>,
+    /// see `CubeConfigJson::location`
+    #[serde(skip)]
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CubeUsageJson {
+    pub cube_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct CubeConfigJson {
     pub name: String,
     pub public: Option<String>,
@@ -24,9 +49,38 @@ pub struct CubeConfigJson {
     pub dimension_usages: Option<Vec<DimensionUsageJson>>,
     pub measures: Vec<MeasureConfigJson>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub fiscal_year_start_month: Option<u32>,
+    pub aggregate_tables: Option<Vec<AggregateTableConfigJson>>,
+    /// file and line the element is read from, to locate errors; it's not
+    /// part of the schema
+    #[serde(skip)]
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AggregateTableConfigJson {
+    pub table: TableConfigJson,
+    pub rows: Option<u64>,
+    pub levels: Vec<AggregateLevelConfigJson>,
+    pub measures: Vec<AggregateMeasureConfigJson>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AggregateLevelConfigJson {
+    pub dimension: String,
+    pub hierarchy: String,
+    pub level: String,
+    pub key_column: String,
+    pub name_column: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AggregateMeasureConfigJson {
+    pub name: String,
+    pub column: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DimensionConfigJson {
     pub name: String,
     pub foreign_key: Option<String>, // does not exist for shared dims
@@ -37,7 +91,7 @@ pub struct DimensionConfigJson {
     pub annotations: Option<Vec<AnnotationConfigJson>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct SharedDimensionConfigJson {
     pub name: String,
     pub hierarchies: Vec<HierarchyConfigJson>,
@@ -45,9 +99,12 @@ pub struct SharedDimensionConfigJson {
     #[serde(rename="type")]
     pub dim_type: Option<DimensionType>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    /// see `CubeConfigJson::location`
+    #[serde(skip)]
+    pub location: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DimensionUsageJson {
     pub source: String,
     pub name: Option<String>,
@@ -55,7 +112,7 @@ pub struct DimensionUsageJson {
     pub annotations: Option<Vec<AnnotationConfigJson>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct HierarchyConfigJson {
     pub name: String,
     pub table: Option<TableConfigJson>,
@@ -64,16 +121,26 @@ pub struct HierarchyConfigJson {
     pub annotations: Option<Vec<AnnotationConfigJson>>,
     pub inline_table: Option<InlineTableJson>,
     pub default_member: Option<String>,
+    pub parent_column: Option<String>,
+    pub closure: Option<ClosureConfigJson>,
+    pub max_depth: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClosureConfigJson {
+    pub table: TableConfigJson,
+    pub parent_column: String,
+    pub child_column: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct InlineTableJson {
     pub alias: String,
     pub column_definitions: Vec<InlineTableColumnDefinitionJson>,
     pub rows: Vec<InlineTableRowJson>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct InlineTableColumnDefinitionJson {
     pub name: String,
     pub key_type: MemberType,
@@ -81,18 +148,18 @@ pub struct InlineTableColumnDefinitionJson {
     pub caption_set: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct InlineTableRowJson {
     pub row_values: Vec<InlineTableRowValueJson>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct InlineTableRowValueJson {
     pub column: String,
     pub value: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct LevelConfigJson {
     pub name: String,
     pub key_column: String,
@@ -100,9 +167,13 @@ pub struct LevelConfigJson {
     pub properties: Option<Vec<PropertyConfigJson>>,
     pub key_type: Option<MemberType>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub time_part: Option<TimePart>,
+    pub table: Option<TableConfigJson>,
+    pub primary_key: Option<String>,
+    pub foreign_key: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct MeasureConfigJson {
     pub name: String,
     pub column: String,
@@ -112,14 +183,14 @@ pub struct MeasureConfigJson {
     pub annotations: Option<Vec<AnnotationConfigJson>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct TableConfigJson {
     pub name: String,
     pub schema: Option<String>,
     pub primary_key: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct PropertyConfigJson {
     pub name: String,
     pub column: String,
@@ -127,7 +198,7 @@ pub struct PropertyConfigJson {
     pub annotations: Option<Vec<AnnotationConfigJson>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct AnnotationConfigJson {
     pub name: String,
     pub text: String,
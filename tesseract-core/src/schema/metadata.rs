@@ -8,6 +8,7 @@ use super::{
     Dimension,
     DimensionType,
     Hierarchy,
+    HierarchyType,
     Level,
     Measure,
     MeasureType,
@@ -87,6 +88,8 @@ impl From<&Dimension> for DimensionMetadata {
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct HierarchyMetadata {
     pub name: String,
+    #[serde(rename="type")]
+    pub hierarchy_type: HierarchyType,
     pub levels: Vec<LevelMetadata>,
     pub annotations: AnnotationMetadata,
 }
@@ -97,6 +100,7 @@ impl From<&Hierarchy> for HierarchyMetadata {
 
         HierarchyMetadata {
             name: hierarchy.name.clone(),
+            hierarchy_type: hierarchy.hierarchy_type(),
             levels: hierarchy.levels.iter().map(|l| l.into()).collect(),
             annotations,
         }
@@ -0,0 +1,852 @@
+//! Imports Mondrian 3 and Mondrian 4 XML schemas into a `SchemaConfigJson`,
+//! see mondrian-tesseract.md for how the formats differ.
+//!
+//! Mondrian elements are freely interleaved (e.g. `Dimension` and
+//! `DimensionUsage` in a cube), which serde-xml-rs can't deserialize into
+//! lists, so the XML is read into a tree of `Element`s instead of structs.
+//!
+//! Features that Tesseract doesn't have, like calculated members, named sets
+//! and roles, are left out. Features that would change the meaning of the
+//! schema if left out, like `Join` hierarchies and SQL expressions, are
+//! errors.
+
+use failure::{Error, format_err, bail};
+use std::collections::HashMap;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::query_ir::MemberType;
+use super::{Aggregator, DimensionType};
+use super::json::{
+    SchemaConfigJson,
+    SharedDimensionConfigJson,
+    VirtualCubeConfigJson,
+    CubeUsageJson,
+    CubeConfigJson,
+    DimensionConfigJson,
+    DimensionUsageJson,
+    HierarchyConfigJson,
+    LevelConfigJson,
+    MeasureConfigJson,
+    TableConfigJson,
+    PropertyConfigJson,
+    AnnotationConfigJson,
+};
+
+
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<String> {
+        self.attributes.iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn required_attr(&self, name: &str) -> Result<String, Error> {
+        self.attr(name)
+            .ok_or_else(|| format_err!("{} {} has no {}", self.name, self.attr("name").unwrap_or_default(), name))
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Children inside a wrapper element, e.g. the `Hierarchy`s inside
+    /// `Hierarchies` in Mondrian 4.
+    fn grandchildren<'a>(&'a self, wrapper: &'a str, name: &'a str) -> impl Iterator<Item=&'a Element> + 'a {
+        self.children(wrapper).flat_map(move |child| child.children(name))
+    }
+
+    /// A column given as an attribute, or as a single `Column` child of a
+    /// `Key`, `Name` or `ForeignKey` element in Mondrian 4.
+    fn column(&self, attr: &str, element: &str) -> Result<Option<String>, Error> {
+        if let Some(column) = self.attr(attr) {
+            return Ok(Some(column));
+        }
+
+        match self.child(element) {
+            Some(columns) => {
+                let columns: Vec<_> = columns.children("Column").collect();
+                match columns.as_slice() {
+                    [column] => Ok(Some(column.required_attr("name")?)),
+                    _ => bail!("{} {}: composite {} is not supported", self.name, self.attr("name").unwrap_or_default(), element),
+                }
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_elements(raw: &str) -> Result<Element, Error> {
+    let mut stack: Vec<Element> = vec![];
+    let mut root = None;
+
+    for event in EventReader::new(raw.as_bytes()) {
+        match event.map_err(|err| format_err!("Error reading Mondrian schema: {}", err))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes.into_iter()
+                        .map(|attr| (attr.name.local_name, attr.value))
+                        .collect(),
+                    ..Default::default()
+                });
+            },
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop()
+                    .ok_or_else(|| format_err!("Error reading Mondrian schema: unbalanced elements"))?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    root.ok_or_else(|| format_err!("Error reading Mondrian schema: no Schema element"))
+}
+
+/// Reads a Mondrian 3 or 4 XML schema. Mondrian 4 schemas are the ones with
+/// a `PhysicalSchema`.
+pub fn read_mondrian_schema(raw: &str) -> Result<SchemaConfigJson, Error> {
+    let schema = parse_elements(raw)?;
+
+    if schema.name != "Schema" {
+        bail!("Mondrian schema must start with Schema, not {}", schema.name);
+    }
+
+    if schema.child("PhysicalSchema").is_some() {
+        mondrian4::schema(&schema)
+    } else {
+        mondrian3::schema(&schema)
+    }
+}
+
+/// Mondrian 3: dimensions have hierarchies with a table and levels on columns
+/// of that table, and cubes have a fact table.
+mod mondrian3 {
+    use super::*;
+
+    pub(super) fn schema(schema: &Element) -> Result<SchemaConfigJson, Error> {
+        let shared_dimensions = schema.children("Dimension")
+            .map(|dim| {
+                Ok(SharedDimensionConfigJson {
+                    name: dim.required_attr("name")?,
+                    hierarchies: hierarchies(dim)?,
+                    default_hierarchy: None,
+                    dim_type: dimension_type(dim),
+                    annotations: annotations(dim),
+                    location: None,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let cubes = schema.children("Cube")
+            .map(cube)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        schema_config(schema, shared_dimensions, cubes)
+    }
+
+    fn cube(cube: &Element) -> Result<CubeConfigJson, Error> {
+        let name = cube.required_attr("name")?;
+
+        let table = cube.child("Table")
+            .ok_or_else(|| format_err!("Cube {}: only a Table fact table is supported", name))?;
+
+        let dimensions = cube.children("Dimension")
+            .map(|dim| {
+                let mut hierarchies = hierarchies(dim)?;
+
+                // degenerate dimensions are in the fact table, and joined on
+                // the key of their bottom level
+                let foreign_key = match dim.attr("foreignKey") {
+                    Some(foreign_key) => foreign_key,
+                    None => {
+                        let hier = hierarchies.first_mut()
+                            .ok_or_else(|| format_err!("Dimension {} has no hierarchies", dim.attr("name").unwrap_or_default()))?;
+                        let key_column = hier.levels.last()
+                            .ok_or_else(|| format_err!("Hierarchy {} has no levels", hier.name))?
+                            .key_column
+                            .clone();
+                        hier.primary_key.get_or_insert(key_column).clone()
+                    },
+                };
+
+                Ok(DimensionConfigJson {
+                    name: dim.required_attr("name")?,
+                    foreign_key: Some(foreign_key),
+                    hierarchies,
+                    default_hierarchy: None,
+                    dim_type: dimension_type(dim),
+                    annotations: annotations(dim),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let dimension_usages = cube.children("DimensionUsage")
+            .map(|usage| {
+                Ok(DimensionUsageJson {
+                    source: usage.required_attr("source")?,
+                    name: usage.attr("name"),
+                    foreign_key: usage.required_attr("foreignKey")?,
+                    annotations: annotations(usage),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let measures = cube.children("Measure")
+            .map(measure)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(cube_config(name, cube, table_config(table)?, dimensions, dimension_usages, measures))
+    }
+
+    fn hierarchies(dim: &Element) -> Result<Vec<HierarchyConfigJson>, Error> {
+        let dim_name = dim.required_attr("name")?;
+
+        dim.children("Hierarchy")
+            .map(|hier| {
+                let name = hier.attr("name").unwrap_or_else(|| dim_name.clone());
+
+                if hier.child("Join").is_some() || hier.child("View").is_some() || hier.child("InlineTable").is_some() {
+                    bail!("Hierarchy {} of dimension {}: only a Table is supported", name, dim_name);
+                }
+
+                let table = match hier.child("Table") {
+                    Some(table) => Some(table_config(table)?),
+                    None => None,
+                };
+
+                let levels = hier.children("Level")
+                    .map(level)
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                Ok(HierarchyConfigJson {
+                    name,
+                    table,
+                    primary_key: hier.attr("primaryKey"),
+                    levels,
+                    annotations: annotations(hier),
+                    inline_table: None,
+                    default_member: None,
+                    parent_column: None,
+                    closure: None,
+                    max_depth: None,
+                })
+            })
+            .collect()
+    }
+
+    fn level(level: &Element) -> Result<LevelConfigJson, Error> {
+        let name = level.required_attr("name")?;
+
+        if level.child("KeyExpression").is_some() || level.child("NameExpression").is_some() {
+            bail!("Level {}: SQL expressions are not supported", name);
+        }
+        let key_column = level.attr("column")
+            .ok_or_else(|| format_err!("Level {} has no column", name))?;
+
+        let properties = level.children("Property")
+            .map(|prop| {
+                Ok(PropertyConfigJson {
+                    name: prop.required_attr("name")?,
+                    column: prop.required_attr("column")?,
+                    caption_set: None,
+                    annotations: annotations(prop),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(level_config(
+            name,
+            key_column,
+            level.attr("nameColumn").or_else(|| level.attr("captionColumn")),
+            key_type(level.attr("type")),
+            properties,
+            annotations(level),
+        ))
+    }
+}
+
+/// Mondrian 4: tables are declared in the `PhysicalSchema`, dimensions have
+/// attributes on columns of the dimension table and hierarchies of levels on
+/// attributes, and cubes have a measure group with links to the dimensions.
+mod mondrian4 {
+    use super::*;
+
+    pub(super) fn schema(schema: &Element) -> Result<SchemaConfigJson, Error> {
+        let tables: HashMap<String, TableConfigJson> = schema.grandchildren("PhysicalSchema", "Table")
+            .map(|table| {
+                let config = table_config(table)?;
+                Ok((table.attr("alias").unwrap_or_else(|| config.name.clone()), config))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let shared_dimensions = schema.children("Dimension")
+            .map(|dim| {
+                Ok(SharedDimensionConfigJson {
+                    name: dim.required_attr("name")?,
+                    hierarchies: hierarchies(dim, &tables, true)?,
+                    default_hierarchy: None,
+                    dim_type: dimension_type(dim),
+                    annotations: annotations(dim),
+                    location: None,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let cubes = schema.children("Cube")
+            .map(|c| cube(c, &tables))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        schema_config(schema, shared_dimensions, cubes)
+    }
+
+    fn cube(cube: &Element, tables: &HashMap<String, TableConfigJson>) -> Result<CubeConfigJson, Error> {
+        let name = cube.required_attr("name")?;
+
+        // a Tesseract cube has a single fact table
+        let measure_groups: Vec<_> = cube.grandchildren("MeasureGroups", "MeasureGroup").collect();
+        let measure_group = match measure_groups.as_slice() {
+            [measure_group] => measure_group,
+            _ => bail!("Cube {}: only cubes with a single MeasureGroup are supported", name),
+        };
+        let table = physical_table(tables, &measure_group.required_attr("table")?)?;
+
+        // foreign keys of the dimensions linked with a foreign key, and None
+        // for dimensions in the fact table
+        let mut links: HashMap<String, Option<String>> = HashMap::new();
+        for link in measure_group.grandchildren("DimensionLinks", "ForeignKeyLink") {
+            let foreign_key = link.column("foreignKeyColumn", "ForeignKey")?
+                .ok_or_else(|| format_err!("ForeignKeyLink to {} has no foreign key column", link.attr("dimension").unwrap_or_default()))?;
+            links.insert(link.required_attr("dimension")?, Some(foreign_key));
+        }
+        for link in measure_group.grandchildren("DimensionLinks", "FactLink") {
+            links.insert(link.required_attr("dimension")?, None);
+        }
+
+        let mut dimensions = vec![];
+        let mut dimension_usages = vec![];
+
+        for dim in cube.grandchildren("Dimensions", "Dimension") {
+            let source = dim.attr("source");
+            let dim_name = dim.attr("name").or_else(|| source.clone())
+                .ok_or_else(|| format_err!("Cube {}: Dimension has no name", name))?;
+
+            let link = links.get(&dim_name)
+                .ok_or_else(|| format_err!("Cube {}: dimension {} is not linked to the measure group", name, dim_name))?;
+
+            match (source, link) {
+                (Some(source), Some(foreign_key)) => {
+                    dimension_usages.push(DimensionUsageJson {
+                        name: if source == dim_name { None } else { Some(dim_name) },
+                        source,
+                        foreign_key: foreign_key.clone(),
+                        annotations: annotations(dim),
+                    });
+                },
+                (Some(_), None) => bail!("Cube {}: shared dimension {} can't be in the fact table", name, dim_name),
+                (None, link) => {
+                    let hierarchies = hierarchies(dim, tables, link.is_some())?;
+
+                    // dimensions in the fact table are joined on their key
+                    let foreign_key = match link {
+                        Some(foreign_key) => foreign_key.clone(),
+                        None => hierarchies.first()
+                            .and_then(|hier| hier.primary_key.clone())
+                            .ok_or_else(|| format_err!("Dimension {} has no key", dim_name))?,
+                    };
+
+                    dimensions.push(DimensionConfigJson {
+                        name: dim_name,
+                        foreign_key: Some(foreign_key),
+                        hierarchies,
+                        default_hierarchy: None,
+                        dim_type: dimension_type(dim),
+                        annotations: annotations(dim),
+                    });
+                },
+            }
+        }
+
+        let measures = measure_group.grandchildren("Measures", "Measure")
+            .map(measure)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(cube_config(name, cube, table, dimensions, dimension_usages, measures))
+    }
+
+    /// Without `Hierarchies`, every attribute that has a hierarchy (the
+    /// default) becomes a hierarchy with a single level.
+    fn hierarchies(
+        dim: &Element,
+        tables: &HashMap<String, TableConfigJson>,
+        has_table: bool,
+        ) -> Result<Vec<HierarchyConfigJson>, Error>
+    {
+        let dim_name = dim.required_attr("name")?;
+
+        let attributes: Vec<&Element> = dim.grandchildren("Attributes", "Attribute").collect();
+        let attribute = |name: &str| {
+            attributes.iter()
+                .find(|attr| attr.attr("name").as_ref().map(|n| n.as_str()) == Some(name))
+                .ok_or_else(|| format_err!("Dimension {}: attribute {} not found", dim_name, name))
+        };
+
+        let key_attribute = match dim.attr("key") {
+            Some(key) => attribute(&key)?,
+            None => attributes.first().ok_or_else(|| format_err!("Dimension {} has no attributes", dim_name))?,
+        };
+        let primary_key = key_attribute.column("keyColumn", "Key")?
+            .ok_or_else(|| format_err!("Dimension {}: key attribute has no key column", dim_name))?;
+
+        let table = match (has_table, dim.attr("table")) {
+            (true, Some(table)) => Some(physical_table(tables, &table)?),
+            (true, None) => bail!("Dimension {} has no table", dim_name),
+            (false, _) => None,
+        };
+
+        let hier_levels: Vec<(String, Vec<(Option<String>, String)>)> = if dim.child("Hierarchies").is_some() {
+            dim.grandchildren("Hierarchies", "Hierarchy")
+                .map(|hier| {
+                    let levels = hier.children("Level")
+                        .map(|level| Ok((level.attr("name"), level.required_attr("attribute")?)))
+                        .collect::<Result<Vec<_>, Error>>()?;
+                    Ok((hier.attr("name").unwrap_or_else(|| dim_name.clone()), levels))
+                })
+                .collect::<Result<_, Error>>()?
+        } else {
+            attributes.iter()
+                .filter(|attr| attr.attr("hasHierarchy").map(|has| has != "false").unwrap_or(true))
+                .map(|attr| {
+                    let name = attr.required_attr("name")?;
+                    Ok((name.clone(), vec![(None, name)]))
+                })
+                .collect::<Result<_, Error>>()?
+        };
+
+        hier_levels.into_iter()
+            .map(|(name, levels)| {
+                let levels = levels.into_iter()
+                    .map(|(level_name, attr_name)| {
+                        let attr = attribute(&attr_name)?;
+
+                        let key_column = attr.column("keyColumn", "Key")?
+                            .ok_or_else(|| format_err!("Attribute {} has no key column", attr_name))?;
+                        let name_column = attr.column("nameColumn", "Name")?;
+
+                        let properties = attr.children("Property")
+                            .map(|prop| {
+                                let prop_attr = attribute(&prop.required_attr("attribute")?)?;
+                                Ok(PropertyConfigJson {
+                                    name: prop.attr("name").unwrap_or(prop_attr.required_attr("name")?),
+                                    column: prop_attr.column("keyColumn", "Key")?
+                                        .ok_or_else(|| format_err!("Attribute {} has no key column", attr_name))?,
+                                    caption_set: None,
+                                    annotations: None,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?;
+
+                        Ok(level_config(
+                            level_name.unwrap_or(attr_name),
+                            key_column,
+                            name_column,
+                            key_type(attr.attr("datatype")),
+                            properties,
+                            annotations(attr),
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                Ok(HierarchyConfigJson {
+                    name,
+                    table: table.clone(),
+                    primary_key: Some(primary_key.clone()),
+                    levels,
+                    annotations: None,
+                    inline_table: None,
+                    default_member: None,
+                    parent_column: None,
+                    closure: None,
+                    max_depth: None,
+                })
+            })
+            .collect()
+    }
+
+    fn physical_table(tables: &HashMap<String, TableConfigJson>, name: &str) -> Result<TableConfigJson, Error> {
+        tables.get(name)
+            .cloned()
+            .ok_or_else(|| format_err!("Table {} not found in PhysicalSchema", name))
+    }
+}
+
+fn schema_config(
+    schema: &Element,
+    shared_dimensions: Vec<SharedDimensionConfigJson>,
+    cubes: Vec<CubeConfigJson>,
+    ) -> Result<SchemaConfigJson, Error>
+{
+    // a virtual cube has all the measures and common shared dimensions of
+    // its cubes, so only the cube usages are kept
+    let virtual_cubes = schema.children("VirtualCube")
+        .map(|virtual_cube| {
+            let name = virtual_cube.required_attr("name")?;
+
+            // without `CubeUsages` (before Mondrian 3.1), the cubes are the
+            // ones that the measures and dimensions come from
+            let cube_names = if virtual_cube.child("CubeUsages").is_some() {
+                virtual_cube.grandchildren("CubeUsages", "CubeUsage")
+                    .map(|usage| usage.required_attr("cubeName"))
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                let mut cube_names: Vec<String> = vec![];
+                let usages = virtual_cube.children("VirtualCubeMeasure")
+                    .chain(virtual_cube.children("VirtualCubeDimension"))
+                    .filter_map(|usage| usage.attr("cubeName"));
+
+                for cube_name in usages {
+                    if !cube_names.contains(&cube_name) {
+                        cube_names.push(cube_name);
+                    }
+                }
+                cube_names
+            };
+
+            Ok(VirtualCubeConfigJson {
+                name,
+                public: visible(virtual_cube),
+                min_auth_level: None,
+                cube_usages: cube_names.into_iter().map(|cube_name| CubeUsageJson { cube_name }).collect(),
+                annotations: annotations(virtual_cube),
+                location: None,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(SchemaConfigJson {
+        name: schema.attr("name").unwrap_or_default(),
+        includes: None,
+        shared_dimensions: if shared_dimensions.is_empty() { None } else { Some(shared_dimensions) },
+        cubes,
+        virtual_cubes: if virtual_cubes.is_empty() { None } else { Some(virtual_cubes) },
+        annotations: annotations(schema),
+        default_locale: None,
+    })
+}
+
+fn cube_config(
+    name: String,
+    cube: &Element,
+    table: TableConfigJson,
+    dimensions: Vec<DimensionConfigJson>,
+    dimension_usages: Vec<DimensionUsageJson>,
+    measures: Vec<MeasureConfigJson>,
+    ) -> CubeConfigJson
+{
+    CubeConfigJson {
+        name,
+        public: visible(cube),
+        min_auth_level: None,
+        table,
+        dimensions: if dimensions.is_empty() { None } else { Some(dimensions) },
+        dimension_usages: if dimension_usages.is_empty() { None } else { Some(dimension_usages) },
+        measures,
+        annotations: annotations(cube),
+        fiscal_year_start_month: None,
+        aggregate_tables: None,
+        location: None,
+    }
+}
+
+fn level_config(
+    name: String,
+    key_column: String,
+    name_column: Option<String>,
+    key_type: Option<MemberType>,
+    properties: Vec<PropertyConfigJson>,
+    annotations: Option<Vec<AnnotationConfigJson>>,
+    ) -> LevelConfigJson
+{
+    LevelConfigJson {
+        name,
+        key_column,
+        name_column,
+        properties: if properties.is_empty() { None } else { Some(properties) },
+        key_type,
+        annotations,
+        time_part: None,
+        table: None,
+        primary_key: None,
+        foreign_key: None,
+    }
+}
+
+/// The format string is kept as a `format_string` annotation.
+fn measure(measure: &Element) -> Result<MeasureConfigJson, Error> {
+    let name = measure.required_attr("name")?;
+
+    if measure.child("MeasureExpression").is_some() {
+        bail!("Measure {}: SQL expressions are not supported", name);
+    }
+
+    let aggregator = match measure.required_attr("aggregator")?.as_str() {
+        "sum" => Aggregator::Sum,
+        "count" => Aggregator::Count,
+        "avg" => Aggregator::Average,
+        "min" => Aggregator::Min,
+        "max" => Aggregator::Max,
+        aggregator => bail!("Measure {}: aggregator {} is not supported", name, aggregator),
+    };
+
+    let mut annotations = annotations(measure).unwrap_or_default();
+    if let Some(format_string) = measure.attr("formatString") {
+        annotations.push(AnnotationConfigJson {
+            name: "format_string".to_owned(),
+            text: format_string,
+        });
+    }
+
+    Ok(MeasureConfigJson {
+        column: measure.attr("column")
+            .ok_or_else(|| format_err!("Measure {} has no column", name))?,
+        name,
+        aggregator,
+        measure_type: None,
+        annotations: if annotations.is_empty() { None } else { Some(annotations) },
+    })
+}
+
+fn table_config(table: &Element) -> Result<TableConfigJson, Error> {
+    Ok(TableConfigJson {
+        name: table.required_attr("name")?,
+        schema: table.attr("schema"),
+        primary_key: None,
+    })
+}
+
+fn dimension_type(dim: &Element) -> Option<DimensionType> {
+    match dim.attr("type").as_ref().map(|t| t.as_str()) {
+        Some("TimeDimension") | Some("TIME") => Some(DimensionType::Time),
+        _ => None,
+    }
+}
+
+/// Only `String` keys are quoted in cuts; numeric types are the default.
+fn key_type(mondrian_type: Option<String>) -> Option<MemberType> {
+    match mondrian_type.as_ref().map(|t| t.as_str()) {
+        Some("String") => Some(MemberType::Text),
+        _ => None,
+    }
+}
+
+fn visible(element: &Element) -> Option<String> {
+    element.attr("visible").filter(|visible| visible == "false")
+}
+
+fn annotations(element: &Element) -> Option<Vec<AnnotationConfigJson>> {
+    let annotations: Vec<_> = element.grandchildren("Annotations", "Annotation")
+        .filter_map(|ann| {
+            Some(AnnotationConfigJson {
+                name: ann.attr("name")?,
+                text: ann.text.trim().to_owned(),
+            })
+        })
+        .collect();
+
+    if annotations.is_empty() { None } else { Some(annotations) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Schema;
+
+    #[test]
+    fn test_mondrian3() {
+        let s = r##"<?xml version="1.0"?>
+            <Schema name="Sales">
+                <Dimension name="Store" type="StandardDimension">
+                    <Hierarchy hasAll="true" primaryKey="store_id">
+                        <Table name="store" schema="retail" />
+                        <Level name="Country" column="country_code" nameColumn="country_name" type="String" uniqueMembers="true" />
+                        <Level name="Store" column="store_id" nameColumn="store_name" type="Numeric">
+                            <Property name="Manager" column="store_manager" />
+                        </Level>
+                    </Hierarchy>
+                </Dimension>
+                <Cube name="Sales">
+                    <Table name="sales_fact" schema="retail" />
+                    <DimensionUsage name="Store" source="Store" foreignKey="store_id" />
+                    <Dimension name="Promotion" foreignKey="promotion_id">
+                        <Hierarchy hasAll="true" primaryKey="promotion_id">
+                            <Table name="promotion" />
+                            <Level name="Promotion" column="promotion_name" uniqueMembers="true" />
+                        </Hierarchy>
+                    </Dimension>
+                    <DimensionUsage name="Sales Store" source="Store" foreignKey="sales_store_id" />
+                    <Dimension name="Payment">
+                        <Hierarchy hasAll="true">
+                            <Level name="Payment" column="payment_type" type="String" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Unit Sales" column="unit_sales" aggregator="sum" formatString="Standard" />
+                    <Measure name="Sales Count" column="product_id" aggregator="count" formatString="#,###" />
+                    <CalculatedMember name="Profit" dimension="Measures">
+                        <Formula>[Measures].[Store Sales] - [Measures].[Store Cost]</Formula>
+                    </CalculatedMember>
+                    <Annotations>
+                        <Annotation name="source">Retail &amp; Co</Annotation>
+                    </Annotations>
+                </Cube>
+                <VirtualCube name="Warehouse and Sales">
+                    <CubeUsages>
+                        <CubeUsage cubeName="Sales" />
+                    </CubeUsages>
+                    <VirtualCubeMeasure cubeName="Sales" name="[Measures].[Unit Sales]" />
+                </VirtualCube>
+                <VirtualCube name="Sales Summary">
+                    <VirtualCubeDimension name="Store" />
+                    <VirtualCubeDimension cubeName="Sales" name="Payment" />
+                    <VirtualCubeMeasure cubeName="Sales" name="[Measures].[Unit Sales]" />
+                    <VirtualCubeMeasure cubeName="Sales" name="[Measures].[Sales Count]" />
+                </VirtualCube>
+            </Schema>
+        "##;
+
+        let schema_config = read_mondrian_schema(s).unwrap();
+        let cube = &schema_config.cubes[0];
+
+        assert_eq!(cube.table.schema, Some("retail".to_owned()));
+        assert_eq!(cube.dimension_usages.as_ref().unwrap().len(), 2);
+        assert_eq!(cube.measures[1].aggregator, Aggregator::Count);
+        assert_eq!(cube.measures[0].annotations.as_ref().unwrap()[0].text, "Standard");
+        assert_eq!(cube.annotations.as_ref().unwrap()[0].text, "Retail & Co");
+
+        let store = &schema_config.shared_dimensions.as_ref().unwrap()[0].hierarchies[0];
+        assert_eq!(store.name, "Store");
+        assert_eq!(store.levels[0].key_type, Some(MemberType::Text));
+        assert_eq!(store.levels[1].name_column, Some("store_name".to_owned()));
+        assert_eq!(store.levels[1].properties.as_ref().unwrap()[0].column, "store_manager");
+
+        // degenerate dimensions are joined on their key
+        let payment = &cube.dimensions.as_ref().unwrap()[1];
+        assert_eq!(payment.foreign_key, Some("payment_type".to_owned()));
+        assert_eq!(payment.hierarchies[0].table, None);
+
+        let virtual_cubes = schema_config.virtual_cubes.as_ref().unwrap();
+        assert_eq!(virtual_cubes[0].cube_usages, vec![CubeUsageJson { cube_name: "Sales".to_owned() }]);
+
+        // without cube usages, the cubes of the measures and dimensions are used
+        assert_eq!(virtual_cubes[1].cube_usages, vec![CubeUsageJson { cube_name: "Sales".to_owned() }]);
+
+        let mut schema: Schema = schema_config.into();
+        schema.validate().unwrap();
+
+        let unnamed = s.replace(r#"<VirtualCube name="Sales Summary">"#, "<VirtualCube>");
+        assert!(read_mondrian_schema(&unnamed).is_err());
+    }
+
+    #[test]
+    fn test_mondrian4() {
+        let s = r#"
+            <Schema name="Sales" metamodelVersion="4.0">
+                <PhysicalSchema>
+                    <Table name="sales_fact" />
+                    <Table name="store" schema="retail">
+                        <Key><Column name="store_id" /></Key>
+                    </Table>
+                </PhysicalSchema>
+                <Dimension name="Store" table="store" key="Store">
+                    <Attributes>
+                        <Attribute name="Country" keyColumn="country_code" datatype="String" />
+                        <Attribute name="Store" nameColumn="store_name" hasHierarchy="false">
+                            <Key><Column name="store_id" /></Key>
+                            <Property attribute="Manager" />
+                        </Attribute>
+                        <Attribute name="Manager" keyColumn="store_manager" hasHierarchy="false" />
+                    </Attributes>
+                    <Hierarchies>
+                        <Hierarchy name="Stores">
+                            <Level attribute="Country" />
+                            <Level attribute="Store" />
+                        </Hierarchy>
+                    </Hierarchies>
+                </Dimension>
+                <Cube name="Sales">
+                    <Dimensions>
+                        <Dimension source="Store" />
+                        <Dimension name="Payment" key="Payment">
+                            <Attributes>
+                                <Attribute name="Payment" keyColumn="payment_type" datatype="String" />
+                            </Attributes>
+                        </Dimension>
+                    </Dimensions>
+                    <MeasureGroups>
+                        <MeasureGroup name="Sales" table="sales_fact">
+                            <Measures>
+                                <Measure name="Unit Sales" column="unit_sales" aggregator="sum" formatString="Standard" />
+                            </Measures>
+                            <DimensionLinks>
+                                <ForeignKeyLink dimension="Store" foreignKeyColumn="store_id" />
+                                <FactLink dimension="Payment" />
+                            </DimensionLinks>
+                        </MeasureGroup>
+                    </MeasureGroups>
+                </Cube>
+            </Schema>
+        "#;
+
+        let schema_config = read_mondrian_schema(s).unwrap();
+        let cube = &schema_config.cubes[0];
+
+        assert_eq!(cube.table.name, "sales_fact");
+        assert_eq!(cube.dimension_usages.as_ref().unwrap()[0].foreign_key, "store_id");
+
+        let store = &schema_config.shared_dimensions.as_ref().unwrap()[0].hierarchies[0];
+        assert_eq!(store.name, "Stores");
+        assert_eq!(store.table.as_ref().unwrap().schema, Some("retail".to_owned()));
+        assert_eq!(store.primary_key, Some("store_id".to_owned()));
+        assert_eq!(store.levels[1].key_column, "store_id");
+        assert_eq!(store.levels[1].properties.as_ref().unwrap()[0].column, "store_manager");
+
+        let payment = &cube.dimensions.as_ref().unwrap()[0];
+        assert_eq!(payment.foreign_key, Some("payment_type".to_owned()));
+        assert_eq!(payment.hierarchies[0].table, None);
+
+        let mut schema: Schema = schema_config.into();
+        schema.validate().unwrap();
+    }
+
+    #[test]
+    fn test_mondrian_errors() {
+        let s = r#"
+            <Schema name="Sales">
+                <Cube name="Sales">
+                    <Table name="sales_fact" />
+                    <Measure name="Customers" column="customer_id" aggregator="distinct-count" />
+                </Cube>
+            </Schema>
+        "#;
+        let err = read_mondrian_schema(s).unwrap_err();
+        assert_eq!(err.to_string(), "Measure Customers: aggregator distinct-count is not supported");
+    }
+}
@@ -0,0 +1,426 @@
+//! Drafts a schema for a fact table from the columns in the database, to be
+//! reviewed and edited by hand.
+//!
+//! Id columns (`{name}_id`, `{name}_key` or `id_{name}`) of the fact table
+//! become dimensions. Their dimension table is the one given for the column,
+//! or else the first of `dim_{name}`, `{name}`, `dim_{name}s` and `{name}s`
+//! that exists. Id and text columns without a dimension table become
+//! dimensions in the fact table, date columns become time dimensions with
+//! virtual time levels, and the other numeric columns become sum measures.
+
+use failure::{Error, format_err};
+use futures::future::{self, Future};
+
+use crate::backend::Backend;
+use super::{Aggregator, DimensionType, Table, TimePart};
+use super::database::{column_types, is_date_type, is_numeric_type, is_text_type};
+use super::json::{
+    SchemaConfigJson,
+    CubeConfigJson,
+    DimensionConfigJson,
+    HierarchyConfigJson,
+    LevelConfigJson,
+    MeasureConfigJson,
+    PropertyConfigJson,
+    TableConfigJson,
+};
+use crate::query_ir::MemberType;
+
+
+/// A dimension table found for an id column of the fact table, with its
+/// columns and their database types.
+#[derive(Debug, Clone)]
+pub struct SkeletonDimension {
+    pub foreign_key: String,
+    pub table: Table,
+    pub columns: Vec<(String, String)>,
+}
+
+/// Queries the columns of the fact table and of its dimension tables, and
+/// drafts a schema with a single cube.
+///
+/// `dimension_tables` are the dimension tables of some fact table columns,
+/// for those that don't follow the naming conventions.
+pub fn schema_skeleton(
+    backend: &dyn Backend,
+    table: Table,
+    dimension_tables: Vec<(String, Table)>,
+    ) -> Box<dyn Future<Item=SchemaConfigJson, Error=Error>>
+{
+    let backend = backend.box_clone();
+
+    let fact_columns = backend.exec_sql(backend.table_columns_sql(&table))
+        .map(|df| column_types(&df));
+
+    let skeleton = fact_columns.and_then(move |columns| {
+        if columns.is_empty() {
+            return future::Either::A(future::err(format_err!("Table {} not found", table.full_name())));
+        }
+
+        // every candidate table of every id column is queried at once
+        let probes: Vec<_> = columns.iter()
+            .flat_map(|(column, _)| {
+                let given = dimension_tables.iter().find(|(fk, _)| fk == column);
+
+                let candidates = match given {
+                    Some((_, dim_table)) => vec![dim_table.clone()],
+                    None if is_id_column(column) => dimension_table_candidates(&table, column),
+                    None => vec![],
+                };
+
+                candidates.into_iter().map(move |dim_table| (column.clone(), dim_table))
+            })
+            .map(|(column, dim_table)| {
+                backend.exec_sql(backend.table_columns_sql(&dim_table))
+                    .map(move |df| SkeletonDimension {
+                        foreign_key: column,
+                        table: dim_table,
+                        columns: column_types(&df),
+                    })
+            })
+            .collect();
+
+        let schema_config = future::join_all(probes)
+            .and_then(move |found| {
+                let mut dimensions: Vec<SkeletonDimension> = vec![];
+
+                for dim in found.into_iter().filter(|dim| !dim.columns.is_empty()) {
+                    if !dimensions.iter().any(|d| d.foreign_key == dim.foreign_key) {
+                        dimensions.push(dim);
+                    }
+                }
+
+                for (column, dim_table) in &dimension_tables {
+                    if !dimensions.iter().any(|d| &d.foreign_key == column) {
+                        return Err(format_err!("Dimension table {} for column {} not found", dim_table.full_name(), column));
+                    }
+                }
+
+                Ok(SchemaConfigJson {
+                    name: table.name.clone(),
+                    includes: None,
+                    shared_dimensions: None,
+                    cubes: vec![cube_skeleton(&table, &columns, &dimensions)],
+                    virtual_cubes: None,
+                    annotations: None,
+                    default_locale: None,
+                })
+            });
+
+        future::Either::B(schema_config)
+    });
+
+    Box::new(skeleton)
+}
+
+/// Drafts a cube from the columns of its fact table, and the dimension
+/// tables found for its id columns.
+pub fn cube_skeleton(
+    table: &Table,
+    columns: &[(String, String)],
+    dimension_tables: &[SkeletonDimension],
+    ) -> CubeConfigJson
+{
+    let mut dimensions = vec![];
+    let mut measures = vec![];
+
+    for (column, db_type) in columns {
+        if let Some(dim) = dimension_tables.iter().find(|dim| &dim.foreign_key == column) {
+            dimensions.push(table_dimension(dim));
+        } else if is_id_column(column) || is_text_type(db_type) {
+            dimensions.push(fact_table_dimension(column, db_type));
+        } else if is_date_type(db_type) {
+            dimensions.push(time_dimension(column));
+        } else if is_numeric_type(db_type) && column != "id" {
+            measures.push(MeasureConfigJson {
+                name: title_case(column),
+                column: column.clone(),
+                aggregator: Aggregator::Sum,
+                measure_type: None,
+                annotations: None,
+            });
+        }
+    }
+
+    CubeConfigJson {
+        name: table.name.clone(),
+        public: None,
+        min_auth_level: None,
+        table: table_config(table),
+        dimensions: Some(dimensions),
+        dimension_usages: None,
+        measures,
+        annotations: None,
+        fiscal_year_start_month: None,
+        aggregate_tables: None,
+        location: None,
+    }
+}
+
+/// Tables that may be the dimension table of an id column, in the fact
+/// table's database schema.
+pub fn dimension_table_candidates(table: &Table, column: &str) -> Vec<Table> {
+    let stem = column_stem(column);
+
+    vec![
+        format!("dim_{}", stem),
+        stem.to_owned(),
+        format!("dim_{}s", stem),
+        format!("{}s", stem),
+    ]
+    .into_iter()
+    .filter(|name| name != &table.name)
+    .map(|name| Table { name, schema: table.schema.clone(), primary_key: None })
+    .collect()
+}
+
+fn table_dimension(dim: &SkeletonDimension) -> DimensionConfigJson {
+    let stem = column_stem(&dim.foreign_key);
+    let name = title_case(stem);
+    let has_column = |column: &str| dim.columns.iter().any(|(c, _)| c == column);
+
+    let primary_key = vec![dim.foreign_key.clone(), "id".to_owned()].into_iter()
+        .find(|column| has_column(column))
+        .unwrap_or_else(|| dim.columns[0].0.clone());
+
+    let key_type = dim.columns.iter()
+        .find(|(column, _)| column == &primary_key)
+        .and_then(|(_, db_type)| text_key_type(db_type));
+
+    let name_column = vec![format!("{}_name", stem), "name".to_owned(), "label".to_owned(), stem.to_owned()].into_iter()
+        .find(|column| column != &primary_key && has_column(column))
+        .or_else(|| {
+            dim.columns.iter()
+                .find(|(column, db_type)| column != &primary_key && is_text_type(db_type))
+                .map(|(column, _)| column.clone())
+        });
+
+    // the other text columns are likely captions or descriptions
+    let properties: Vec<_> = dim.columns.iter()
+        .filter(|(column, db_type)| {
+            column != &primary_key &&
+                Some(column) != name_column.as_ref() &&
+                is_text_type(db_type)
+        })
+        .map(|(column, _)| PropertyConfigJson {
+            name: title_case(column),
+            column: column.clone(),
+            caption_set: None,
+            annotations: None,
+        })
+        .collect();
+
+    let level = LevelConfigJson {
+        name: name.clone(),
+        key_column: primary_key.clone(),
+        name_column,
+        properties: if properties.is_empty() { None } else { Some(properties) },
+        key_type,
+        annotations: None,
+        time_part: None,
+        table: None,
+        primary_key: None,
+        foreign_key: None,
+    };
+
+    dimension(name, &dim.foreign_key, None, Some(table_config(&dim.table)), primary_key, vec![level])
+}
+
+/// Dimension of id or text values in the fact table itself.
+fn fact_table_dimension(column: &str, db_type: &str) -> DimensionConfigJson {
+    let name = title_case(column_stem(column));
+
+    let level = LevelConfigJson {
+        name: name.clone(),
+        key_column: column.to_owned(),
+        name_column: None,
+        properties: None,
+        key_type: text_key_type(db_type),
+        annotations: None,
+        time_part: None,
+        table: None,
+        primary_key: None,
+        foreign_key: None,
+    };
+
+    dimension(name, column, None, None, column.to_owned(), vec![level])
+}
+
+fn time_dimension(column: &str) -> DimensionConfigJson {
+    let levels = vec![
+        ("Year", "year", TimePart::Year),
+        ("Quarter", "quarter", TimePart::Quarter),
+        ("Month", "month", TimePart::Month),
+        ("Day", "day", TimePart::Day),
+    ];
+
+    let levels: Vec<_> = levels.into_iter()
+        .map(|(name, key_column, time_part)| LevelConfigJson {
+            name: name.to_owned(),
+            key_column: key_column.to_owned(),
+            name_column: None,
+            properties: None,
+            key_type: None,
+            annotations: None,
+            time_part: Some(time_part),
+            table: None,
+            primary_key: None,
+            foreign_key: None,
+        })
+        .collect();
+
+    dimension(title_case(column), column, Some(DimensionType::Time), None, column.to_owned(), levels)
+}
+
+fn dimension(
+    name: String,
+    foreign_key: &str,
+    dim_type: Option<DimensionType>,
+    table: Option<TableConfigJson>,
+    primary_key: String,
+    levels: Vec<LevelConfigJson>,
+    ) -> DimensionConfigJson
+{
+    // virtual time levels have no primary key
+    let primary_key = if dim_type.is_some() { None } else { Some(primary_key) };
+
+    DimensionConfigJson {
+        name: name.clone(),
+        foreign_key: Some(foreign_key.to_owned()),
+        hierarchies: vec![HierarchyConfigJson {
+            name,
+            table,
+            primary_key,
+            levels,
+            annotations: None,
+            inline_table: None,
+            default_member: None,
+            parent_column: None,
+            closure: None,
+            max_depth: None,
+        }],
+        default_hierarchy: None,
+        dim_type,
+        annotations: None,
+    }
+}
+
+fn table_config(table: &Table) -> TableConfigJson {
+    TableConfigJson {
+        name: table.name.clone(),
+        schema: table.schema.clone(),
+        primary_key: None,
+    }
+}
+
+fn text_key_type(db_type: &str) -> Option<MemberType> {
+    if is_text_type(db_type) {
+        Some(MemberType::Text)
+    } else {
+        None
+    }
+}
+
+fn is_id_column(column: &str) -> bool {
+    column_stem(column) != column
+}
+
+/// Column name without its id suffix or prefix, e.g. `product` for
+/// `product_id`.
+fn column_stem(column: &str) -> &str {
+    if column.ends_with("_id") || column.ends_with("_key") {
+        &column[..column.rfind('_').unwrap()]
+    } else if column.starts_with("id_") && column.len() > 3 {
+        &column[3..]
+    } else {
+        column
+    }
+}
+
+/// `product_category` becomes `Product Category`.
+fn title_case(column: &str) -> String {
+    column.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Schema;
+    use crate::schema::write::schema_config_to_xml;
+
+    fn columns(columns: &[(&str, &str)]) -> Vec<(String, String)> {
+        columns.iter().map(|(c, t)| (c.to_string(), t.to_string())).collect()
+    }
+
+    #[test]
+    fn test_cube_skeleton() {
+        let table = Table { name: "sales".to_owned(), schema: None, primary_key: None };
+        let fact_columns = columns(&[
+            ("id", "UInt64"),
+            ("product_id", "UInt32"),
+            ("channel_code", "String"),
+            ("store_id", "UInt32"),
+            ("sale_date", "Date"),
+            ("quantity", "UInt32"),
+            ("amount", "Nullable(Float64)"),
+        ]);
+        let dims = vec![SkeletonDimension {
+            foreign_key: "product_id".to_owned(),
+            table: Table { name: "dim_product".to_owned(), schema: None, primary_key: None },
+            columns: columns(&[
+                ("product_id", "UInt32"),
+                ("product_name", "String"),
+                ("description", "String"),
+                ("price", "Float64"),
+            ]),
+        }];
+
+        let cube = cube_skeleton(&table, &fact_columns, &dims);
+
+        let dim_names: Vec<_> = cube.dimensions.iter().flat_map(|dims| dims.iter()).map(|d| d.name.as_str()).collect();
+        assert_eq!(dim_names, vec!["Product", "Channel Code", "Store", "Sale Date"]);
+
+        let mea_names: Vec<_> = cube.measures.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(mea_names, vec!["Quantity", "Amount"]);
+
+        let product = &cube.dimensions.as_ref().unwrap()[0].hierarchies[0];
+        assert_eq!(product.primary_key, Some("product_id".to_owned()));
+        assert_eq!(product.levels[0].name_column, Some("product_name".to_owned()));
+        assert_eq!(product.levels[0].properties.as_ref().unwrap()[0].column, "description");
+
+        let channel = &cube.dimensions.as_ref().unwrap()[1].hierarchies[0];
+        assert_eq!(channel.table, None);
+        assert_eq!(channel.levels[0].key_type, Some(MemberType::Text));
+
+        // the draft is a valid schema
+        let schema_config = SchemaConfigJson {
+            name: "sales".to_owned(),
+            includes: None,
+            shared_dimensions: None,
+            cubes: vec![cube],
+            virtual_cubes: None,
+            annotations: None,
+            default_locale: None,
+        };
+        let mut schema = Schema::from_xml(&schema_config_to_xml(&schema_config).unwrap()).unwrap();
+        schema.validate().unwrap();
+    }
+
+    #[test]
+    fn test_dimension_table_candidates() {
+        let table = Table { name: "sales".to_owned(), schema: Some("retail".to_owned()), primary_key: None };
+
+        let names: Vec<_> = dimension_table_candidates(&table, "id_store").iter().map(|t| t.full_name()).collect();
+        assert_eq!(names, vec!["retail.dim_store", "retail.store", "retail.dim_stores", "retail.stores"]);
+    }
+}
@@ -0,0 +1,479 @@
+//! Schema validation collects every problem in a schema, each with the path
+//! of the schema element that has it, e.g.
+//! `cubes[sales].dimensions[Geography].hierarchies[Geography]`. Problems in
+//! schemas read from files also have the file and line of their cube or
+//! shared dimension.
+
+use failure::Fail;
+use serde_derive::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::names::Cut;
+use super::{Aggregator, Cube, Dimension, DimensionType, Hierarchy, InlineTable, MeasureType, Schema};
+
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaDiagnostic {
+    pub location: Option<String>,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(ref location) => write!(f, "{} {}: {}", location, self.path, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+/// All the problems found by `Schema::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaValidationError {
+    pub diagnostics: Vec<SchemaDiagnostic>,
+}
+
+impl fmt::Display for SchemaValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Schema has {} problem(s):", self.diagnostics.len())?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n- {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for SchemaValidationError {}
+
+/// Collects diagnostics under the path of the current schema element, and
+/// the location of the cube or shared dimension being checked.
+struct Diagnostics {
+    diagnostics: Vec<SchemaDiagnostic>,
+    location: Option<String>,
+}
+
+impl Diagnostics {
+    fn push<S: Into<String>>(&mut self, path: &str, message: S) {
+        self.diagnostics.push(SchemaDiagnostic {
+            location: self.location.clone(),
+            path: path.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    /// Reports each name that appears more than once.
+    fn check_duplicates<'a, I>(&mut self, path: &str, kind: &str, names: I)
+        where I: Iterator<Item=&'a String>
+    {
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+
+        for name in names {
+            if !seen.insert(name) && reported.insert(name) {
+                self.push(path, format!("Duplicate {} name {}", kind, name));
+            }
+        }
+    }
+}
+
+pub(crate) fn schema_diagnostics(schema: &Schema) -> Vec<SchemaDiagnostic> {
+    let mut diagnostics = Diagnostics { diagnostics: vec![], location: None };
+
+    diagnostics.check_duplicates("schema", "cube", schema.cubes.iter().map(|cube| &cube.name));
+    diagnostics.check_duplicates("schema", "shared dimension", schema.shared_dimensions.iter().map(|dim| &dim.name));
+
+    // shared dimensions are checked once, and not in each cube that uses them
+    for dim in &schema.shared_dimensions {
+        diagnostics.location = dim.location.clone();
+        check_dimension(dim, &format!("shared_dimensions[{}]", dim.name), &mut diagnostics);
+    }
+
+    for cube in &schema.cubes {
+        diagnostics.location = cube.location.clone();
+        check_cube(schema, cube, &mut diagnostics);
+    }
+
+    diagnostics.diagnostics
+}
+
+fn check_cube(schema: &Schema, cube: &Cube, diagnostics: &mut Diagnostics) {
+    let path = format!("cubes[{}]", cube.name);
+
+    // Virtual cubes combine physical cubes from this schema
+    if let Some(ref cube_usages) = cube.cube_usages {
+        if cube_usages.is_empty() {
+            diagnostics.push(&path, "Virtual cube must use at least one cube");
+        }
+
+        for usage in cube_usages {
+            match schema.cubes.iter().find(|c| &c.name == usage) {
+                Some(c) if c.cube_usages.is_none() => {
+                    // queries read the cube, so it can't be opened up through
+                    // a virtual cube
+                    if cube.min_auth_level < c.min_auth_level {
+                        diagnostics.push(&path, format!("Min auth level {} is below {}, the min auth level of cube {}", cube.min_auth_level, c.min_auth_level, usage));
+                    }
+
+                    // drilldowns and cuts are on dimensions that every cube has
+                    for dim in &cube.dimensions {
+                        let is_shared = c.dimensions.iter()
+                            .any(|d| d.is_shared && d.name == dim.name && d.hierarchies == dim.hierarchies);
+
+                        if !is_shared {
+                            diagnostics.push(&format!("{}.dimensions[{}]", path, dim.name), format!("Dimension {} is not a shared dimension of cube {}", dim.name, usage));
+                        }
+                    }
+                },
+                Some(_) => diagnostics.push(&path, format!("Virtual cube can't use virtual cube {}", usage)),
+                None => diagnostics.push(&path, format!("Cube {} not found", usage)),
+            }
+        }
+    }
+
+    for source in &cube.missing_shared_dimensions {
+        diagnostics.push(&format!("{}.dimension_usages[{}]", path, source), format!("Shared dimension {} not found", source));
+    }
+
+    // There should be at least one dimension. Both dim and shared dim are optional,
+    // so need to do a validation check here.
+    if cube.dimensions.is_empty() {
+        diagnostics.push(&path, "Between Dimensions and Shared Dimensions, a cube must have a total of at least 1.");
+    }
+
+    if let Some(month) = cube.fiscal_year_start_month {
+        if month < 1 || month > 12 {
+            diagnostics.push(&path, "Fiscal year start month must be between 1 and 12");
+        }
+    }
+
+    diagnostics.check_duplicates(&path, "dimension", cube.dimensions.iter().map(|dim| &dim.name));
+    diagnostics.check_duplicates(&path, "measure", cube.measures.iter().map(|mea| &mea.name));
+
+    // the dimensions of virtual cubes are checked in their cubes
+    if cube.cube_usages.is_none() {
+        for dim in &cube.dimensions {
+            let dim_path = format!("{}.dimensions[{}]", path, dim.name);
+
+            if !dim.is_shared {
+                check_dimension(dim, &dim_path, diagnostics);
+            }
+            for hier in &dim.hierarchies {
+                check_hierarchy_in_cube(cube, dim, hier, &format!("{}.hierarchies[{}]", dim_path, hier.name), diagnostics);
+            }
+        }
+    }
+
+    for mea in &cube.measures {
+        if let MeasureType::Error { ref for_measure, .. } = mea.measure_type {
+            if !cube.measures.iter().any(|m| &m.name == for_measure) {
+                diagnostics.push(&format!("{}.measures[{}]", path, mea.name), format!("Error measure is for measure {}, which is not found", for_measure));
+            }
+        }
+    }
+
+    // Aggregate tables are re-aggregated, so their measures must have
+    // aggregators that roll up, and their levels must be in the cube.
+    for agg in &cube.aggregate_tables {
+        let agg_path = format!("{}.aggregate_tables[{}]", path, agg.table.name);
+
+        for agg_level in &agg.levels {
+            let hier = cube.dimensions.iter()
+                .find(|dim| dim.name == agg_level.dimension)
+                .and_then(|dim| dim.hierarchies.iter().find(|hier| hier.name == agg_level.hierarchy));
+            let level = hier.and_then(|hier| hier.levels.iter().find(|level| level.name == agg_level.level));
+
+            match (hier, level) {
+                (Some(hier), Some(level)) => {
+                    if level.name_column.is_some() != agg_level.name_column.is_some() {
+                        diagnostics.push(&agg_path, format!("Level {} must have a name column only if it has one in the cube", agg_level.level));
+                    }
+                    if hier.parent_column.is_some() {
+                        diagnostics.push(&agg_path, format!("Parent-child level {} can't be aggregated", agg_level.level));
+                    }
+                },
+                _ => diagnostics.push(&agg_path, format!("Level {}.{}.{} not found", agg_level.dimension, agg_level.hierarchy, agg_level.level)),
+            }
+        }
+
+        for agg_mea in &agg.measures {
+            match cube.measures.iter().find(|mea| mea.name == agg_mea.name) {
+                Some(mea) => match mea.aggregator {
+                    Aggregator::Sum | Aggregator::Count | Aggregator::Max | Aggregator::Min => (),
+                    _ => diagnostics.push(&agg_path, format!("Measure {} can't be re-aggregated; only sum, count, max and min can", mea.name)),
+                },
+                None => diagnostics.push(&agg_path, format!("Measure {} not found", agg_mea.name)),
+            }
+        }
+    }
+}
+
+fn check_dimension(dim: &Dimension, path: &str, diagnostics: &mut Diagnostics) {
+    diagnostics.check_duplicates(path, "hierarchy", dim.hierarchies.iter().map(|hier| &hier.name));
+
+    // if there's multiple hierarchies in a dim, there must be a default hierarchy.
+    // also, the default hierarchy must match names with an actual hierarchy.
+    //
+    // This means that later, we can just check whether there is a default
+    // hierarchy only, instead of also checking for hierarchy cardinality during
+    // a request
+    if dim.hierarchies.len() > 1 {
+        match dim.default_hierarchy {
+            Some(ref default_hierarchy) => {
+                if !dim.hierarchies.iter().any(|hier| &hier.name == default_hierarchy) {
+                    diagnostics.push(path, format!("Default hierarchy {} must exist in multiple hierarchies", default_hierarchy));
+                }
+            },
+            None => diagnostics.push(path, "Default hierarchy required for multiple hierarchies"),
+        }
+    }
+
+    for hier in &dim.hierarchies {
+        check_hierarchy(dim, hier, &format!("{}.hierarchies[{}]", path, hier.name), diagnostics);
+    }
+}
+
+fn check_hierarchy(dim: &Dimension, hier: &Hierarchy, path: &str, diagnostics: &mut Diagnostics) {
+    diagnostics.check_duplicates(path, "level", hier.levels.iter().map(|level| &level.name));
+
+    if hier.levels.is_empty() {
+        diagnostics.push(path, "Hierarchy must have at least one level");
+    }
+
+    if hier.table.is_some() && hier.inline_table.is_some() {
+        diagnostics.push(path, "Can't have table and inline table definitions in the same hierarchy");
+    }
+
+    if let Some(ref inline_table) = hier.inline_table {
+        check_inline_table(inline_table, &format!("{}.inline_table", path), diagnostics);
+    }
+
+    // default members are cuts on a level of the hierarchy
+    if let Some(ref default_member) = hier.default_member {
+        let cut = if default_member.is_empty() {
+            Err(failure::format_err!("empty cut"))
+        } else {
+            default_member.parse::<Cut>()
+        };
+
+        match cut {
+            Ok(cut) => {
+                let in_hierarchy = cut.level_name.dimension == dim.name &&
+                    cut.level_name.hierarchy == hier.name &&
+                    hier.levels.iter().any(|level| level.name == cut.level_name.level);
+
+                if !in_hierarchy {
+                    diagnostics.push(path, format!("Default member {} is not on a level of the hierarchy", default_member));
+                }
+            },
+            Err(err) => diagnostics.push(path, format!("Default member {} can't be parsed: {}", default_member, err)),
+        }
+    }
+
+    // Virtual time levels are derived from the date column that is the
+    // foreign key of a time dimension, so their hierarchy has no table,
+    // and they have no labels or properties.
+    let num_virtual = hier.levels.iter()
+        .filter(|level| level.time_part.is_some())
+        .count();
+
+    if num_virtual != 0 {
+        if num_virtual != hier.levels.len() {
+            diagnostics.push(path, "Virtual time levels can't be mixed with other levels");
+        }
+        if dim.dim_type != DimensionType::Time {
+            diagnostics.push(path, "Virtual time levels must be in a time dimension");
+        }
+        if hier.table.is_some() || hier.inline_table.is_some() {
+            diagnostics.push(path, "Virtual time levels can't have a table");
+        }
+
+        let has_labels = hier.levels.iter()
+            .any(|level| level.name_column.is_some() || level.properties.is_some());
+
+        if has_labels {
+            diagnostics.push(path, "Virtual time levels can't have a name column or properties");
+        }
+    }
+
+    // Parent-child hierarchies have a single level, whose members are rows
+    // of the dimension table that reference their parent row.
+    if hier.parent_column.is_none() {
+        if hier.closure.is_some() || hier.max_depth.is_some() {
+            diagnostics.push(path, "Closure table and max depth require a parent column");
+        }
+    } else {
+        if hier.levels.len() != 1 {
+            diagnostics.push(path, "Parent-child hierarchies must have exactly one level");
+        }
+        if hier.inline_table.is_some() {
+            diagnostics.push(path, "Parent-child hierarchies can't have an inline table");
+        }
+
+        if hier.table.is_none() {
+            diagnostics.push(path, "Parent-child hierarchies need a dimension table");
+        }
+    }
+
+    // Snowflake levels are joined to the hierarchy table, which has the
+    // bottom level.
+    if hier.levels.iter().any(|level| level.table.is_some()) {
+        if hier.table.is_none() || hier.inline_table.is_some() {
+            diagnostics.push(path, "Levels with a table need a hierarchy table");
+        }
+        if hier.parent_column.is_some() {
+            diagnostics.push(path, "Parent-child hierarchies can't have levels with a table");
+        }
+    }
+}
+
+/// Checks that depend on the cube that has the hierarchy, which for shared
+/// dimensions are made for each cube that uses them.
+fn check_hierarchy_in_cube(cube: &Cube, dim: &Dimension, hier: &Hierarchy, path: &str, diagnostics: &mut Diagnostics) {
+    if hier.levels.iter().any(|level| level.time_part.is_some()) && dim.foreign_key.is_none() {
+        diagnostics.push(path, "Virtual time levels need the date column as foreign key");
+    }
+
+    if hier.parent_column.is_some() {
+        if let Some(ref table) = hier.table {
+            if table.name == cube.table.name {
+                diagnostics.push(path, "Parent-child hierarchies need a dimension table, not the fact table");
+            }
+        }
+    }
+}
+
+/// Each row of an inline table has a value for each of its columns.
+fn check_inline_table(inline_table: &InlineTable, path: &str, diagnostics: &mut Diagnostics) {
+    diagnostics.check_duplicates(path, "column", inline_table.column_definitions.iter().map(|col_def| &col_def.name));
+
+    for (i, row) in inline_table.rows.iter().enumerate() {
+        let row_path = format!("{}.rows[{}]", path, i);
+
+        diagnostics.check_duplicates(&row_path, "column", row.row_values.iter().map(|value| &value.column));
+
+        for value in &row.row_values {
+            if !inline_table.column_definitions.iter().any(|col_def| col_def.name == value.column) {
+                diagnostics.push(&row_path, format!("Column {} is not defined", value.column));
+            }
+        }
+        for col_def in &inline_table.column_definitions {
+            if !row.row_values.iter().any(|value| value.column == col_def.name) {
+                diagnostics.push(&row_path, format!("Column {} has no value", col_def.name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics() {
+        let s = r##"
+            <Schema name="my_schema">
+                <SharedDimension name="Geography">
+                    <Hierarchy name="Geography" primary_key="state_id" default_member="Geography.Geography.County.1">
+                        <Table name="dim_geo" />
+                        <Level name="State" key_column="state_id" />
+                        <Level name="State" key_column="state_name" />
+                    </Hierarchy>
+                </SharedDimension>
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <DimensionUsage source="Geography" foreign_key="state_id" />
+                    <DimensionUsage source="Geo" foreign_key="state_id" />
+                    <Dimension name="Product" foreign_key="product_id">
+                        <Hierarchy name="Product" primary_key="product_id">
+                            <InlineTable alias="products">
+                                <ColumnDef name="product_id" key_type="nontext" />
+                                <ColumnDef name="product_name" key_type="text" />
+                                <Row>
+                                    <Value column="product_id">1</Value>
+                                    <Value column="product_label">Chair</Value>
+                                </Row>
+                            </InlineTable>
+                            <Level name="Product" key_column="product_id" name_column="product_name" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                    <Measure name="Sales" column="sales_total" aggregator="sum" />
+                    <Measure name="Sales MOE" column="sales_moe" aggregator="sum" />
+                </Cube>
+                <Cube name="sales">
+                    <Table name="sales_table" />
+                    <DimensionUsage source="Geography" foreign_key="state_id" />
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema = Schema::from_xml(s).unwrap();
+        schema.cubes[0].measures[2].measure_type = MeasureType::Error {
+            for_measure: "Sale".to_owned(),
+            err_type: "moe".to_owned(),
+        };
+
+        let err = schema.validate().unwrap_err();
+        let diagnostics = err.downcast::<SchemaValidationError>().unwrap().diagnostics;
+        let diagnostics: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+
+        // the shared dimension is reported once, not for each cube using it
+        let geo_path = "shared_dimensions[Geography].hierarchies[Geography]";
+        assert_eq!(diagnostics, vec![
+            "schema: Duplicate cube name sales".to_owned(),
+            format!("{}: Duplicate level name State", geo_path),
+            format!("{}: Default member Geography.Geography.County.1 is not on a level of the hierarchy", geo_path),
+            "cubes[sales].dimension_usages[Geo]: Shared dimension Geo not found".to_owned(),
+            "cubes[sales]: Duplicate measure name Sales".to_owned(),
+            "cubes[sales].dimensions[Product].hierarchies[Product].inline_table.rows[0]: Column product_label is not defined".to_owned(),
+            "cubes[sales].dimensions[Product].hierarchies[Product].inline_table.rows[0]: Column product_name has no value".to_owned(),
+            "cubes[sales].measures[Sales MOE]: Error measure is for measure Sale, which is not found".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn test_virtual_cube_diagnostics() {
+        let s = r##"
+            <Schema name="my_schema">
+                <SharedDimension name="Geography">
+                    <Hierarchy name="Geography" primary_key="state_id">
+                        <Table name="dim_geo" />
+                        <Level name="State" key_column="state_id" />
+                    </Hierarchy>
+                </SharedDimension>
+                <Cube name="sales" min_auth_level="2">
+                    <Table name="sales_table" />
+                    <DimensionUsage source="Geography" foreign_key="state_id" />
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                </Cube>
+                <Cube name="returns">
+                    <Table name="returns_table" />
+                    <DimensionUsage source="Geography" foreign_key="state_id" />
+                    <Measure name="Returns" column="returns" aggregator="sum" />
+                </Cube>
+                <VirtualCube name="sales_returns" min_auth_level="1">
+                    <CubeUsage cube_name="sales" />
+                    <CubeUsage cube_name="returns" />
+                </VirtualCube>
+            </Schema>
+        "##;
+        let mut schema = Schema::from_xml(s).unwrap();
+
+        // a dimension that only one of the cubes has
+        let mut dim = schema.cubes[2].dimensions[0].clone();
+        dim.name = "Store".to_owned();
+        schema.cubes[0].dimensions.push(dim.clone());
+        schema.cubes[2].dimensions.push(dim);
+
+        let err = schema.validate().unwrap_err();
+        let diagnostics = err.downcast::<SchemaValidationError>().unwrap().diagnostics;
+        let diagnostics: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+
+        assert_eq!(diagnostics, vec![
+            "cubes[sales_returns]: Min auth level 1 is below 2, the min auth level of cube sales".to_owned(),
+            "cubes[sales_returns].dimensions[Store]: Dimension Store is not a shared dimension of cube returns".to_owned(),
+        ]);
+    }
+}
@@ -0,0 +1,239 @@
+//! Writes a `SchemaConfigJson` out as a JSON or XML schema file, for
+//! schemas that are generated or converted instead of written by hand.
+//!
+//! XML is written from the JSON value of the config: scalar fields become
+//! attributes, and list or struct fields become child elements, named as in
+//! xml.rs. Other fields, like `weighted_avg` aggregators, become a child
+//! element named after the field, e.g.
+//! `<aggregator><weighted_avg weight_column="population" /></aggregator>`.
+
+use failure::{Error, bail};
+use serde_json::{Map, Value};
+
+use super::json::SchemaConfigJson;
+
+
+/// Pretty-printed JSON schema, leaving out empty optional fields.
+pub fn schema_config_to_json(schema_config: &SchemaConfigJson) -> Result<String, Error> {
+    let mut value = serde_json::to_value(schema_config)?;
+    strip_nulls(&mut value);
+
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+/// XML schema, indented by four spaces.
+pub fn schema_config_to_xml(schema_config: &SchemaConfigJson) -> Result<String, Error> {
+    let mut value = serde_json::to_value(schema_config)?;
+    strip_nulls(&mut value);
+
+    let mut xml = String::new();
+    write_element(&mut xml, "Schema", &value, 0)?;
+
+    Ok(xml)
+}
+
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            // rebuilt instead of removing keys, which would reorder fields
+            *map = std::mem::replace(map, Map::new())
+                .into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .collect();
+            for v in map.values_mut() {
+                strip_nulls(v);
+            }
+        },
+        Value::Array(values) => {
+            for v in values.iter_mut() {
+                strip_nulls(v);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Element name of the items of a list or struct field.
+fn child_element_name(element: &str, key: &str) -> Option<&'static str> {
+    let name = match (element, key) {
+        ("AggregateTable", "levels") => "AggregateLevel",
+        ("AggregateTable", "measures") => "AggregateMeasure",
+        (_, "includes") => "Include",
+        (_, "shared_dimensions") => "SharedDimension",
+        (_, "cubes") => "Cube",
+        (_, "virtual_cubes") => "VirtualCube",
+        (_, "cube_usages") => "CubeUsage",
+        (_, "annotations") => "Annotation",
+        (_, "table") => "Table",
+        (_, "dimensions") => "Dimension",
+        (_, "dimension_usages") => "DimensionUsage",
+        (_, "measures") => "Measure",
+        (_, "aggregate_tables") => "AggregateTable",
+        (_, "hierarchies") => "Hierarchy",
+        (_, "levels") => "Level",
+        (_, "properties") => "Property",
+        (_, "inline_table") => "InlineTable",
+        (_, "column_definitions") => "ColumnDef",
+        (_, "rows") => "Row",
+        (_, "row_values") => "Value",
+        (_, "closure") => "Closure",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Field that is the text of an element instead of an attribute.
+fn is_text_field(element: &str, key: &str) -> bool {
+    match (element, key) {
+        ("Annotation", "text") | ("Value", "value") => true,
+        _ => false,
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn write_element(xml: &mut String, element: &str, value: &Value, depth: usize) -> Result<(), Error> {
+    let indent = "    ".repeat(depth);
+    let fields = match value {
+        Value::Object(fields) => fields,
+        // items of a list of scalars, e.g. secondary columns
+        _ => match scalar(value) {
+            Some(text) => {
+                xml.push_str(&format!("{}<{}>{}</{}>\n", indent, element, escape(&text), element));
+                return Ok(());
+            },
+            None => bail!("{} can't be written as XML", element),
+        },
+    };
+
+    let mut attributes = String::new();
+    let mut text = None;
+    let mut children = vec![];
+
+    for (key, field) in fields {
+        match scalar(field) {
+            Some(scalar) => {
+                if is_text_field(element, key) {
+                    text = Some(scalar);
+                } else {
+                    attributes.push_str(&format!(" {}=\"{}\"", key, escape(&scalar)));
+                }
+            },
+            None => {
+                let child = child_element_name(element, key).unwrap_or(key.as_str());
+
+                match field {
+                    Value::Array(items) => children.extend(items.iter().map(|item| (child, item))),
+                    _ => children.push((child, field)),
+                }
+            },
+        }
+    }
+
+    if let Some(text) = text {
+        xml.push_str(&format!("{}<{}{}>{}</{}>\n", indent, element, attributes, escape(&text), element));
+    } else if children.is_empty() {
+        xml.push_str(&format!("{}<{}{} />\n", indent, element, attributes));
+    } else {
+        xml.push_str(&format!("{}<{}{}>\n", indent, element, attributes));
+        for (child, item) in children {
+            write_element(xml, child, item, depth + 1)?;
+        }
+        xml.push_str(&format!("{}</{}>\n", indent, element));
+    }
+
+    Ok(())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Schema;
+    use crate::schema::xml::SchemaConfigXML;
+    use serde_xml_rs::from_reader;
+
+    const SCHEMA_XML: &str = r#"<Schema name="my_schema">
+    <Cube name="sales">
+        <Table name="sales_table" schema="public" />
+        <Dimension name="Geography" foreign_key="state_id">
+            <Hierarchy name="Geography" primary_key="state_id">
+                <Table name="dim_geo" />
+                <Level name="State" key_column="state_id" name_column="state_name" key_type="text">
+                    <Property name="Abbreviation" column="state_abbr" />
+                </Level>
+            </Hierarchy>
+        </Dimension>
+        <Measure name="Sales" column="sales" aggregator="sum">
+            <Annotation name="units">USD &amp; cents</Annotation>
+        </Measure>
+    </Cube>
+</Schema>
+"#;
+
+    fn read_xml(raw: &str) -> SchemaConfigJson {
+        let schema_config: SchemaConfigXML = from_reader(raw.as_bytes()).unwrap();
+        let serialized = serde_json::to_string(&schema_config).unwrap();
+        serde_json::from_str(&serialized).unwrap()
+    }
+
+    #[test]
+    fn test_write_xml() {
+        let schema_config = read_xml(SCHEMA_XML);
+        let xml = schema_config_to_xml(&schema_config).unwrap();
+
+        assert_eq!(xml, SCHEMA_XML);
+        assert!(Schema::from_xml(&xml).is_ok());
+    }
+
+    #[test]
+    fn test_write_xml_enum_fields() {
+        let schema_config: SchemaConfigJson = serde_json::from_str(r#"{
+            "name": "my_schema",
+            "cubes": [{
+                "name": "income",
+                "table": { "name": "income_table" },
+                "dimensions": [{
+                    "name": "Year",
+                    "foreign_key": "year",
+                    "hierarchies": [{ "name": "Year", "primary_key": "year", "levels": [{ "name": "Year", "key_column": "year" }] }]
+                }],
+                "measures": [
+                    { "name": "Income", "column": "income", "aggregator": { "weighted_avg": { "weight_column": "population" } }, "type": { "standard": { "units": "USD" } } },
+                    { "name": "Income MOE", "column": "income", "aggregator": { "replicate_weight_moe": { "critical_value": 1.645, "design_factor": 0.05, "secondary_columns": ["rw1", "rw2"] } } }
+                ]
+            }]
+        }"#).unwrap();
+
+        let xml = schema_config_to_xml(&schema_config).unwrap();
+        assert!(xml.contains(r#"<aggregator>
+                <weighted_avg weight_column="population" />
+            </aggregator>"#));
+        assert!(xml.contains("<secondary_columns>rw1</secondary_columns>"));
+
+        assert_eq!(read_xml(&xml), schema_config);
+    }
+
+    #[test]
+    fn test_write_json() {
+        let schema_config = read_xml(SCHEMA_XML);
+        let json = schema_config_to_json(&schema_config).unwrap();
+
+        assert!(!json.contains("null"));
+        assert_eq!(serde_json::from_str::<SchemaConfigJson>(&json).unwrap(), schema_config);
+    }
+}
@@ -14,21 +14,47 @@ use serde_derive::Serialize;
 
 use crate::query_ir::MemberType;
 use super::aggregator::Aggregator;
-use super::{DimensionType, MeasureType};
+use super::{DimensionType, MeasureType, TimePart};
 
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct SchemaConfigXML {
     pub name: String,
+    #[serde(rename(deserialize="Include"))]
+    pub includes: Option<Vec<IncludeConfigXML>>,
     #[serde(rename(deserialize="SharedDimension"))]
     pub shared_dimensions: Option<Vec<SharedDimensionConfigXML>>,
-    #[serde(rename(deserialize="Cube"))]
+    #[serde(rename(deserialize="Cube"), default)]
     pub cubes: Vec<CubeConfigXML>,
+    #[serde(rename(deserialize="VirtualCube"))]
+    pub virtual_cubes: Option<Vec<VirtualCubeConfigXML>>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
     pub default_locale: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct IncludeConfigXML {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct VirtualCubeConfigXML {
+    pub name: String,
+    pub public: Option<String>,
+    #[serde(rename(deserialize="min_auth_level"))]
+    pub min_auth_level: Option<i32>,
+    #[serde(rename(deserialize="CubeUsage"))]
+    pub cube_usages: Vec<CubeUsageXML>,
+    #[serde(rename(deserialize="Annotation"))]
+    pub annotations: Option<Vec<AnnotationConfigXML>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CubeUsageXML {
+    pub cube_name: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct CubeConfigXML {
     pub name: String,
@@ -45,6 +71,35 @@ pub struct CubeConfigXML {
     pub measures: Vec<MeasureConfigXML>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    pub fiscal_year_start_month: Option<u32>,
+    #[serde(rename(deserialize="AggregateTable"))]
+    pub aggregate_tables: Option<Vec<AggregateTableConfigXML>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AggregateTableConfigXML {
+    #[serde(rename(deserialize="Table"))]
+    pub table: TableConfigXML,
+    pub rows: Option<u64>,
+    #[serde(rename(deserialize="AggregateLevel"))]
+    pub levels: Vec<AggregateLevelConfigXML>,
+    #[serde(rename(deserialize="AggregateMeasure"))]
+    pub measures: Vec<AggregateMeasureConfigXML>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AggregateLevelConfigXML {
+    pub dimension: String,
+    pub hierarchy: String,
+    pub level: String,
+    pub key_column: String,
+    pub name_column: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AggregateMeasureConfigXML {
+    pub name: String,
+    pub column: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -94,6 +149,18 @@ pub struct HierarchyConfigXML {
     #[serde(rename(deserialize="InlineTable"))]
     pub inline_table: Option<InlineTableXML>,
     pub default_member: Option<String>,
+    pub parent_column: Option<String>,
+    #[serde(rename(deserialize="Closure"))]
+    pub closure: Option<ClosureConfigXML>,
+    pub max_depth: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClosureConfigXML {
+    #[serde(rename(deserialize="Table"))]
+    pub table: TableConfigXML,
+    pub parent_column: String,
+    pub child_column: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -136,6 +203,11 @@ pub struct LevelConfigXML {
     pub key_type: Option<MemberType>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    pub time_part: Option<TimePart>,
+    #[serde(rename(deserialize="Table"))]
+    pub table: Option<TableConfigXML>,
+    pub primary_key: Option<String>,
+    pub foreign_key: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
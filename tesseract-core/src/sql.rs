@@ -1,7 +1,10 @@
+use failure::{Error, bail};
 use itertools::join;
 
 use crate::Aggregator;
+use crate::schema::{Table, TimePart};
 use crate::query_ir::{
+    QueryIr,
     TableSql,
     CutSql,
     DrilldownSql,
@@ -11,8 +14,39 @@ use crate::query_ir::{
     LimitSql,
     RcaSql,
     GrowthSql,
+    TotalsSql,
+    quote_standard_text,
 };
 
+/// Features of a query that `standard_sql` can't write. These are rejected
+/// instead of ignored, so that a query never silently returns different data.
+pub(crate) fn check_query_ir(query_ir: &QueryIr) -> Result<(), Error> {
+    let unsupported = [
+        (query_ir.drills.iter().any(|d| d.bin.is_some()), "Bins"),
+        (!query_ir.filters.is_empty(), "Filters"),
+        (!query_ir.filter_exprs.is_empty(), "Filter expressions"),
+        (query_ir.top.is_some(), "Top"),
+        (query_ir.top_where.is_some(), "Top where"),
+        (!query_ir.sort.is_empty(), "Sort"),
+        (query_ir.limit.is_some(), "Limit"),
+        (query_ir.rca.is_some(), "Rca"),
+        (query_ir.growth.is_some(), "Growth"),
+        (query_ir.rate.is_some(), "Rate"),
+        (query_ir.rank.is_some(), "Rank"),
+        (query_ir.sparse, "Sparse"),
+        (query_ir.dense.is_some(), "Dense"),
+        (query_ir.nonempty, "Nonempty"),
+    ];
+
+    for (is_used, feature) in unsupported.iter() {
+        if *is_used {
+            bail!("{} is not supported for this database", feature);
+        }
+    }
+
+    Ok(())
+}
+
 /// Error checking is done before this point. This string formatter
 /// accepts any input
 /// Currently just does the standard aggregation.
@@ -24,10 +58,13 @@ pub(crate) fn standard_sql(
     meas: &[MeasureSql],
     // TODO put Filters and Calculations into own structs
     _top: &Option<TopSql>,
-    _sort: &Option<SortSql>,
+    _sort: &[SortSql],
     _limit: &Option<LimitSql>,
     _rca: &Option<RcaSql>,
     _growth: &Option<GrowthSql>,
+    totals: &Option<TotalsSql>,
+    part_sql: &dyn Fn(&str, &TimePart, Option<u32>) -> String,
+    quote: &dyn Fn(&str) -> String,
     ) -> String
 {
     // hack for now... remove later
@@ -70,45 +107,147 @@ pub(crate) fn standard_sql(
     //    .collect();
     // --------------------------------------------------
 
-    let drill_cols = join(drills.iter().map(|d| d.col_qual_string()), ", ");
+    let drill_cols = join(drills.iter().map(|d| drill_qual_string(d, part_sql)), ", ");
     let mea_cols = join(meas.iter().map(|m| agg_sql_string(m)), ", ");
 
-    let mut final_sql = format!("select {}, {} from {}",
-        drill_cols,
-        mea_cols,
-        table.name,
-    );
+    let mut from_sql = table.name.clone();
 
     // join external dims
     if !ext_drills.is_empty() {
         let join_ext_dim_clauses = join(ext_drills.iter()
             .map(|d| {
                 format!("inner join {} on {}.{} = {}.{}",
-                    d.table.full_name(),
-                    d.table.full_name(),
+                    d.table_sql(),
+                    d.table.name,
                     d.primary_key,
                     table.name,
                     d.foreign_key,
                 )
         }), ", ");
 
-        final_sql = format!("{} {}", final_sql, join_ext_dim_clauses);
+        from_sql = format!("{} {}", from_sql, join_ext_dim_clauses);
     }
 
     if !cuts.is_empty() {
-        let cut_clauses = join(cuts.iter().map(|c| format!("{} in ({})", c.col_qual_string(), c.members_string())), " and ");
-        final_sql = format!("{} where {}", final_sql, cut_clauses);
+        let cut_clauses = join(cuts.iter().map(|c| {
+            match c.range {
+                Some(ref range) => c.range_string(&cut_qual_string(c, part_sql), range, quote),
+                None => format!("{} in ({})", cut_qual_string(c, part_sql), c.members_string(quote)),
+            }
+        }), " and ");
+        from_sql = format!("{} where {}", from_sql, cut_clauses);
+    }
+
+    let final_sql = if let Some(totals) = totals {
+        // Not every backend supports rollups, so each grouping is done
+        // separately and unioned. Rolled up drilldowns are null.
+        let groupings = (0..=totals.drills.len())
+            .map(|n_rolled| {
+                let rolled = &totals.drills[totals.drills.len() - n_rolled..];
+
+                let select_cols = drills.iter()
+                    .map(|d| {
+                        if rolled.contains(d) {
+                            join(drill_qual_string(d, part_sql).split(", ").map(|_| "null"), ", ")
+                        } else {
+                            drill_qual_string(d, part_sql)
+                        }
+                    });
+                let group_cols = join(drills.iter()
+                    .filter(|d| !rolled.contains(d))
+                    .map(|d| drill_qual_string(d, part_sql)), ", ");
+
+                let mut grouping = format!("select {}, {}, {} as total from {}",
+                    join(select_cols, ", "),
+                    mea_cols,
+                    n_rolled,
+                    from_sql,
+                );
+
+                if !group_cols.is_empty() {
+                    grouping = format!("{} group by {}", grouping, group_cols);
+                }
+
+                grouping
+            });
+
+        join(groupings, " union all ")
+    } else {
+        // distinct queries have no measures
+        let select_cols = join([&drill_cols, &mea_cols].iter().filter(|cols| !cols.is_empty()), ", ");
+
+        format!("select {} from {} group by {}",
+            select_cols,
+            from_sql,
+            drill_cols,
+        )
+    };
+
+    format!("{};", final_sql)
+}
+
+/// Virtual time levels are generated from the date column.
+fn drill_qual_string(drill: &DrilldownSql, part_sql: &dyn Fn(&str, &TimePart, Option<u32>) -> String) -> String {
+    match drill.time {
+        Some(ref time) => {
+            let date_col = format!("{}.{}", drill.table.name, time.column);
+            join(time.parts.iter().map(|part| part_sql(&date_col, part, time.fiscal_year_start_month)), ", ")
+        },
+        None => drill.col_qual_string(),
+    }
+}
+
+fn cut_qual_string(cut: &CutSql, part_sql: &dyn Fn(&str, &TimePart, Option<u32>) -> String) -> String {
+    match cut.time_part {
+        Some(ref time_part) => part_sql(&cut.col_qual_string(), time_part, cut.fiscal_year_start_month),
+        None => cut.col_qual_string(),
     }
+}
+
+/// Integer id of a virtual time level, e.g. `201901` for January 2019.
+/// ISO weeks use `isoyear`, which MySQL doesn't support, so it has its own
+/// version.
+pub(crate) fn time_part_sql(column: &str, time_part: &TimePart, fiscal_year_start_month: Option<u32>) -> String {
+    // fiscal parts are the calendar parts of the shifted date
+    let column = match time_part.month_offset(fiscal_year_start_month) {
+        0 => column.to_owned(),
+        months => format!("({} + interval '{}' month)", column, months),
+    };
+
+    let sql = match time_part {
+        TimePart::Year => format!("extract(year from {})", column),
+        TimePart::Quarter => format!("extract(year from {c}) * 10 + extract(quarter from {c})", c = column),
+        TimePart::Month => format!("extract(year from {c}) * 100 + extract(month from {c})", c = column),
+        TimePart::Week => format!("extract(isoyear from {c}) * 100 + extract(week from {c})", c = column),
+        TimePart::Day => format!("extract(year from {c}) * 10000 + extract(month from {c}) * 100 + extract(day from {c})", c = column),
+        TimePart::IsoYear => format!("extract(isoyear from {})", column),
+        TimePart::FiscalYear => format!("extract(year from {})", column),
+        TimePart::FiscalQuarter => format!("extract(year from {c}) * 10 + extract(quarter from {c})", c = column),
+    };
+
+    format!("cast({} as integer)", sql)
+}
+
+/// Names and types of the columns of a table in order, from `information_schema`.
+/// Without a schema, the table is looked up in the current schema, so that
+/// tables of the same name in other schemas aren't mixed in.
+pub(crate) fn table_columns_sql(table: &Table) -> String {
+    let schema = match table.schema {
+        Some(ref schema) => quote_standard_text(schema),
+        None => "current_schema()".to_owned(),
+    };
 
-    final_sql = format!("{} group by {};", final_sql, drill_cols);
-    final_sql
+    format!("select column_name, data_type from information_schema.columns where table_name = {} and table_schema = {} order by ordinal_position",
+        quote_standard_text(&table.name),
+        schema,
+    )
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::names::Mask;
-    use crate::query_ir::{MemberType, LevelColumn};
+    use crate::query_ir::{MemberType, LevelColumn, quote_text};
     use crate::Table;
 
     #[test]
@@ -129,12 +268,17 @@ mod test {
                 foreign_key: "project_id".into(),
                 primary_key: "id".into(),
                 inline_table: None,
+                parent_child: None,
+                snowflake_sql: None,
                 table: Table { name: "valid_projects".into(), schema: None, primary_key: None },
                 column: "id".into(),
                 members: vec!["3".into()],
                 member_type: MemberType::NonText,
                 mask: Mask::Include,
-                for_match: false
+                for_match: false,
+                range: None,
+                time_part: None,
+                fiscal_year_start_month: None,
             },
         ];
         let drills = vec![
@@ -145,6 +289,8 @@ mod test {
                 foreign_key: "project_id".into(),
                 primary_key: "id".into(),
                 inline_table: None,
+                parent_child: None,
+                snowflake_sql: None,
                 table: Table { name: "valid_projects".into(), schema: None, primary_key: None },
                 level_columns: vec![
                     LevelColumn {
@@ -153,6 +299,8 @@ mod test {
                     },
                 ],
                 property_columns: vec![],
+                bin: None,
+                time: None,
             },
         ];
         let meas = vec![
@@ -160,9 +308,133 @@ mod test {
         ];
 
         assert_eq!(
-            standard_sql(&table, &cuts, &drills, &meas, &None, &None, &None, &None, &None),
+            standard_sql(&table, &cuts, &drills, &meas, &None, &[], &None, &None, &None, &None, &time_part_sql, &quote_standard_text),
             "select valid_projects.id, valid_projects.name, sum(commits) from project_facts inner join valid_projects on valid_projects.id = project_facts.project_id where valid_projects.id in (3) group by valid_projects.id, valid_projects.name;".to_owned()
         );
     }
-}
 
+    #[test]
+    fn test_standard_sql_text_cut() {
+        let table = TableSql {
+            name: "project_facts".into(),
+            primary_key: None,
+        };
+        let cuts = vec![
+            CutSql {
+                foreign_key: "project_id".into(),
+                primary_key: "id".into(),
+                inline_table: None,
+                parent_child: None,
+                snowflake_sql: None,
+                table: Table { name: "project_facts".into(), schema: None, primary_key: None },
+                column: "path".into(),
+                members: vec!["a\\b".into(), "it's".into()],
+                member_type: MemberType::Text,
+                mask: Mask::Include,
+                for_match: false,
+                range: None,
+                time_part: None,
+                fiscal_year_start_month: None,
+            },
+        ];
+        let meas = vec![
+            MeasureSql { aggregator: Aggregator::Sum, column: "commits".into() }
+        ];
+
+        // Postgres reads backslashes literally, so only quotes are escaped
+        let sql = standard_sql(&table, &cuts, &[], &meas, &None, &[], &None, &None, &None, &None, &time_part_sql, &quote_standard_text);
+        assert!(sql.contains("where project_facts.path in ('a\\b', 'it''s')"));
+
+        // MySQL reads backslashes as escapes
+        let sql = standard_sql(&table, &cuts, &[], &meas, &None, &[], &None, &None, &None, &None, &time_part_sql, &quote_text);
+        assert!(sql.contains("where project_facts.path in ('a\\\\b', 'it''s')"));
+    }
+
+    #[test]
+    fn test_standard_sql_totals() {
+        let table = TableSql {
+            name: "project_facts".into(),
+            primary_key: None,
+        };
+        let year_drill = DrilldownSql {
+            alias_postfix: "".into(),
+            foreign_key: "year".into(),
+            primary_key: "year".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: "project_facts".into(), schema: None, primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: "year".into(),
+                    name_column: None,
+                },
+            ],
+            property_columns: vec![],
+            bin: None,
+            time: None,
+        };
+        let project_drill = DrilldownSql {
+            alias_postfix: "".into(),
+            foreign_key: "project_id".into(),
+            primary_key: "id".into(),
+            inline_table: None,
+            parent_child: None,
+            snowflake_sql: None,
+            table: Table { name: "valid_projects".into(), schema: None, primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: "id".into(),
+                    name_column: Some("name".to_owned()),
+                },
+            ],
+            property_columns: vec![],
+            bin: None,
+            time: None,
+        };
+        let drills = vec![year_drill, project_drill.clone()];
+        let meas = vec![
+            MeasureSql { aggregator: Aggregator::Sum, column: "commits".into() }
+        ];
+        let totals = Some(TotalsSql { drills: vec![project_drill] });
+
+        assert_eq!(
+            standard_sql(&table, &[], &drills, &meas, &None, &[], &None, &None, &None, &totals, &time_part_sql, &quote_standard_text),
+            "select project_facts.year, valid_projects.id, valid_projects.name, sum(commits), 0 as total \
+            from project_facts inner join valid_projects on valid_projects.id = project_facts.project_id \
+            group by project_facts.year, valid_projects.id, valid_projects.name \
+            union all \
+            select project_facts.year, null, null, sum(commits), 1 as total \
+            from project_facts inner join valid_projects on valid_projects.id = project_facts.project_id \
+            group by project_facts.year;".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_time_part_sql() {
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::Month, Some(7)),
+            "cast(extract(year from sale_date) * 100 + extract(month from sale_date) as integer)".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::FiscalYear, Some(10)),
+            "cast(extract(year from (sale_date + interval '3' month)) as integer)".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::Week, None),
+            "cast(extract(isoyear from sale_date) * 100 + extract(week from sale_date) as integer)".to_owned(),
+        );
+    }
+
+    #[test]
+    fn test_table_columns_sql() {
+        let table = Table { name: "sales".into(), schema: None, primary_key: None };
+        assert_eq!(
+            table_columns_sql(&table),
+            "select column_name, data_type from information_schema.columns where table_name = 'sales' and table_schema = current_schema() order by ordinal_position",
+        );
+
+        let table = Table { name: "sales".into(), schema: Some("retail".into()), primary_key: None };
+        assert!(table_columns_sql(&table).contains("where table_name = 'sales' and table_schema = 'retail' order by"));
+    }
+}
@@ -1,12 +1,16 @@
 use failure::{Error, format_err};
 use futures::future::Future;
-use tesseract_core::{Backend, DataFrame};
+use tesseract_core::{Backend, DataFrame, Table};
+use tesseract_core::query_ir::quote_text;
+use tesseract_core::schema::TimePart;
 
 extern crate futures;
 extern crate mysql_async as my;
 
 mod df;
+mod sql;
 use self::df::{rows_to_df};
+use self::sql::{time_part_sql, table_columns_sql};
 
 use my::prelude::*;
 
@@ -43,6 +47,18 @@ impl Backend for MySql {
     fn box_clone(&self) -> Box<dyn Backend + Send + Sync> {
         Box::new((*self).clone())
     }
+
+    fn time_part_sql(&self, column: &str, time_part: &TimePart, fiscal_year_start_month: Option<u32>) -> String {
+        time_part_sql(column, time_part, fiscal_year_start_month)
+    }
+
+    fn quote_text(&self, s: &str) -> String {
+        quote_text(s)
+    }
+
+    fn table_columns_sql(&self, table: &Table) -> String {
+        table_columns_sql(table)
+    }
 }
 
 
@@ -0,0 +1,76 @@
+//! Virtual time levels for MySQL, which has no `isoyear` and can only cast
+//! to `signed`, not `integer`. ISO weeks come from `yearweek` in mode 3.
+//!
+//! MySQL schemas are databases, so tables without a schema are looked up in
+//! the current database.
+
+use tesseract_core::Table;
+use tesseract_core::query_ir::quote_text;
+use tesseract_core::schema::TimePart;
+
+pub fn time_part_sql(column: &str, time_part: &TimePart, fiscal_year_start_month: Option<u32>) -> String {
+    // fiscal parts are the calendar parts of the shifted date
+    let column = match time_part.month_offset(fiscal_year_start_month) {
+        0 => column.to_owned(),
+        months => format!("({} + interval {} month)", column, months),
+    };
+
+    let sql = match time_part {
+        TimePart::Year => format!("extract(year from {})", column),
+        TimePart::Quarter => format!("extract(year from {c}) * 10 + extract(quarter from {c})", c = column),
+        TimePart::Month => format!("extract(year_month from {})", column),
+        TimePart::Week => format!("yearweek({}, 3)", column),
+        TimePart::Day => format!("extract(year from {c}) * 10000 + extract(month from {c}) * 100 + extract(day from {c})", c = column),
+        TimePart::IsoYear => format!("yearweek({}, 3) div 100", column),
+        TimePart::FiscalYear => format!("extract(year from {})", column),
+        TimePart::FiscalQuarter => format!("extract(year from {c}) * 10 + extract(quarter from {c})", c = column),
+    };
+
+    format!("cast({} as signed)", sql)
+}
+
+pub fn table_columns_sql(table: &Table) -> String {
+    let database = match table.schema {
+        Some(ref schema) => quote_text(schema),
+        None => "database()".to_owned(),
+    };
+
+    format!("select column_name, data_type from information_schema.columns where table_name = {} and table_schema = {} order by ordinal_position",
+        quote_text(&table.name),
+        database,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn time_part_sql_mysql() {
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::Week, None),
+            "cast(yearweek(sale_date, 3) as signed)".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::IsoYear, None),
+            "cast(yearweek(sale_date, 3) div 100 as signed)".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::Month, None),
+            "cast(extract(year_month from sale_date) as signed)".to_owned(),
+        );
+        assert_eq!(
+            time_part_sql("sale_date", &TimePart::FiscalQuarter, Some(7)),
+            "cast(extract(year from (sale_date + interval 6 month)) * 10 + extract(quarter from (sale_date + interval 6 month)) as signed)".to_owned(),
+        );
+    }
+
+    #[test]
+    fn table_columns_sql_mysql() {
+        let table = Table { name: "sales".into(), schema: None, primary_key: None };
+        assert_eq!(
+            table_columns_sql(&table),
+            "select column_name, data_type from information_schema.columns where table_name = 'sales' and table_schema = database() order by ordinal_position".to_owned(),
+        );
+    }
+}
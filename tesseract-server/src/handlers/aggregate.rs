@@ -14,7 +14,8 @@ use serde_derive::{Serialize, Deserialize};
 use serde_qs as qs;
 use std::convert::{TryFrom, TryInto};
 use tesseract_core::format::{format_records, FormatType};
-use tesseract_core::Query as TsQuery;
+use tesseract_core::{Query as TsQuery, DataFrame};
+use tesseract_core::{FilterExpr, FilterQuery};
 
 use crate::handlers::util::validate_members;
 
@@ -59,7 +60,10 @@ pub fn do_aggregate(
     let schema = &req.state().schema.read().unwrap().clone();
     let cube_obj = ok_or_404!(schema.get_cube_by_name(&cube));
 
-    if let Err(err) = verify_authorization(&req, cube_obj.min_auth_level) {
+    // virtual cubes need access to each of their cubes
+    let auth_level = ok_or_404!(schema.cube_auth_level(&cube));
+
+    if let Err(err) = verify_authorization(&req, auth_level) {
         return boxed_error_http_response(err);
     }
 
@@ -99,23 +103,67 @@ pub fn do_aggregate(
     {
         let cache = req.state().cache.read().unwrap();
         let cube_cache = some_or_404!(cache.find_cube_info(&cube), format!("Cube {} not found", cube));
-        ok_or_404!(validate_members(&ts_query.cuts, &cube_cache));
+        ok_or_404!(validate_members(&ts_query.cuts, &ts_query.bins, &cube_cache));
     }
 
-    let query_ir_headers = schema.sql_query(&cube, &ts_query, None);
-    let (query_ir, headers) = ok_or_404!(query_ir_headers);
+    if let Some(ref normalize) = ts_query.normalize {
+        let normalize_cube = ok_or_404!(schema.get_cube_by_name(&normalize.cube));
+
+        if let Err(err) = verify_authorization(&req, normalize_cube.min_auth_level) {
+            return boxed_error_http_response(err);
+        }
+    }
+
+    // Virtual cubes and normalize queries run on several cubes, and join the results
+    let multi_query_ir_headers = if cube_obj.cube_usages.is_some() {
+        Some(schema.virtual_cube_sql_query(&cube, &ts_query, None))
+    } else if ts_query.normalize.is_some() {
+        Some(schema.multi_cube_sql_query(&[cube.clone()], &ts_query, None))
+    } else {
+        None
+    };
+
+    let df_fut: Box<dyn Future<Item=(DataFrame, Vec<String>), Error=Error>> = match multi_query_ir_headers {
+        Some(multi_query_ir_headers) => {
+            let (multi_query_ir, headers) = ok_or_404!(multi_query_ir_headers);
+
+            for query_ir in &multi_query_ir.query_irs {
+                ok_or_404!(req.state().backend.check_query_ir(query_ir));
+            }
+
+            let sqls: Vec<_> = multi_query_ir.query_irs.into_iter()
+                .map(|query_ir| req.state().backend.generate_sql(query_ir))
+                .collect();
+
+            info!("Sql queries: {:?}", sqls);
+            info!("Headers: {:?}", headers);
+
+            let cube_join = multi_query_ir.join;
+            let dfs = future::join_all(sqls.into_iter()
+                .map(|sql| req.state().backend.exec_sql(sql))
+                .collect::<Vec<_>>()
+            );
+
+            Box::new(dfs.and_then(move |dfs| cube_join.join(dfs)).map(|df| (df, headers)))
+        },
+        None => {
+            let query_ir_headers = schema.sql_query(&cube, &ts_query, None);
+            let (query_ir, headers) = ok_or_404!(query_ir_headers);
+            ok_or_404!(req.state().backend.check_query_ir(&query_ir));
 
-    let sql = req.state()
-        .backend
-        .generate_sql(query_ir);
+            let sql = req.state()
+                .backend
+                .generate_sql(query_ir);
 
-    info!("Sql query: {}", sql);
-    info!("Headers: {:?}", headers);
-    
-    req.state()
-        .backend
-        .exec_sql(sql)
-        .and_then(move |df| {
+            info!("Sql query: {}", sql);
+            info!("Headers: {:?}", headers);
+
+            Box::new(req.state().backend.exec_sql(sql).map(|df| (df, headers)))
+        },
+    };
+
+    df_fut
+        .and_then(move |(df, headers)| {
             let content_type = format_to_content_type(&format);
 
             match format_records(&headers, df, format, source_data, false) {
@@ -144,6 +192,8 @@ pub fn do_aggregate(
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AggregateQueryOpt {
     drilldowns: Option<Vec<String>>,
+    property_drilldowns: Option<Vec<String>>,
+    bins: Option<Vec<String>>,
     cuts: Option<Vec<String>>,
     measures: Option<Vec<String>>,
     properties: Option<Vec<String>>,
@@ -152,16 +202,22 @@ pub struct AggregateQueryOpt {
     parents: Option<bool>,
     top: Option<String>,
     top_where: Option<String>,
+    top_other: Option<bool>,
     sort: Option<String>,
     limit: Option<String>,
     growth: Option<String>,
     rca: Option<String>,
     rate: Option<String>,
+    rank: Option<String>,
+    totals: Option<Vec<String>>,
+    normalize: Option<String>,
     debug: Option<bool>,
     exclude_default_members: Option<bool>,
-//    distinct: Option<bool>,
-//    nonempty: Option<bool>,
+    distinct: Option<bool>,
+    nonempty: Option<bool>,
     sparse: Option<bool>,
+    dense: Option<bool>,
+    dense_fill: Option<f64>,
 }
 
 impl TryFrom<AggregateQueryOpt> for TsQuery {
@@ -174,6 +230,18 @@ impl TryFrom<AggregateQueryOpt> for TsQuery {
             })
             .unwrap_or(Ok(vec![]));
 
+        let property_drilldowns: Result<Vec<_>, _> = agg_query_opt.property_drilldowns
+            .map(|ds| {
+                ds.iter().map(|d| d.parse()).collect()
+            })
+            .unwrap_or(Ok(vec![]));
+
+        let bins: Result<Vec<_>, _> = agg_query_opt.bins
+            .map(|bs| {
+                bs.iter().map(|b| b.parse()).collect()
+            })
+            .unwrap_or(Ok(vec![]));
+
         let cuts: Result<Vec<_>, _> = agg_query_opt.cuts
             .map(|cs| {
                 cs.iter().map(|c| c.parse()).collect()
@@ -192,11 +260,6 @@ impl TryFrom<AggregateQueryOpt> for TsQuery {
             })
             .unwrap_or(Ok(vec![]));
 
-        let filters: Result<Vec<_>, _> = agg_query_opt.filters
-            .map(|fs| {
-                fs.iter().map(|f| f.parse()).collect()
-            })
-            .unwrap_or(Ok(vec![]));
 
         let captions: Result<Vec<_>, _> = agg_query_opt.captions
             .map(|cs| {
@@ -205,12 +268,23 @@ impl TryFrom<AggregateQueryOpt> for TsQuery {
             .unwrap_or(Ok(vec![]));
 
         let drilldowns = drilldowns?;
+        let property_drilldowns = property_drilldowns?;
+        let bins = bins?;
         let cuts = cuts?;
         let measures = measures?;
         let properties = properties?;
-        let filters = filters?;
         let captions = captions?;
 
+        // Simple measure filters are tried first, then filter expressions
+        let mut filters = vec![];
+        let mut filter_exprs = vec![];
+        for f in agg_query_opt.filters.unwrap_or(vec![]) {
+            match f.parse::<FilterQuery>() {
+                Ok(filter) => filters.push(filter),
+                Err(_) => filter_exprs.push(f.parse::<FilterExpr>()?),
+            }
+        }
+
         let parents = agg_query_opt.parents.unwrap_or(false);
 
         let top = agg_query_opt.top
@@ -219,9 +293,13 @@ impl TryFrom<AggregateQueryOpt> for TsQuery {
         let top_where = agg_query_opt.top_where
             .map(|t| t.parse())
             .transpose()?;
-        let sort = agg_query_opt.sort
-            .map(|s| s.parse())
-            .transpose()?;
+        let top_other = agg_query_opt.top_other.unwrap_or(false);
+        let sort: Result<Vec<_>, _> = agg_query_opt.sort
+            .map(|ss| {
+                ss.split(",").map(|s| s.parse()).collect()
+            })
+            .unwrap_or(Ok(vec![]));
+        let sort = sort?;
         let limit = agg_query_opt.limit
             .map(|l| l.parse())
             .transpose()?;
@@ -238,28 +316,57 @@ impl TryFrom<AggregateQueryOpt> for TsQuery {
             .map(|r| r.parse())
             .transpose()?;
 
+        let rank = agg_query_opt.rank
+            .map(|r| r.parse())
+            .transpose()?;
+
+        let totals: Result<Vec<_>, _> = agg_query_opt.totals
+            .map(|ts| {
+                ts.iter().map(|t| t.parse()).collect()
+            })
+            .unwrap_or(Ok(vec![]));
+        let totals = totals?;
+
+        let normalize = agg_query_opt.normalize
+            .map(|n| n.parse())
+            .transpose()?;
+
         let debug = agg_query_opt.debug.unwrap_or(false);
         let sparse = agg_query_opt.sparse.unwrap_or(false);
+        let dense = agg_query_opt.dense.unwrap_or(false);
+        let nonempty = agg_query_opt.nonempty.unwrap_or(false);
+        let distinct = agg_query_opt.distinct.unwrap_or(false);
         let exclude_default_members = agg_query_opt.exclude_default_members.unwrap_or(false);
 
         // TODO: deserialize rate
         Ok(TsQuery {
             drilldowns,
+            property_drilldowns,
+            bins,
             cuts,
             measures,
             parents,
             properties,
             filters,
+            filter_exprs,
             captions,
             top,
             top_where,
+            top_other,
             sort,
             limit,
             rca,
             growth,
             debug,
             rate,
+            rank,
+            totals,
+            normalize,
             sparse,
+            dense,
+            dense_fill: agg_query_opt.dense_fill,
+            nonempty,
+            distinct,
             exclude_default_members,
         })
     }
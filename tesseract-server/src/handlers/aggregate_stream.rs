@@ -77,6 +77,7 @@ pub fn do_aggregate(
         .sql_query(&cube, &ts_query, None);
 
     let (query_ir, headers) = ok_or_404!(query_ir_headers);
+    ok_or_404!(req.state().backend.check_query_ir(&query_ir));
 
     let sql = req.state()
         .backend
@@ -18,6 +18,7 @@ use url::Url;
 use tesseract_core::names::{Property, LevelName};
 use tesseract_core::format::{format_records, FormatType};
 use tesseract_core::{DataFrame, Column, ColumnData};
+use tesseract_core::Schema;
 use tesseract_core::schema::{Cube, DimensionType, Level};
 use crate::app::AppState;
 use crate::logic_layer::{LogicLayerConfig, CubeCache};
@@ -86,7 +87,7 @@ pub fn perform_diagnosis(
                         return Ok(err);
                     }
 
-                    let (error_types, error_messages) = diagnose_cube(&req, cube);
+                    let (error_types, error_messages) = diagnose_cube(&req, &schema, cube);
 
                     format_diagnosis_response(error_types, error_messages, format, None)
                 },
@@ -103,7 +104,7 @@ pub fn perform_diagnosis(
                     continue;
                 }
 
-                let (new_error_types, new_error_messages) = diagnose_cube(&req, &cube);
+                let (new_error_types, new_error_messages) = diagnose_cube(&req, &schema, &cube);
 
                 // Add these to the overall list
                 if new_error_types.len() != 0 {
@@ -125,10 +126,29 @@ pub fn perform_diagnosis(
 }
 
 
-fn diagnose_cube(req: &HttpRequest<AppState>, cube: &Cube) -> (Vec<String>, Vec<String>) {
+fn diagnose_cube(req: &HttpRequest<AppState>, schema: &Schema, cube: &Cube) -> (Vec<String>, Vec<String>) {
     let mut error_types: Vec<String> = vec![];
     let mut error_messages: Vec<String> = vec![];
 
+    // Check for `SchemaColumns`: tables and columns missing from the
+    // database, or with a type that doesn't match their use
+    let schema_check = schema
+        .check_database(Some(&cube.name), &*req.state().backend)
+        .wait();
+
+    match schema_check {
+        Ok(diagnostics) => {
+            for diagnostic in diagnostics {
+                error_types.push("SchemaColumns".to_string());
+                error_messages.push(diagnostic.to_string());
+            }
+        },
+        Err(err) => {
+            error_types.push("SchemaColumns".to_string());
+            error_messages.push(format!("Could not check the schema against the database: {}", err));
+        }
+    }
+
     for dimension in &cube.dimensions {
         for hierarchy in &dimension.hierarchies {
             let last_level: &Level = &hierarchy.levels[hierarchy.levels.len() - 1];
@@ -49,7 +49,7 @@ pub fn flush_handler(req: HttpRequest<AppState>) -> ActixResult<HttpResponse> {
             SchemaSource::RemoteSchema { ref endpoint } => endpoint,
         };
 
-        let schema = match schema_config::read_schema(&schema_path) {
+        let mut schema = match schema_config::read_schema(&schema_path) {
             Ok(val) => val,
             Err(err) => {
                 error!("{}", err);
@@ -57,6 +57,12 @@ pub fn flush_handler(req: HttpRequest<AppState>) -> ActixResult<HttpResponse> {
             },
         };
 
+        // keep the current schema if the new one has problems
+        if let Err(err) = schema.validate() {
+            error!("{}", err);
+            return Ok(HttpResponse::InternalServerError().finish());
+        }
+
         // Update shared schema
         let mut w = req.state().schema.write().unwrap();
         *w = schema.clone();
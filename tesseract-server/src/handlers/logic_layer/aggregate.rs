@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::str;
 
@@ -12,15 +13,17 @@ use serde_qs as qs;
 use serde_derive::Deserialize;
 use url::Url;
 
-use tesseract_core::names::{Cut, Drilldown, Property, Measure, LevelName, Mask};
+use tesseract_core::names::{Cut, CutRange, Drilldown, Property, Measure, LevelName, Mask};
 use tesseract_core::format::{format_records, FormatType};
-use tesseract_core::query::{FilterQuery, GrowthQuery, RcaQuery, TopQuery, RateQuery};
-use tesseract_core::{Query as TsQuery, MeaOrCalc, DataFrame, Column, ColumnData, is_same_columndata_type};
+use tesseract_core::query::{FilterQuery, GrowthQuery, RcaQuery, TopQuery, RateQuery, RankQuery, RankType};
+use tesseract_core::query::{Calculation, FilterExpr, SortDirection, SortQuery, ColumnKey};
+use tesseract_core::query::{BinQuery, BinType, NormalizeQuery};
+use tesseract_core::{Query as TsQuery, MeaOrCalc, DataFrame, Column, ColumnData, MultiCubeJoin, is_same_columndata_type};
 use tesseract_core::schema::{Cube, DimensionType};
 
 use crate::app::AppState;
 use crate::errors::ServerError;
-use crate::logic_layer::{LogicLayerConfig, CubeCache, Time};
+use crate::logic_layer::{LogicLayerConfig, CubeCache, Time, TimePrecision};
 use super::super::util::{
     boxed_error_string, boxed_error_http_response,
     verify_authorization, format_to_content_type, generate_source_data,
@@ -69,8 +72,12 @@ pub fn logic_layer_handler(
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct LogicLayerQueryOpt {
+    #[serde(default)]
     pub cube: String,
+    // several cubes, joined on their shared drilldowns
+    pub cubes: Option<String>,
     pub drilldowns: Option<String>,
+    bins: Option<String>,
     #[serde(flatten)]
     pub cuts: Option<HashMap<String, String>>,
     pub time: Option<String>,
@@ -80,6 +87,7 @@ pub struct LogicLayerQueryOpt {
     parents: Option<bool>,
     top: Option<String>,
     top_where: Option<String>,
+    top_other: Option<bool>,
     sort: Option<String>,
     limit: Option<String>,
     growth: Option<String>,
@@ -88,16 +96,23 @@ pub struct LogicLayerQueryOpt {
     exclude: Option<String>,
     exclude_default_members: Option<bool>,
     locale: Option<String>,
-    //    distinct: Option<bool>,
-    //    nonempty: Option<bool>,
+    distinct: Option<bool>,
+    nonempty: Option<bool>,
     sparse: Option<bool>,
+    dense: Option<bool>,
+    dense_fill: Option<f64>,
     rate: Option<String>,
+    rank: Option<String>,
+    totals: Option<String>,
+    normalize: Option<String>,
 }
 
 
 impl LogicLayerQueryOpt {
     pub fn deserialize_args(arg: String) -> Vec<String> {
         let mut open = false;
+        // commas inside parentheses (e.g. filter expressions) don't split args
+        let mut paren_depth = 0;
         let mut curr_str = "".to_string();
         let mut arg_vec: Vec<String> = vec![];
 
@@ -108,8 +123,15 @@ impl LogicLayerQueryOpt {
                 open = true;
             } else if c_str == "]" {
                 open = false;
+            } else if c_str == "(" || c_str == ")" {
+                if c_str == "(" {
+                    paren_depth += 1;
+                } else if paren_depth > 0 {
+                    paren_depth -= 1;
+                }
+                curr_str += &c_str;
             } else if c_str == "," {
-                if open {
+                if open || paren_depth > 0 {
                     curr_str += &c_str;
                 } else {
                     arg_vec.push(curr_str.clone());
@@ -214,24 +236,43 @@ pub fn logic_layer_aggregation(
     let agg_query_res = QS_NON_STRICT.deserialize_str::<LogicLayerQueryOpt>(query);
     let agg_query = ok_or_404!(agg_query_res);
 
+    // Multi-cube queries list their cubes in `cubes`. The first cube is used
+    // to resolve names, which is fine since drilldowns and cuts must be on
+    // dimensions shared by all of them.
+    let cube_names: Vec<String> = match &agg_query.cubes {
+        Some(cubes) => cubes.split(",").map(|c| c.to_string()).collect(),
+        None => vec![agg_query.cube.clone()]
+    };
+
     // Check to see if the logic layer config has a alias with the
     // provided cube name
-    let cube_name = match logic_layer_config.clone() {
-        Some(llc) => {
-            match llc.substitute_cube_name(agg_query.cube.clone()) {
-                Ok(cn) => cn,
-                Err(_) => agg_query.cube.clone()
+    let cube_names: Vec<String> = cube_names.into_iter()
+        .map(|name| {
+            match logic_layer_config.clone() {
+                Some(llc) => {
+                    match llc.substitute_cube_name(name.clone()) {
+                        Ok(cn) => cn,
+                        Err(_) => name
+                    }
+                },
+                None => name
             }
-        },
-        None => agg_query.cube.clone()
-    };
+        })
+        .collect();
 
-    let cube = ok_or_404!(schema.get_cube_by_name(&cube_name));
+    let cube_name = cube_names[0].clone();
+
+    // virtual cubes need access to each of their cubes
+    for name in &cube_names {
+        let auth_level = ok_or_404!(schema.cube_auth_level(name));
 
-    if let Err(err) = verify_authorization(&req, cube.min_auth_level) {
-        return boxed_error_http_response(err);
+        if let Err(err) = verify_authorization(&req, auth_level) {
+            return boxed_error_http_response(err);
+        }
     }
 
+    let cube = ok_or_404!(schema.get_cube_by_name(&cube_name));
+
     // Check if this query is already cached
     let redis_pool = req.state().redis_pool.clone();
     let redis_cache_key = get_redis_cache_key("logic-layer", &req, &cube_name, &format);
@@ -263,6 +304,15 @@ pub fn logic_layer_aggregation(
         return boxed_error_string("Unable to generate queries".to_string())
     }
 
+    // The normalize cube is queried too
+    if let Some(ref normalize) = ts_queries[0].normalize {
+        let normalize_cube = ok_or_404!(schema.get_cube_by_name(&normalize.cube));
+
+        if let Err(err) = verify_authorization(&req, normalize_cube.min_auth_level) {
+            return boxed_error_http_response(err);
+        }
+    }
+
     // Need to create a map here to help create unique header names in the next step
     let unique_header_map: HashMap<String, String> = if let Some(ref llc) = logic_layer_config {
         llc.get_unique_names_map(cube_name.clone())
@@ -270,29 +320,65 @@ pub fn logic_layer_aggregation(
         HashMap::new()
     };
 
-    let mut sql_strings: Vec<String> = vec![];
+    // one sql string per cube for each query
+    let mut sql_strings: Vec<Vec<String>> = vec![];
     let mut final_headers: Vec<String> = vec![];
 
+    // for multi-cube queries, how to join the results of each cube
+    let mut cube_join: Option<MultiCubeJoin> = None;
+
     for ts_query in &ts_queries {
         // SQL injection mitigation
-        ok_or_404!(validate_members(&ts_query.cuts, &cube_cache));
+        ok_or_404!(validate_members(&ts_query.cuts, &ts_query.bins, &cube_cache));
 
         debug!("Tesseract query: {:?}", ts_query);
 
-        let query_ir_headers = req
-            .state()
-            .schema.read().unwrap()
-            .sql_query(&cube_name, &ts_query, Some(&unique_header_map));
+        let (query_irs, headers) = if cube.cube_usages.is_some() && cube_names.len() == 1 {
+            let virtual_query_ir_headers = req
+                .state()
+                .schema.read().unwrap()
+                .virtual_cube_sql_query(&cube_name, &ts_query, Some(&unique_header_map));
+
+            let (multi_query_ir, headers) = ok_or_404!(virtual_query_ir_headers);
+            cube_join = Some(multi_query_ir.join);
+
+            (multi_query_ir.query_irs, headers)
+        } else if cube_names.len() > 1 || ts_query.normalize.is_some() {
+            let multi_query_ir_headers = req
+                .state()
+                .schema.read().unwrap()
+                .multi_cube_sql_query(&cube_names, &ts_query, Some(&unique_header_map));
 
-        let (query_ir, headers) = ok_or_404!(query_ir_headers);
+            let (multi_query_ir, headers) = ok_or_404!(multi_query_ir_headers);
+            cube_join = Some(multi_query_ir.join);
 
-        debug!("Query IR: {:?}", query_ir);
+            (multi_query_ir.query_irs, headers)
+        } else {
+            let query_ir_headers = req
+                .state()
+                .schema.read().unwrap()
+                .sql_query(&cube_name, &ts_query, Some(&unique_header_map));
+
+            let (query_ir, headers) = ok_or_404!(query_ir_headers);
+
+            (vec![query_ir], headers)
+        };
 
-        let sql = req.state()
-            .backend
-            .generate_sql(query_ir);
+        debug!("Query IR: {:?}", query_irs);
 
-        debug!("SQL query: {}", sql);
+        for query_ir in &query_irs {
+            ok_or_404!(req.state().backend.check_query_ir(query_ir));
+        }
+
+        let sqls: Vec<String> = query_irs.into_iter()
+            .map(|query_ir| {
+                req.state()
+                    .backend
+                    .generate_sql(query_ir)
+            })
+            .collect();
+
+        debug!("SQL query: {:?}", sqls);
 
         // Substitute header names (only need to do this once)
         if final_headers.len() == 0 {
@@ -309,7 +395,7 @@ pub fn logic_layer_aggregation(
             }
         }
 
-        sql_strings.push(sql);
+        sql_strings.push(sqls);
     }
 
     debug!("Headers: {:?}", final_headers);
@@ -319,10 +405,28 @@ pub fn logic_layer_aggregation(
     // Joins all the futures for each TsQuery
     let futs: JoinAll<Vec<Box<dyn Future<Item=DataFrame, Error=Error>>>> = join_all(sql_strings
             .iter()
-            .map(|sql| {
-                req.state()
-                    .backend
-                    .exec_sql(sql.clone())
+            .map(|sqls| {
+                match cube_join.clone() {
+                    // multi-cube queries join the results of each cube
+                    Some(cube_join) => {
+                        let cube_futs = join_all(sqls.iter()
+                            .map(|sql| {
+                                req.state()
+                                    .backend
+                                    .exec_sql(sql.clone())
+                            })
+                            .collect::<Vec<_>>()
+                        );
+
+                        Box::new(cube_futs.and_then(move |dfs| cube_join.join(dfs)))
+                            as Box<dyn Future<Item=DataFrame, Error=Error>>
+                    },
+                    None => {
+                        req.state()
+                            .backend
+                            .exec_sql(sqls[0].clone())
+                    }
+                }
             })
             .collect()
         );
@@ -580,6 +684,9 @@ pub fn generate_ts_queries(
 
     let parents = agg_query_opt.parents.unwrap_or(false);
 
+    // Properties can also be drilled down on, grouping members by property value
+    let mut property_drilldowns: Vec<Property> = vec![];
+
     let drilldowns: Vec<_> = agg_query_opt.drilldowns
         .map(|ds| {
             let mut drilldowns: Vec<Drilldown> = vec![];
@@ -601,7 +708,14 @@ pub fn generate_ts_queries(
                     None => level_value.clone()
                 };
 
-                let level_name = some_or_break!(level_map.get(&level_key));
+                let level_name = match level_map.get(&level_key) {
+                    Some(level_name) => level_name,
+                    None => {
+                        let property = some_or_break!(property_map.get(&level_key));
+                        property_drilldowns.push(property.clone());
+                        continue;
+                    }
+                };
 
                 let level = some_or_break!(cube.get_level(level_name));
 
@@ -622,6 +736,31 @@ pub fn generate_ts_queries(
         })
         .unwrap_or(vec![]);
 
+    // A bin is `level_or_property,bin_type,args`, e.g. `Population,breaks,1000,5000`
+    let bins: Vec<BinQuery> = match agg_query_opt.bins {
+        Some(b) => {
+            let bin_split: Vec<&str> = b.splitn(2, ',').collect();
+
+            if bin_split.len() != 2 {
+                return Err(format_err!("Bad formatting for bins param."));
+            }
+
+            let bin_type = bin_split[1].parse::<BinType>()?;
+
+            let bin = match level_map.get(bin_split[0]) {
+                Some(level_name) => BinQuery::new(level_name.clone(), None, bin_type),
+                None => {
+                    let property = property_map.get(bin_split[0])
+                        .ok_or_else(|| format_err!("Unrecognized level or property in bins param."))?;
+                    BinQuery::new(property.level_name.clone(), Some(property.property.clone()), bin_type)
+                },
+            };
+
+            vec![bin]
+        },
+        None => vec![]
+    };
+
     let measures: Vec<_> = agg_query_opt.measures
         .map(|ms| {
             let mut measures: Vec<Measure> = vec![];
@@ -653,57 +792,68 @@ pub fn generate_ts_queries(
         })
         .unwrap_or(vec![]);
 
-    let filters: Vec<FilterQuery> = agg_query_opt.filters
-        .map(|fs| LogicLayerQueryOpt::deserialize_args(fs).iter().map(|f| {
-            // Validate that the measure provided is an actual measure for this cube
-            match &f.splitn(2, ".").collect::<Vec<_>>()[..] {
-                [filter_measure, _] => {
-                    let mut found = false;
-
-                    for mea in &cube.measures {
-                        if &mea.name == filter_measure {
-                            found = true;
-                            break;
-                        }
-                    }
+    let mut filters: Vec<FilterQuery> = vec![];
+    let mut filter_exprs: Vec<FilterExpr> = vec![];
 
-                    if !found {
-                        return Err(format_err!("The measure name provided in the `filter` param is not valid."))
-                    }
-                },
-                _ => return Err(format_err!("Could not parse a filter query"))
+    if let Some(fs) = agg_query_opt.filters {
+        for f in LogicLayerQueryOpt::deserialize_args(fs) {
+            // Simple measure filters, e.g. `Exports.gt.1000.and.lt.5000`
+            let is_measure_filter = match &f.splitn(2, ".").collect::<Vec<_>>()[..] {
+                [filter_measure, _] => cube.measures.iter().any(|mea| &mea.name == filter_measure),
+                _ => false,
+            };
+
+            if is_measure_filter {
+                if let Ok(filter) = f.parse::<FilterQuery>() {
+                    filters.push(filter);
+                    continue;
+                }
             }
 
-            f.parse()
-        }).collect())
-        .unwrap_or(Ok(vec![]))?;
+            let filter_expr = f.parse::<FilterExpr>()?;
+            filter_exprs.push(resolve_filter_expr(filter_expr, &cube, &level_map, &property_map)?);
+        }
+    }
 
     let top: Option<TopQuery> = agg_query_opt.top.clone()
         .map(|t| {
             let top_split: Vec<String> = t.split(',').map(|s| s.to_string()).collect();
 
-            if top_split.len() != 4 {
+            if top_split.len() < 3 {
                 return Err(format_err!("Bad formatting for top param."));
             }
 
             let level_name = some_or_bail!(level_map.get(&top_split[1]));
 
-            let mea_or_calc: MeaOrCalc = top_split[2].parse()?;
+            // Either a single `measure,direction` pair, or a list of `key.direction`
+            let sort_keys = if top_split.len() == 4 && top_split[3].parse::<SortDirection>().is_ok() {
+                vec![format!("{}.{}", top_split[2], top_split[3])]
+            } else {
+                top_split[2..].to_vec()
+            };
+
+            let sort = sort_keys.iter()
+                .map(|s| resolve_sort(s, &cube, &level_map, &property_map))
+                .collect::<Result<Vec<_>, _>>()?;
 
             Ok(TopQuery::new(
                 top_split[0].parse()?,
                 level_name.clone(),
-                vec![mea_or_calc],
-                top_split[3].parse()?
+                sort
             ))
         })
         .transpose()?;
     let top_where = agg_query_opt.top_where
         .map(|t| t.parse())
         .transpose()?;
-    let sort = agg_query_opt.sort
-        .map(|s| s.parse())
-        .transpose()?;
+    let top_other = agg_query_opt.top_other.unwrap_or(false);
+    let sort: Vec<SortQuery> = agg_query_opt.sort
+        .map(|ss| {
+            ss.split(',')
+                .map(|s| resolve_sort(s, &cube, &level_map, &property_map))
+                .collect()
+        })
+        .unwrap_or(Ok(vec![]))?;
     let limit = agg_query_opt.limit
         .map(|l| l.parse())
         .transpose()?;
@@ -721,7 +871,16 @@ pub fn generate_ts_queries(
             let level_key = gro_split[0].clone();
             let measure = gro_split[1].clone();
 
-            let level_name = some_or_bail!(level_map.get(&level_key));
+            // The time level can also be given by its precision (e.g. `year`),
+            // for cubes where it has a localized or fiscal name
+            let level_name = match level_map.get(&level_key) {
+                Some(level_name) => level_name,
+                None => {
+                    let time_level_name = TimePrecision::from_str(level_key).ok()
+                        .and_then(|precision| cube_cache.get_time_level_name(&precision));
+                    some_or_bail!(time_level_name)
+                },
+            };
 
             let growth = GrowthQuery::new(
                 level_name.dimension.clone(),
@@ -802,8 +961,64 @@ pub fn generate_ts_queries(
         None => None
     };
 
+    let rank: Option<RankQuery> = agg_query_opt.rank.clone()
+        .map(|r| {
+            let rank_split: Vec<String> = r.split(',').map(|s| s.to_string()).collect();
+
+            if rank_split.len() != 3 && rank_split.len() != 4 {
+                return Err(format_err!("Bad formatting for rank param."));
+            }
+
+            let level_name = some_or_bail!(level_map.get(&rank_split[0]));
+
+            let mea_or_calc: MeaOrCalc = rank_split[1].parse()?;
+
+            let rank_type = match rank_split.get(3) {
+                Some(rank_type) => rank_type.parse()?,
+                None => RankType::Standard,
+            };
+
+            Ok(RankQuery::new(
+                level_name.clone(),
+                mea_or_calc,
+                rank_split[2].parse()?,
+                rank_type
+            ))
+        })
+        .transpose()?;
+
+    // Levels to roll up into subtotals, which must also be drilled down on
+    let totals: Vec<Drilldown> = match agg_query_opt.totals.clone() {
+        Some(ts) => {
+            LogicLayerQueryOpt::deserialize_args(ts).iter()
+                .map(|t| {
+                    let level_name = some_or_bail!(level_map.get(t));
+                    Ok(Drilldown(level_name.clone()))
+                })
+                .collect::<Result<Vec<_>, Error>>()?
+        },
+        None => vec![]
+    };
+
+    // `cube.measure[,scale]`, with the cube name alias substituted
+    let normalize: Option<NormalizeQuery> = agg_query_opt.normalize.clone()
+        .map(|n| -> Result<_, Error> {
+            let mut normalize = n.parse::<NormalizeQuery>()?;
+
+            if let Some(llc) = ll_config.clone() {
+                normalize.cube = llc.substitute_cube_name(normalize.cube.clone())?;
+            }
+
+            Ok(normalize)
+        })
+        .transpose()?;
+
     let debug = agg_query_opt.debug.unwrap_or(false);
     let sparse = agg_query_opt.sparse.unwrap_or(false);
+    let dense = agg_query_opt.dense.unwrap_or(false);
+    let dense_fill = agg_query_opt.dense_fill;
+    let nonempty = agg_query_opt.nonempty.unwrap_or(false);
+    let distinct = agg_query_opt.distinct.unwrap_or(false);
     let exclude_default_members = agg_query_opt.exclude_default_members.unwrap_or(false);
 
     // This is where all the different queries are ACTUALLY generated.
@@ -813,6 +1028,27 @@ pub fn generate_ts_queries(
         &cuts_map, &cube, &cube_cache, &level_map, &property_map, &geoservice_url
     )?;
 
+    // Cuts on level properties (e.g. `Region=South`) don't take part in the
+    // cut combinations, they are added to every query
+    let mut property_cuts: Vec<Cut> = vec![];
+
+    for (cut_key, cut_values) in cuts_map.iter() {
+        if cut_values.is_empty() || level_map.contains_key(cut_key) || cube_cache.dimension_caches.contains_key(cut_key) {
+            continue;
+        }
+
+        if let Some(property) = property_map.get(cut_key) {
+            property_cuts.push(Cut {
+                level_name: property.level_name.clone(),
+                members: cut_values.split(",").map(|s| s.to_string()).collect(),
+                mask: Mask::Include,
+                for_match: false,
+                range: None,
+                property: Some(property.property.clone()),
+            });
+        }
+    }
+
     // Groups together cuts for the same dimension
     // This is needed so we can generate all the possible cut combinations in the next step
     let mut dimension_cuts: Vec<Vec<Cut>> = vec![];
@@ -828,11 +1064,16 @@ pub fn generate_ts_queries(
         let num_level_cuts = level_cuts_map.len();
 
         for (level_name, level_cuts) in level_cuts_map.iter() {
+            // A range (e.g. `2010..2020`) replaces the member list for a level
+            let (members, range) = level_cut_members(level_cuts, cube_cache.members_for_level(level_name));
+
             let cut = Cut {
                 level_name: level_name.clone(),
-                members: level_cuts.clone(),
+                members,
                 mask: Mask::Include,
-                for_match: false
+                for_match: false,
+                range,
+                property: None,
             };
 
             inner_cuts.push(cut.clone());
@@ -855,13 +1096,16 @@ pub fn generate_ts_queries(
     if cut_combinations.len() == 0 {
         queries.push(TsQuery {
             drilldowns: drilldowns.clone(),
-            cuts: vec![],
+            property_drilldowns: property_drilldowns.clone(),
+            bins: bins.clone(),
+            cuts: property_cuts.clone(),
             measures: measures.clone(),
             parents: parents.clone(),
             properties: properties.clone(),
             captions: captions.clone(),
             top: top.clone(),
             top_where: top_where.clone(),
+            top_other,
             sort: sort.clone(),
             limit: limit.clone(),
             rca: rca.clone(),
@@ -869,8 +1113,16 @@ pub fn generate_ts_queries(
             debug: debug.clone(),
             exclude_default_members: exclude_default_members.clone(),
             filters: filters.clone(),
+            filter_exprs: filter_exprs.clone(),
             rate: rate.clone(),
+            rank: rank.clone(),
+            totals: totals.clone(),
+            normalize: normalize.clone(),
             sparse: sparse.clone(),
+            dense: dense.clone(),
+            dense_fill: dense_fill.clone(),
+            nonempty: nonempty.clone(),
+            distinct: distinct.clone(),
         });
     } else {
         // Create a TsQuery for each cut combination
@@ -894,13 +1146,16 @@ pub fn generate_ts_queries(
             // Populate queries vector
             queries.push(TsQuery {
                 drilldowns: drills,
-                cuts: cut_combination.clone(),
+                property_drilldowns: property_drilldowns.clone(),
+                bins: bins.clone(),
+                cuts: [&cut_combination[..], &property_cuts[..]].concat(),
                 measures: measures.clone(),
                 parents: parents.clone(),
                 properties: properties.clone(),
                 captions: caps,
                 top: top.clone(),
                 top_where: top_where.clone(),
+                top_other,
                 sort: sort.clone(),
                 limit: limit.clone(),
                 rca: rca.clone(),
@@ -908,8 +1163,16 @@ pub fn generate_ts_queries(
                 debug: debug.clone(),
                 exclude_default_members: exclude_default_members.clone(),
                 filters: filters.clone(),
+                filter_exprs: filter_exprs.clone(),
                 rate: rate.clone(),
+                rank: rank.clone(),
+                totals: totals.clone(),
+                normalize: normalize.clone(),
                 sparse: sparse.clone(),
+                dense: dense.clone(),
+                dense_fill: dense_fill.clone(),
+                nonempty: nonempty.clone(),
+                distinct: distinct.clone(),
             });
         }
     }
@@ -919,6 +1182,77 @@ pub fn generate_ts_queries(
 }
 
 
+/// Resolves a `name.direction` sort param.
+fn resolve_sort(
+    sort: &str,
+    cube: &Cube,
+    level_map: &HashMap<String, LevelName>,
+    property_map: &HashMap<String, Property>,
+) -> Result<SortQuery, Error> {
+    let (name, direction) = match &sort.rsplitn(2, '.').collect::<Vec<_>>()[..] {
+        [direction, name] => (name.to_string(), direction.parse::<SortDirection>()?),
+        _ => bail!("Bad formatting for sort param."),
+    };
+
+    Ok(SortQuery {
+        direction,
+        key: resolve_column_key(&name, cube, level_map, property_map)?,
+    })
+}
+
+/// Resolves the column keys of a filter expression, which are parsed as
+/// measure names, to logic layer names.
+fn resolve_filter_expr(
+    filter_expr: FilterExpr,
+    cube: &Cube,
+    level_map: &HashMap<String, LevelName>,
+    property_map: &HashMap<String, Property>,
+) -> Result<FilterExpr, Error> {
+    let resolve_all = |exprs: Vec<FilterExpr>| {
+        exprs.into_iter()
+            .map(|expr| resolve_filter_expr(expr, cube, level_map, property_map))
+            .collect::<Result<Vec<_>, Error>>()
+    };
+
+    Ok(match filter_expr {
+        FilterExpr::And(exprs) => FilterExpr::And(resolve_all(exprs)?),
+        FilterExpr::Or(exprs) => FilterExpr::Or(resolve_all(exprs)?),
+        FilterExpr::Not(expr) => {
+            FilterExpr::Not(Box::new(resolve_filter_expr(*expr, cube, level_map, property_map)?))
+        },
+        FilterExpr::Condition(ColumnKey::MeaOrCalc(MeaOrCalc::Mea(measure)), op) => {
+            FilterExpr::Condition(resolve_column_key(&measure.0, cube, level_map, property_map)?, op)
+        },
+        filter_expr => filter_expr,
+    })
+}
+
+/// A column name can be a measure, a calculation, a level (its label), a level
+/// followed by ` ID` (its ID), or a property.
+fn resolve_column_key(
+    name: &str,
+    cube: &Cube,
+    level_map: &HashMap<String, LevelName>,
+    property_map: &HashMap<String, Property>,
+) -> Result<ColumnKey, Error> {
+    let key = if cube.measures.iter().any(|m| m.name == name) {
+        ColumnKey::MeaOrCalc(MeaOrCalc::Mea(Measure::new(name)))
+    } else if let Ok(calc) = name.parse::<Calculation>() {
+        ColumnKey::MeaOrCalc(MeaOrCalc::Calc(calc))
+    } else if let Some(level_name) = level_map.get(name) {
+        ColumnKey::LevelLabel(level_name.clone())
+    } else if let Some(level_name) = level_map.get(name.trim_end_matches(" ID")) {
+        ColumnKey::LevelId(level_name.clone())
+    } else if let Some(property) = property_map.get(name) {
+        ColumnKey::Property(property.clone())
+    } else {
+        bail!("Unrecognized column name: {}", name);
+    };
+
+    Ok(key)
+}
+
+
 /// Given a vector containing a partial Cartesian product, and a list of items,
 /// return a vector adding the list of items to the partial Cartesian product.
 /// From: https://gist.github.com/kylewlacy/115965b40e02a3325558
@@ -953,6 +1287,77 @@ pub fn cartesian_product<T: Clone>(lists: Vec<Vec<T>>) -> Vec<Vec<T>> {
 
 /// Performs named set and time substitutions in the original cuts HashMap
 /// deserialized from the query.
+/// A level cut value is a range (e.g. `2010..2020`) only if its ends are
+/// members of the level. Members can contain `..` too (e.g. `N.A.. Region`),
+/// so a value that is itself a member is never a range.
+fn level_cut_range(value: &str, members: Option<&HashSet<String>>) -> Option<CutRange> {
+    let members = members?;
+
+    if members.contains(value) {
+        return None;
+    }
+
+    let range = value.parse::<CutRange>().ok()?;
+
+    if range.ends().iter().all(|end| members.contains(*end)) {
+        Some(range)
+    } else {
+        None
+    }
+}
+
+/// The members or range to cut a level on. A range combined with other
+/// values (e.g. `time=year.latest,year.2010..2012`) is expanded into the
+/// level's members between its ends, so that the level is cut on one list.
+fn level_cut_members(level_cuts: &[String], members: Option<&HashSet<String>>) -> (Vec<String>, Option<CutRange>) {
+    if let [value] = level_cuts {
+        if let Some(range) = level_cut_range(value, members) {
+            return (vec![], Some(range));
+        }
+    }
+
+    let mut cut_members: Vec<String> = vec![];
+
+    for value in level_cuts {
+        match (level_cut_range(value, members), members) {
+            (Some(range), Some(members)) => {
+                let mut range_members: Vec<_> = members.iter()
+                    .filter(|m| in_cut_range(m, &range))
+                    .cloned()
+                    .collect();
+                range_members.sort_by(|a, b| compare_members(a, b));
+
+                cut_members.extend(range_members);
+            },
+            _ => cut_members.push(value.clone()),
+        }
+    }
+
+    let mut seen = HashSet::new();
+    cut_members.retain(|m| seen.insert(m.clone()));
+
+    (cut_members, None)
+}
+
+fn in_cut_range(member: &str, range: &CutRange) -> bool {
+    match range {
+        CutRange::Between(start, end) => {
+            compare_members(member, start) != Ordering::Less && compare_members(member, end) != Ordering::Greater
+        },
+        CutRange::From(start) => compare_members(member, start) != Ordering::Less,
+        CutRange::To(end) => compare_members(member, end) != Ordering::Greater,
+    }
+}
+
+/// Members are compared as numbers if they both are, as a range cut on a
+/// numeric key would be.
+fn compare_members(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
 pub fn clean_cuts_map(
         agg_query_opt: &LogicLayerQueryOpt,
         cube_cache: &CubeCache,
@@ -964,13 +1369,16 @@ pub fn clean_cuts_map(
         None => HashMap::new()
     };
 
-    // Process `time` param (latest/oldest)
+    // Process `time` param (latest/oldest). Several time cuts on the same
+    // precision are combined into a list of members.
     match &agg_query_opt.time {
         Some(time_param) => {
             let time_cuts: Vec<String> = time_param.split(",").map(|s| s.to_string()).collect();
+            let mut time_cuts_map: HashMap<String, Vec<String>> = HashMap::new();
 
             for time_cut in time_cuts {
-                let tc: Vec<String> = time_cut.split(".").map(|s| s.to_string()).collect();
+                // Only split on the first period, ranges contain `..`
+                let tc: Vec<String> = time_cut.splitn(2, ".").map(|s| s.to_string()).collect();
 
                 if tc.len() != 2 {
                     return Err(format_err!("Malformatted time cut"));
@@ -986,7 +1394,11 @@ pub fn clean_cuts_map(
                     Err(err) => return Err(format_err!("{}", err.to_string()))
                 };
 
-                agg_query_opt_cuts.insert(cut, cut_value);
+                time_cuts_map.entry(cut).or_insert(vec![]).push(cut_value);
+            }
+
+            for (cut, cut_values) in time_cuts_map {
+                agg_query_opt_cuts.insert(cut, cut_values.join(","));
             }
         },
         None => ()
@@ -1314,3 +1726,53 @@ pub fn get_parent_captions(cube: &Cube, level_name: &LevelName, locales: &Vec<St
 
     captions
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn members(members: &[&str]) -> HashSet<String> {
+        members.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn test_level_cut_range() {
+        let years = members(&["2010", "2011", "2012"]);
+
+        assert_eq!(level_cut_range("2010..2012", Some(&years)), Some(CutRange::Between("2010".into(), "2012".into())));
+        assert_eq!(level_cut_range("2011..", Some(&years)), Some(CutRange::From("2011".into())));
+        assert_eq!(level_cut_range("2010", Some(&years)), None);
+        assert_eq!(level_cut_range("2010..2012", None), None);
+
+        // text members can contain `..`
+        let regions = members(&["N.A.. Region", "S.A.. Region"]);
+        assert_eq!(level_cut_range("N.A.. Region", Some(&regions)), None);
+        assert_eq!(level_cut_range("http://a..b", Some(&regions)), None);
+    }
+
+    #[test]
+    fn test_level_cut_members() {
+        let years = members(&["2009", "2010", "2011", "2012", "2013"]);
+        let cuts = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            level_cut_members(&cuts(&["2010..2012"]), Some(&years)),
+            (vec![], Some(CutRange::Between("2010".into(), "2012".into()))),
+        );
+        assert_eq!(
+            level_cut_members(&cuts(&["2009", "2013"]), Some(&years)),
+            (cuts(&["2009", "2013"]), None),
+        );
+
+        // ranges in a list are expanded into their members
+        assert_eq!(
+            level_cut_members(&cuts(&["2013", "2010..2012"]), Some(&years)),
+            (cuts(&["2013", "2010", "2011", "2012"]), None),
+        );
+        assert_eq!(
+            level_cut_members(&cuts(&["2011..", "..2010", "2012"]), Some(&years)),
+            (cuts(&["2011", "2012", "2013", "2009", "2010"]), None),
+        );
+    }
+}
@@ -20,7 +20,7 @@ use tesseract_core::names::LevelName;
 
 use super::super::util::{
     boxed_error_string, boxed_error_http_response,
-    verify_authorization, format_to_content_type
+    verify_authorization, format_to_content_type, members_sql
 };
 
 
@@ -145,10 +145,10 @@ pub fn get_members(
     debug!("{:?}", cube_name);
     debug!("{:?}", level_name);
 
-    let members_sql_and_headers = match members_query.locale {
-        Some(locale) => schema.members_locale_sql(&cube_name, &level_name, &locale),
-        None => schema.members_sql(&cube_name, &level_name)
-    };
+    let members_sql_and_headers = members_sql(
+        &schema, &req.state().backend, &cube_name, &level_name,
+        members_query.locale.as_ref().map(|l| l.as_str())
+    );
 
     let (members_sql, header) = match members_sql_and_headers {
         Ok(s) => s,
@@ -19,7 +19,7 @@ use tesseract_core::DEFAULT_ALLOWED_ACCESS;
 
 use crate::app::AppState;
 use crate::logic_layer::LogicLayerConfig;
-use super::util::{boxed_error_http_response, verify_authorization, get_user_auth_level};
+use super::util::{boxed_error_http_response, verify_authorization, get_user_auth_level, members_sql};
 
 
 pub fn metadata_handler(
@@ -179,8 +179,9 @@ pub fn do_members(
 
     info!("Members for cube: {}, level: {}", cube, level);
 
-    let members_sql_and_headers = req.state().schema.read().unwrap()
-        .members_sql(&cube, &level);
+    let members_sql_and_headers = members_sql(
+        schema, &req.state().backend, &cube, &level, None
+    );
 
     let (members_sql, header) = ok_or_400!(members_sql_and_headers);
 
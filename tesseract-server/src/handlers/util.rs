@@ -11,13 +11,15 @@ use mime;
 use r2d2_redis::{r2d2, redis, RedisConnectionManager};
 
 use tesseract_core::format::FormatType;
+use tesseract_core::{Backend, Schema};
 use tesseract_core::schema::Cube;
 use tesseract_core::schema::metadata::SourceMetadata;
 
 use crate::app::AppState;
 
 use failure::{bail, format_err, Error};
-use tesseract_core::names::Cut;
+use tesseract_core::names::{Cut, LevelName};
+use tesseract_core::query::BinQuery;
 use crate::logic_layer::CubeCache;
 use crate::auth::{validate_web_token, extract_token, user_auth_level};
 
@@ -136,8 +138,19 @@ macro_rules! some_or_404 {
 }
 
 
-pub fn validate_members(cuts: &[Cut], cube_cache: &CubeCache) -> Result<(), Error> {
+pub fn validate_members(cuts: &[Cut], bins: &[BinQuery], cube_cache: &CubeCache) -> Result<(), Error> {
     for cut in cuts {
+        // property values are not cached, and are always quoted and escaped as text
+        if cut.property.is_some() {
+            continue;
+        }
+
+        // cuts on a binned level refer to bin labels, not members, and are
+        // checked against the bin's labels when generating the query ir
+        if bins.iter().any(|b| b.is_cut_on(cut)) {
+            continue;
+        }
+
         // get level cache
         let member_cache = cube_cache.members_for_level(&cut.level_name)
             .ok_or_else(|| format_err!("Level not found in cache"))?;
@@ -146,11 +159,55 @@ pub fn validate_members(cuts: &[Cut], cube_cache: &CubeCache) -> Result<(), Erro
                 bail!("Cut member not found");
             }
         }
+
+        // range ends are members too, and are compared against directly
+        if let Some(ref range) = cut.range {
+            for end in range.ends() {
+                if !member_cache.contains(end) {
+                    bail!("Cut range member not found");
+                }
+            }
+        }
     }
     Ok(())
 }
 
 
+/// Generates SQL (and headers) for a members query. Virtual time levels don't
+/// have a dimension table, so their members are generated by the backend from
+/// the fact table's date column.
+pub fn members_sql(
+    schema: &Schema,
+    backend: &Box<dyn Backend + Sync + Send>,
+    cube_name: &str,
+    level_name: &LevelName,
+    locale: Option<&str>,
+) -> Result<(String, Vec<String>), Error>
+{
+    let cube = schema.get_cube_by_name(cube_name)?;
+
+    let level = cube.get_level(level_name)
+        .ok_or_else(|| format_err!("could not find level {}", level_name))?;
+    let dimension = cube.get_dimension(level_name)
+        .ok_or_else(|| format_err!("could not find dimension for level {}", level_name))?;
+
+    match (&level.time_part, &dimension.foreign_key) {
+        (Some(time_part), Some(foreign_key)) => {
+            let sql = format!("select distinct {} from {}",
+                backend.time_part_sql(foreign_key, time_part, cube.fiscal_year_start_month),
+                cube.table.full_name(),
+            );
+
+            Ok((sql, vec!["ID".into()]))
+        },
+        _ => match locale {
+            Some(locale) => schema.members_locale_sql(cube_name, level_name, locale),
+            None => schema.members_sql(cube_name, level_name),
+        },
+    }
+}
+
+
 /// Gets the Redis cache key for a given query.
 /// The sorting of query param keys is an attempt to increase cache hits.
 pub fn get_redis_cache_key(prefix: &str, req: &HttpRequest<AppState>, cube: &str, format: &FormatType) -> String {
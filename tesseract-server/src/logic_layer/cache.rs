@@ -1,14 +1,14 @@
 use std::collections::{HashMap, HashSet};
 use actix::SystemRunner;
 use failure::{Error, format_err};
-use log::{info, debug};
+use log::{info, debug, warn};
 use std::time::Instant;
 
 use serde_derive::Deserialize;
 
 use tesseract_core::{Schema, Backend};
 use tesseract_core::names::{LevelName, Property};
-use tesseract_core::schema::{Level, Cube, InlineTable};
+use tesseract_core::schema::{Level, Cube, Dimension, InlineTable, TimePart};
 
 use crate::logic_layer::{LogicLayerConfig};
 
@@ -18,15 +18,54 @@ pub enum TimeValue {
     First,
     Last,
     Value(u32),
+    // Steps through the cached members from another value, e.g. `latest-4`
+    // is the fifth most recent member.
+    Offset(Box<TimeValue>, i64),
+    // Inclusive range of time values. Either end (but not both) can be left
+    // open, e.g. `2015..` or `2010..latest`.
+    Range(Option<Box<TimeValue>>, Option<Box<TimeValue>>),
+    // Members from the start of the value's year up to the value, e.g.
+    // `latest:ytd`. Member IDs start with the year.
+    YearToDate(Box<TimeValue>),
 }
 
 
 impl TimeValue {
     pub fn from_str(raw: String) -> Result<Self, Error> {
-        if raw == "latest" {
+        if let Some(range_idx) = raw.find("..") {
+            let start = &raw[..range_idx];
+            let end = &raw[range_idx+2..];
+
+            if (start.is_empty() && end.is_empty()) || end.contains("..") {
+                return Err(format_err!("Wrong format for time range argument."));
+            }
+
+            let start = if start.is_empty() {
+                None
+            } else {
+                Some(Box::new(TimeValue::from_str(start.to_string())?))
+            };
+            let end = if end.is_empty() {
+                None
+            } else {
+                Some(Box::new(TimeValue::from_str(end.to_string())?))
+            };
+
+            Ok(TimeValue::Range(start, end))
+        } else if raw.ends_with(":ytd") {
+            let value = TimeValue::from_str(raw.trim_end_matches(":ytd").to_string())?;
+
+            Ok(TimeValue::YearToDate(Box::new(value)))
+        } else if raw == "latest" {
             Ok(TimeValue::Last)
         } else if raw == "oldest" {
             Ok(TimeValue::First)
+        } else if let Some(offset_idx) = raw.rfind(|c: char| c == '+' || c == '-').filter(|&i| i > 0) {
+            let value = TimeValue::from_str(raw[..offset_idx].to_string())?;
+            let offset = raw[offset_idx..].trim_start_matches('+').parse::<i64>()
+                .map_err(|_| format_err!("Wrong format for time offset argument."))?;
+
+            Ok(TimeValue::Offset(Box::new(value), offset))
         } else {
             match raw.parse::<u32>() {
                 Ok(n) => Ok(TimeValue::Value(n)),
@@ -37,7 +76,8 @@ impl TimeValue {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all="lowercase")]
 pub enum TimePrecision {
     Year,
     Quarter,
@@ -77,7 +117,8 @@ pub struct Time {
 
 impl Time {
     pub fn from_str(raw: String) -> Result<Self, Error> {
-        let e: Vec<&str> = raw.split(".").collect();
+        // Only split on the first period, ranges contain `..`
+        let e: Vec<&str> = raw.splitn(2, ".").collect();
 
         if e.len() != 2 {
             return Err(format_err!("Wrong format for time argument."));
@@ -135,23 +176,8 @@ impl Cache {
 pub struct CubeCache {
     pub name: String,
 
-    pub year_level: Option<Level>,
-    pub year_values: Option<Vec<String>>,
-
-    pub quarter_level: Option<Level>,
-    pub quarter_values: Option<Vec<String>>,
-
-    pub month_level: Option<Level>,
-    pub month_values: Option<Vec<String>>,
-
-    pub week_level: Option<Level>,
-    pub week_values: Option<Vec<String>>,
-
-    pub day_level: Option<Level>,
-    pub day_values: Option<Vec<String>>,
-
-    pub time_level: Option<Level>,
-    pub time_values: Option<Vec<String>>,
+    // Maps a time precision to the level used for it
+    pub time_levels: HashMap<TimePrecision, TimeLevelCache>,
 
     pub level_map: HashMap<String, LevelName>,
     pub property_map: HashMap<String, Property>,
@@ -165,80 +191,100 @@ pub struct CubeCache {
 
 
 impl CubeCache {
+    /// Resolves a time cut into the unique name of the level for its
+    /// precision, and the cut value.
     pub fn get_time_cut(&self, time: Time) -> Result<(String, String), Error> {
-        let (val_res, ln_res) = match time.precision {
-            TimePrecision::Year => {
-                let v = self.get_value(&time, self.year_values.clone());
-                let l = self.get_level_name(self.year_level.clone());
-                (v, l)
-            },
-            TimePrecision::Quarter => {
-                let v = self.get_value(&time, self.quarter_values.clone());
-                let l = self.get_level_name(self.quarter_level.clone());
-                (v, l)
-            },
-            TimePrecision::Month => {
-                let v = self.get_value(&time, self.month_values.clone());
-                let l = self.get_level_name(self.month_level.clone());
-                (v, l)
+        let time_level = self.time_levels.get(&time.precision)
+            .ok_or_else(|| format_err!("Unable to get requested time precision level name."))?;
+
+        let val = self.get_time_value(&time.value, &time_level.values)
+            .ok_or_else(|| format_err!("Unable to get requested time precision data."))?;
+
+        Ok((time_level.unique_name.clone(), val))
+    }
+
+    /// Finds the level used for a time precision, e.g. for `growth=year,...`.
+    pub fn get_time_level_name(&self, precision: &TimePrecision) -> Option<&LevelName> {
+        self.time_levels.get(precision)
+            .map(|time_level| &time_level.level_name)
+    }
+
+    /// Resolves a `TimeValue` against the cached values for a time level.
+    /// Ranges resolve to a range cut value (e.g. `2010..2020`).
+    fn get_time_value(&self, time_value: &TimeValue, v: &[String]) -> Option<String> {
+        match time_value {
+            TimeValue::First => {
+                if v.len() >= 1 {
+                    return Some(v[0].clone());
+                }
+                None
             },
-            TimePrecision::Week => {
-                let v = self.get_value(&time, self.week_values.clone());
-                let l = self.get_level_name(self.week_level.clone());
-                (v, l)
+            TimeValue::Last => {
+                if v.len() >= 1 {
+                    return Some(v.last().unwrap().clone())
+                }
+                None
             },
-            TimePrecision::Day => {
-                let v = self.get_value(&time, self.day_values.clone());
-                let l = self.get_level_name(self.day_level.clone());
-                (v, l)
+            TimeValue::Value(t) => return Some(t.to_string()),
+            TimeValue::Offset(..) => {
+                let idx = self.get_time_value_index(time_value, v)?;
+
+                if idx < 0 || idx >= v.len() as i64 {
+                    return None;
+                }
+                Some(v[idx as usize].clone())
             },
-            TimePrecision::Time => {
-                let v = self.get_value(&time, self.time_values.clone());
-                let l = self.get_level_name(self.time_level.clone());
-                (v, l)
-            }
-        };
+            TimeValue::Range(start, end) => {
+                let start = match start {
+                    Some(start) => self.get_range_bound(start, v)?,
+                    None => "".to_string()
+                };
+                let end = match end {
+                    Some(end) => self.get_range_bound(end, v)?,
+                    None => "".to_string()
+                };
 
-        let val = match val_res {
-            Some(o) => o,
-            None => return Err(format_err!("Unable to get requested time precision data."))
-        };
+                Some(format!("{}..{}", start, end))
+            },
+            TimeValue::YearToDate(value) => {
+                let end = self.get_time_value(value, v)?;
+                let year = end.get(..4)?;
 
-        let ln = match ln_res {
-            Some(o) => o,
-            None => return Err(format_err!("Unable to get requested time precision level name."))
-        };
+                let start = v.iter()
+                    .find(|m| m.starts_with(year) && m.len() == end.len())
+                    .unwrap_or(&end);
 
-        Ok((ln, val))
+                Some(format!("{}..{}", start, end))
+            },
+        }
     }
 
-    pub fn get_level_name(&self, level: Option<Level>) -> Option<String> {
-        match level {
-            Some(l) => Some(l.name),
-            None => None
+    /// Position of a time value in the cached values, which offsets can move
+    /// past either end.
+    fn get_time_value_index(&self, time_value: &TimeValue, v: &[String]) -> Option<i64> {
+        match time_value {
+            TimeValue::Offset(value, offset) => {
+                Some(self.get_time_value_index(value, v)? + offset)
+            },
+            _ => {
+                let value = self.get_time_value(time_value, v)?;
+                v.iter().position(|m| *m == value).map(|idx| idx as i64)
+            },
         }
     }
 
-    pub fn get_value(&self, time: &Time, opt: Option<Vec<String>>) -> Option<String> {
-        match opt {
-            Some(v) => {
-                match time.value {
-                    TimeValue::First => {
-                        if v.len() >= 1 {
-                            return Some(v[0].clone());
-                        }
-                        None
-                    },
-                    TimeValue::Last => {
-                        if v.len() >= 1 {
-                            return Some(v.last().unwrap().clone())
-                        }
-                        None
-                    },
-                    TimeValue::Value(t) => return Some(t.to_string())
-                }
+    /// Range ends with an offset past the cached values are limited to the
+    /// cached values, so that rolling windows work with less data loaded.
+    fn get_range_bound(&self, time_value: &TimeValue, v: &[String]) -> Option<String> {
+        match time_value {
+            TimeValue::Offset(..) if !v.is_empty() => {
+                let idx = self.get_time_value_index(time_value, v)?;
+                let idx = idx.max(0).min(v.len() as i64 - 1);
+
+                Some(v[idx as usize].clone())
             },
-            None => None
+            TimeValue::YearToDate(_) => None,
+            _ => self.get_time_value(time_value, v),
         }
     }
 
@@ -257,6 +303,16 @@ impl CubeCache {
 }
 
 
+/// Holds the sorted member IDs of the level used for a time precision, to
+/// resolve `latest` and `oldest`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeLevelCache {
+    pub level_name: LevelName,
+    pub unique_name: String,
+    pub values: Vec<String>,
+}
+
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct LevelCache {
     pub unique_name: String,
@@ -285,29 +341,11 @@ pub fn populate_cache(
     info!("Populating cache...");
     let time_start = Instant::now();
 
-    let time_column_names = vec![
-        "Year".to_string(),
-        "Quarter".to_string(),
-        "Month".to_string(),
-        "Week".to_string(),
-        "Day".to_string()
-    ];
-
     let mut cubes: Vec<CubeCache> = vec![];
 
     for cube in schema.cubes {
-        let mut year_level: Option<Level> = None;
-        let mut year_values: Option<Vec<String>> = None;
-        let mut quarter_level: Option<Level> = None;
-        let mut quarter_values: Option<Vec<String>> = None;
-        let mut month_level: Option<Level> = None;
-        let mut month_values: Option<Vec<String>> = None;
-        let mut week_level: Option<Level> = None;
-        let mut week_values: Option<Vec<String>> = None;
-        let mut day_level: Option<Level> = None;
-        let mut day_values: Option<Vec<String>> = None;
-        let mut time_level: Option<Level> = None;
-        let mut time_values: Option<Vec<String>> = None;
+        let mut time_levels: HashMap<TimePrecision, TimeLevelCache> = HashMap::new();
+        let mut time_level_sources: HashMap<TimePrecision, PrecisionSource> = HashMap::new();
 
         let mut level_caches: HashMap<LevelName, LevelCache> = HashMap::new();
         let mut dimension_caches: HashMap<String, DimensionCache> = HashMap::new();
@@ -316,92 +354,11 @@ pub fn populate_cache(
             let mut id_map: HashMap<String, Vec<LevelName>> = HashMap::new();
 
             for hierarchy in &dimension.hierarchies {
-                let table = match &hierarchy.table {
-                    Some(t) => &t.name,
-                    None => &cube.table.name
-                };
+                let table = hierarchy.table_sql()
+                    .unwrap_or_else(|| cube.table.name.clone());
 
                 for level in &hierarchy.levels {
-                    if time_column_names.contains(&level.name) {
-                        let val = get_distinct_values(
-                            &level.key_column, &table, backend.clone(), sys
-                        )?;
-
-                        if level.name == "Year" {
-                            year_level = Some(level.clone());
-                            year_values = Some(val);
-                        } else if level.name == "Quarter" {
-                            quarter_level = Some(level.clone());
-                            quarter_values = Some(val);
-                        } else if level.name == "Month" {
-                            month_level = Some(level.clone());
-                            month_values = Some(val);
-                        } else if level.name == "Week" {
-                            week_level = Some(level.clone());
-                            week_values = Some(val);
-                        } else if level.name == "Day" {
-                            day_level = Some(level.clone());
-                            day_values = Some(val);
-                        }
-                    } else if level.name == "Time" {
-                        // Identify what time of level this is based on the annotation name
-                        let mut found_time = false;
-
-                        // This is a hack for now. It handles the case where you
-                        // have a level called Time that is actually at a more
-                        // specific depth. It allows to cut on that depth using the
-                        // .latest/.oldest feature.
-                        match &level.annotations {
-                            Some(annotations) => {
-                                for annotation in annotations {
-                                    if annotation.name == "level" && time_column_names.contains(&annotation.text) {
-                                        let val = get_distinct_values(
-                                            &level.key_column, &table, backend.clone(), sys
-                                        )?;
-
-                                        if annotation.text == "Year" {
-                                            year_level = Some(level.clone());
-                                            year_values = Some(val);
-                                            found_time = true;
-                                        } else if annotation.text == "Quarter" {
-                                            quarter_level = Some(level.clone());
-                                            quarter_values = Some(val);
-                                            found_time = true;
-                                        } else if annotation.text == "Month" {
-                                            month_level = Some(level.clone());
-                                            month_values = Some(val);
-                                            found_time = true;
-                                        } else if annotation.text == "Week" {
-                                            week_level = Some(level.clone());
-                                            week_values = Some(val);
-                                            found_time = true;
-                                        } else if annotation.text == "Day" {
-                                            day_level = Some(level.clone());
-                                            day_values = Some(val);
-                                            found_time = true;
-                                        } else if annotation.text == "Time" {
-                                            time_level = Some(level.clone());
-                                            time_values = Some(val);
-                                            found_time = true;
-                                        }
-                                    }
-                                }
-                            },
-                            None => ()
-                        }
-
-                        // Consider this to be a time generic Time level
-                        if !found_time {
-                            // Want to get distinct time values from the fact table
-                            let val = get_distinct_values(
-                                &level.key_column, &cube.table.name, backend.clone(), sys
-                            )?;
-
-                            time_level = Some(level.clone());
-                            time_values = Some(val);
-                        }
-                    }
-
+                    let key_column = level_column(&cube, dimension, level, &backend);
                     let level_name = LevelName::new(
                         dimension.name.clone(),
                         hierarchy.name.clone(),
@@ -414,6 +371,47 @@ pub fn populate_cache(
                         None => return Err(format_err!("Couldn't find unique name for {}", level.name.clone()))
                     };
 
+                    if let Some((precision, source)) = get_time_precision(&cube, ll_config, &level_name, level)? {
+                        // when several levels have the same precision, configured levels
+                        // come first, then calendar levels, then the first level found
+                        let is_preferred = match time_level_sources.get(&precision) {
+                            Some(existing_source) => {
+                                let existing_level = &time_levels[&precision].level_name;
+                                let replaces = source < *existing_source;
+                                let (used, ignored) = if replaces {
+                                    (&level_name, existing_level)
+                                } else {
+                                    (existing_level, &level_name)
+                                };
+                                warn!("Cube {} has several levels with time precision {:?}, using {} instead of {}",
+                                    cube.name, precision, used, ignored);
+                                replaces
+                            },
+                            None => true,
+                        };
+
+                        if is_preferred {
+                            // Generic `Time` levels get their values from the fact table,
+                            // unless annotated as a `Time` level
+                            let values_table = if precision == TimePrecision::Time && level_annotation(level) != Some("Time") {
+                                &cube.table.name
+                            } else {
+                                &table
+                            };
+
+                            let values = get_distinct_values(
+                                &key_column, values_table, backend.clone(), sys
+                            )?;
+
+                            time_level_sources.insert(precision.clone(), source);
+                            time_levels.insert(precision, TimeLevelCache {
+                                level_name: level_name.clone(),
+                                unique_name: unique_name.clone(),
+                                values,
+                            });
+                        }
+                    }
+
                     let mut parent_map: Option<HashMap<String, String>> = None;
                     let mut children_map: Option<HashMap<String, Vec<String>>> = None;
 
@@ -458,17 +456,23 @@ pub fn populate_cache(
                         // Database table
 
                         if parent_levels.len() >= 1 {
+                            let parent_column = level_column(
+                                &cube, dimension, &parent_levels[parent_levels.len() - 1], &backend
+                            );
+
                             parent_map = Some(get_parent_data(
-                                &parent_levels[parent_levels.len() - 1], &level,
-                                table, backend.clone(), sys
+                                &parent_column, &key_column,
+                                &table, backend.clone(), sys
                             )?);
                         }
 
                         match child_level {
                             Some(child_level) => {
+                                let child_column = level_column(&cube, dimension, &child_level, &backend);
+
                                 children_map = Some(get_children_data(
-                                    &level, &child_level,
-                                    table, backend.clone(), sys
+                                    &key_column, &child_column,
+                                    &table, backend.clone(), sys
                                 )?);
                             },
                             None => ()
@@ -476,7 +480,7 @@ pub fn populate_cache(
 
                         // Get all IDs for this level
                         distinct_ids = get_distinct_values(
-                            &level.key_column, &table, backend.clone(), sys
+                            &key_column, &table, backend.clone(), sys
                         )?;
                     }
 
@@ -514,18 +518,7 @@ pub fn populate_cache(
 
         cubes.push(CubeCache {
             name: cube.name,
-            year_level,
-            year_values,
-            quarter_level,
-            quarter_values,
-            month_level,
-            month_values,
-            week_level,
-            week_values,
-            day_level,
-            day_values,
-            time_level,
-            time_values,
+            time_levels,
             level_map,
             property_map,
             level_caches,
@@ -539,6 +532,77 @@ pub fn populate_cache(
 }
 
 
+/// Where the time precision of a level comes from, in order of preference when
+/// several levels of a cube have the same precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PrecisionSource {
+    Config,
+    Calendar,
+    // ISO and fiscal years and quarters
+    NonCalendar,
+}
+
+/// Finds the time precision of a level, if any. Precisions set for the cube in
+/// the logic layer config come first, then the time part of virtual time
+/// levels, and then the level name.
+fn get_time_precision(
+        cube: &Cube,
+        ll_config: &Option<LogicLayerConfig>,
+        level_name: &LevelName,
+        level: &Level,
+) -> Result<Option<(TimePrecision, PrecisionSource)>, Error> {
+    if let Some(ll_config) = ll_config {
+        if let Some(precision) = ll_config.find_time_precision(&cube.name, level_name)? {
+            return Ok(Some((precision, PrecisionSource::Config)));
+        }
+    }
+
+    if let Some(ref time_part) = level.time_part {
+        let precision = match time_part {
+            TimePart::Year => (TimePrecision::Year, PrecisionSource::Calendar),
+            TimePart::IsoYear | TimePart::FiscalYear => (TimePrecision::Year, PrecisionSource::NonCalendar),
+            TimePart::Quarter => (TimePrecision::Quarter, PrecisionSource::Calendar),
+            TimePart::FiscalQuarter => (TimePrecision::Quarter, PrecisionSource::NonCalendar),
+            TimePart::Month => (TimePrecision::Month, PrecisionSource::Calendar),
+            TimePart::Week => (TimePrecision::Week, PrecisionSource::Calendar),
+            TimePart::Day => (TimePrecision::Day, PrecisionSource::Calendar),
+        };
+        return Ok(Some(precision));
+    }
+
+    // This is a hack for now. It handles the case where you have a level
+    // called Time that is actually at a more specific depth. It allows to cut
+    // on that depth using the .latest/.oldest feature.
+    let name = if level.name == "Time" {
+        level_annotation(level).unwrap_or("Time")
+    } else {
+        level.name.as_str()
+    };
+
+    let precision = match name {
+        "Year" => Some(TimePrecision::Year),
+        "Quarter" => Some(TimePrecision::Quarter),
+        "Month" => Some(TimePrecision::Month),
+        "Week" => Some(TimePrecision::Week),
+        "Day" => Some(TimePrecision::Day),
+        // Otherwise, consider this to be a time generic Time level
+        _ if level.name == "Time" => Some(TimePrecision::Time),
+        _ => None,
+    };
+
+    Ok(precision.map(|precision| (precision, PrecisionSource::Calendar)))
+}
+
+
+/// Value of the `level` annotation of a level, if any.
+fn level_annotation(level: &Level) -> Option<&str> {
+    level.annotations.as_ref()?
+        .iter()
+        .find(|annotation| annotation.name == "level")
+        .map(|annotation| annotation.text.as_str())
+}
+
+
 pub fn get_unique_level_name(cube: &Cube, ll_config: &Option<LogicLayerConfig>, level_name: &LevelName) -> Result<Option<String>, Error> {
     for dimension in &cube.dimensions {
         for hierarchy in &dimension.hierarchies {
@@ -746,8 +810,8 @@ pub fn get_inline_children_data(
 
 
 pub fn get_parent_data(
-        parent_level: &Level,
-        current_level: &Level,
+        parent_column: &str,
+        current_column: &str,
         table: &str,
         backend: Box<dyn Backend + Sync + Send>,
         sys: &mut SystemRunner
@@ -758,7 +822,7 @@ pub fn get_parent_data(
         .exec_sql(
             format!(
                 "select distinct {0}, {1} from {2} group by {0}, {1} order by {0}, {1}",
-                parent_level.key_column, current_level.key_column, table,
+                parent_column, current_column, table,
             ).to_string()
         );
 
@@ -781,8 +845,8 @@ pub fn get_parent_data(
 
 
 pub fn get_children_data(
-        current_level: &Level,
-        child_level: &Level,
+        current_column: &str,
+        child_column: &str,
         table: &str,
         backend: Box<dyn Backend + Sync + Send>,
         sys: &mut SystemRunner
@@ -793,7 +857,7 @@ pub fn get_children_data(
         .exec_sql(
             format!(
                 "select distinct {0}, {1} from {2} group by {0}, {1} order by {0}, {1}",
-                current_level.key_column, child_level.key_column, table,
+                current_column, child_column, table,
             ).to_string()
         );
 
@@ -831,6 +895,21 @@ pub fn get_children_data(
 }
 
 
+/// Column (or expression) with the member ids of a level. Virtual time levels
+/// are generated from the dimension's date column.
+fn level_column(
+        cube: &Cube,
+        dimension: &Dimension,
+        level: &Level,
+        backend: &Box<dyn Backend + Sync + Send>,
+) -> String {
+    match (&level.time_part, &dimension.foreign_key) {
+        (Some(time_part), Some(foreign_key)) => backend.time_part_sql(foreign_key, time_part, cube.fiscal_year_start_month),
+        _ => level.key_column.clone(),
+    }
+}
+
+
 /// Queries the database to get all the distinct values for a given level.
 pub fn get_distinct_values(
         column: &str,
@@ -902,3 +981,107 @@ pub fn get_neighbors_map(distinct_ids: &Vec<String>) -> HashMap<String, Vec<Stri
 
     neighbors_map
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time_cut(raw: &str, values: &[&str]) -> Result<(String, String), Error> {
+        let mut time_levels = HashMap::new();
+        time_levels.insert(TimePrecision::Quarter, TimeLevelCache {
+            level_name: LevelName::new("Date", "Date", "Quarter"),
+            unique_name: "Quarter".to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        });
+
+        let cube_cache = CubeCache {
+            name: "sales".to_string(),
+            time_levels,
+            level_map: HashMap::new(),
+            property_map: HashMap::new(),
+            level_caches: HashMap::new(),
+            dimension_caches: HashMap::new(),
+        };
+
+        cube_cache.get_time_cut(Time::from_str(raw.to_string())?)
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let quarters = ["20184", "20191", "20192", "20193"];
+
+        assert_eq!(time_cut("quarter.latest-1", &quarters).unwrap().1, "20192");
+        assert_eq!(time_cut("quarter.oldest+1", &quarters).unwrap().1, "20191");
+        assert!(time_cut("quarter.latest-4", &quarters).is_err());
+
+        // range ends are limited to the cached members
+        assert_eq!(time_cut("quarter.latest-1..latest", &quarters).unwrap().1, "20192..20193");
+        assert_eq!(time_cut("quarter.latest-9..latest", &quarters).unwrap().1, "20184..20193");
+
+        assert_eq!(time_cut("quarter.latest:ytd", &quarters).unwrap(), ("Quarter".to_string(), "20191..20193".to_string()));
+        assert!(time_cut("year.latest", &quarters).is_err());
+    }
+
+    #[test]
+    fn test_time_precision() {
+        let schema = Schema::from_xml(r#"
+            <Schema name="my_schema">
+                <Cube name="sales" fiscal_year_start_month="7">
+                    <Table name="sales_table" />
+                    <Dimension name="Date" foreign_key="sale_date" type="time">
+                        <Hierarchy name="Date">
+                            <Level name="Calendar Year" key_column="year" time_part="year" />
+                            <Level name="Quarter" key_column="quarter" time_part="quarter" />
+                            <Level name="Month" key_column="month" time_part="month" />
+                            <Level name="Day" key_column="day" time_part="day" />
+                        </Hierarchy>
+                        <Hierarchy name="Fiscal">
+                            <Level name="Fiscal Year" key_column="fiscal_year" time_part="fiscal_year" />
+                            <Level name="Fiscal Quarter" key_column="fiscal_quarter" time_part="fiscal_quarter" />
+                        </Hierarchy>
+                        <Hierarchy name="ISO">
+                            <Level name="ISO Year" key_column="iso_year" time_part="isoyear" />
+                            <Level name="Week" key_column="week" time_part="week" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Dimension name="Period" foreign_key="period_id">
+                        <Hierarchy name="Period" primary_key="period_id">
+                            <Table name="dim_period" />
+                            <Level name="Year" key_column="year" />
+                            <Level name="Time" key_column="month_id">
+                                <Annotation name="level">Month</Annotation>
+                            </Level>
+                            <Level name="Period" key_column="period_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Sales" column="sales" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "#).unwrap();
+        let cube = schema.get_cube_by_name("sales").unwrap();
+
+        let precision = |dimension: &str, hierarchy: &str, level: &str| {
+            let level_name = LevelName::new(dimension, hierarchy, level);
+            let level = cube.get_level(&level_name).unwrap();
+            get_time_precision(cube, &None, &level_name, &level).unwrap()
+        };
+
+        assert_eq!(precision("Date", "Date", "Calendar Year"), Some((TimePrecision::Year, PrecisionSource::Calendar)));
+        assert_eq!(precision("Date", "Date", "Quarter"), Some((TimePrecision::Quarter, PrecisionSource::Calendar)));
+        assert_eq!(precision("Date", "Date", "Month"), Some((TimePrecision::Month, PrecisionSource::Calendar)));
+        assert_eq!(precision("Date", "Date", "Day"), Some((TimePrecision::Day, PrecisionSource::Calendar)));
+        assert_eq!(precision("Date", "Fiscal", "Fiscal Year"), Some((TimePrecision::Year, PrecisionSource::NonCalendar)));
+        assert_eq!(precision("Date", "Fiscal", "Fiscal Quarter"), Some((TimePrecision::Quarter, PrecisionSource::NonCalendar)));
+        assert_eq!(precision("Date", "ISO", "ISO Year"), Some((TimePrecision::Year, PrecisionSource::NonCalendar)));
+        assert_eq!(precision("Date", "ISO", "Week"), Some((TimePrecision::Week, PrecisionSource::Calendar)));
+
+        // levels without a time part go by name
+        assert_eq!(precision("Period", "Period", "Year"), Some((TimePrecision::Year, PrecisionSource::Calendar)));
+        assert_eq!(precision("Period", "Period", "Time"), Some((TimePrecision::Month, PrecisionSource::Calendar)));
+        assert_eq!(precision("Period", "Period", "Period"), None);
+
+        // calendar years are used over fiscal and ISO years
+        assert!(PrecisionSource::Config < PrecisionSource::Calendar);
+        assert!(PrecisionSource::Calendar < PrecisionSource::NonCalendar);
+    }
+}
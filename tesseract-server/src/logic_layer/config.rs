@@ -6,11 +6,14 @@ use serde_json;
 use tesseract_core::{Schema, CubeHasUniqueLevelsAndProperties};
 use tesseract_core::names::{LevelName, Property};
 
+use super::cache::TimePrecision;
+
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct LogicLayerConfig {
     pub aliases: Option<AliasConfig>,
     pub named_sets: Option<Vec<NamedSetsConfig>>,
+    pub time: Option<Vec<CubeTimeConfig>>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -47,6 +50,20 @@ pub struct NamedSetConfig {
     pub values: Vec<String>
 }
 
+/// Maps levels of a cube to the time precisions used by the `time` and
+/// `growth` params, for levels not named `Year`, `Quarter`, etc.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CubeTimeConfig {
+    pub cube: String,
+    pub levels: Vec<TimeLevelConfig>
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeLevelConfig {
+    pub level_name: String,
+    pub precision: TimePrecision
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct LevelPropertyConfig {
     pub current_name: String,
@@ -267,6 +284,26 @@ impl LogicLayerConfig {
         }
     }
 
+    /// Returns the time precision set for a given cube level if there is one.
+    pub fn find_time_precision(
+        &self, cube_name: &String, level_name: &LevelName
+    ) -> Result<Option<TimePrecision>, Error> {
+        if let Some(time) = &self.time {
+            for cube_time in time {
+                if &cube_time.cube == cube_name {
+                    for level in &cube_time.levels {
+                        let ll_level_name: LevelName = level.level_name.parse()?;
+
+                        if &ll_level_name == level_name {
+                            return Ok(Some(level.precision.clone()))
+                        }
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
     /// Returns a unique name definition for a given cube level if there is one.
     pub fn find_unique_cube_level_name(
         &self, cube_name: &String, level_name: &LevelName
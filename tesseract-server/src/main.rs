@@ -85,6 +85,12 @@ fn main() -> Result<(), Error> {
         opt.streaming_response // true
     };
 
+    // Commands that don't need the database
+    if let Some(Command::Convert { input, mondrian, format, output }) = &opt.command {
+        let schema = schema_config::convert_schema(input, *mondrian, format)?;
+        return write_command_output(schema, output);
+    }
+
     // address
     let server_addr = opt.address.unwrap_or("127.0.0.1:7777".to_owned());
 
@@ -102,6 +108,13 @@ fn main() -> Result<(), Error> {
     let (db, db_url, db_type) = db_config::get_db(&db_url_full)?;
     let db_type_viz = db_type.clone();
 
+    // Commands that don't start the server
+    if let Some(Command::Skeleton { table, dimensions, format, output }) = opt.command {
+        let mut sys = actix::System::new("tesseract");
+        let skeleton = schema_config::write_schema_skeleton(&table, &dimensions, &format, &*db, &mut sys)?;
+        return write_command_output(skeleton, &output);
+    }
+
     // Schema
     let schema_path = env::var("TESSERACT_SCHEMA_FILEPATH")
         .expect("TESSERACT_SCHEMA_FILEPATH not found");
@@ -155,6 +168,25 @@ fn main() -> Result<(), Error> {
     // Initialize actix system
     let mut sys = actix::System::new("tesseract");
 
+    // Check the schema against the database: `warn` only logs the problems,
+    // `true` refuses to start if there are any
+    let schema_db_check = env::var("TESSERACT_SCHEMA_DB_CHECK").ok();
+    if let Some(check) = schema_db_check {
+        if check == "true" || check == "warn" {
+            let diagnostics = sys.block_on(schema.check_database(None, &*db))
+                .map_err(|err| format_err!("Schema database check error: {}", err))?;
+
+            for diagnostic in &diagnostics {
+                warn!("Schema database check: {}", diagnostic);
+            }
+            if check == "true" && !diagnostics.is_empty() {
+                return Err(format_err!("Schema does not match the database, {} problems found", diagnostics.len()));
+            }
+        } else if check != "false" {
+            return Err(format_err!("could not parse TESSERACT_SCHEMA_DB_CHECK, expected true, warn or false"));
+        }
+    }
+
     // Populate internal cache
     let cache = logic_layer::populate_cache(
         schema.clone(), &logic_layer_config, db.clone(), &mut sys
@@ -236,6 +268,15 @@ fn main() -> Result<(), Error> {
 }
 
 
+/// Writes the output of a command to a file, or to stdout.
+fn write_command_output(content: String, output: &Option<String>) -> Result<(), Error> {
+    match output {
+        Some(path) => std::fs::write(path, content)?,
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
 /// CLI arguments helper.
 #[derive(Debug, StructOpt)]
 #[structopt(name="tesseract")]
@@ -254,4 +295,49 @@ struct Opt {
 
     #[structopt(long="streaming")]
     streaming_response: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Drafts a schema for a fact table from the database
+    #[structopt(name="skeleton")]
+    Skeleton {
+        /// Fact table, as `table` or `schema.table`
+        #[structopt(long="table")]
+        table: String,
+
+        /// Dimension table of a fact table column, as `column=table`
+        #[structopt(long="dimension")]
+        dimensions: Vec<String>,
+
+        /// `xml` or `json`
+        #[structopt(long="format", default_value="xml")]
+        format: String,
+
+        /// File to write the schema to, instead of stdout
+        #[structopt(short="o", long="output")]
+        output: Option<String>,
+    },
+
+    /// Converts a schema file between XML and JSON, or from Mondrian XML
+    #[structopt(name="convert")]
+    Convert {
+        /// Schema file to convert
+        input: String,
+
+        /// The schema file is a Mondrian 3 or 4 schema
+        #[structopt(long="mondrian")]
+        mondrian: bool,
+
+        /// `xml` or `json`
+        #[structopt(long="format")]
+        format: String,
+
+        /// File to write the schema to, instead of stdout
+        #[structopt(short="o", long="output")]
+        output: Option<String>,
+    },
 }
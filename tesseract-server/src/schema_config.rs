@@ -1,34 +1,114 @@
-use failure::{Error, format_err};
+use actix::SystemRunner;
+use failure::{Error, format_err, bail};
+use std::path::Path;
 
-use tesseract_core::Schema;
+use tesseract_core::{Backend, Schema, Table};
+use tesseract_core::schema::{SchemaConfigJson, parse_schema_config, read_mondrian_schema};
+use tesseract_core::schema::skeleton::schema_skeleton;
+use tesseract_core::schema::write::{schema_config_to_json, schema_config_to_xml};
 
 
-/// Reads a schema from an XML or JSON file and converts it into a `tesseract_core::Schema` object.
+/// Reads a schema from an XML or JSON file, or from a directory of XML and
+/// JSON schema files, and converts it into a `tesseract_core::Schema` object.
+/// Schema files can include other schema files.
 pub fn read_schema(schema_path: &str) -> Result<Schema, Error> {
-    let schema_str = std::fs::read_to_string(&schema_path)
-        .map_err(|_| format_err!("Schema file not found at {}", schema_path))?;
+    let paths = schema_file_paths(schema_path)?;
 
-    let schema = if schema_path.ends_with("xml") {
-        Schema::from_xml(&schema_str)?
-    } else if schema_path.ends_with("json") {
-        Schema::from_json(&schema_str)?
+    Schema::from_files(&paths, |path| {
+        std::fs::read_to_string(path)
+            .map_err(|_| format_err!("Schema file not found at {}", path))
+    })
+}
+
+/// A directory's schema files are merged in file name order.
+fn schema_file_paths(schema_path: &str) -> Result<Vec<String>, Error> {
+    if !Path::new(schema_path).is_dir() {
+        return Ok(vec![schema_path.to_owned()]);
+    }
+
+    let mut paths = vec![];
+
+    for entry in std::fs::read_dir(schema_path)? {
+        let path = entry?.path();
+        let is_schema = path.extension()
+            .map(|ext| ext == "xml" || ext == "json")
+            .unwrap_or(false);
+
+        if is_schema {
+            paths.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(format_err!("No schema files found in {}", schema_path));
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Converts a Tesseract XML or JSON schema file, or a Mondrian XML schema
+/// file, into a Tesseract XML or JSON schema. Includes are kept as includes.
+pub fn convert_schema(input_path: &str, mondrian: bool, format: &str) -> Result<String, Error> {
+    let raw = std::fs::read_to_string(input_path)
+        .map_err(|_| format_err!("Schema file not found at {}", input_path))?;
+
+    let schema_config = if mondrian {
+        read_mondrian_schema(&raw)?
     } else {
-        return Err(format_err!("Schema format not supported"))
+        parse_schema_config(input_path, &raw)?
     };
 
-    // TODO Should this check be done in core?
-    for cube in &schema.cubes {
-        for dimension in &cube.dimensions {
-            for hierarchy in &dimension.hierarchies {
-                let has_table = hierarchy.table.is_some();
-                let has_inline_table = hierarchy.inline_table.is_some();
+    write_schema_config(&schema_config, format)
+}
 
-                if has_table && has_inline_table {
-                    return Err(format_err!("Can't have table and inline table definitions in the same hierarchy"))
-                }
+/// Drafts a schema for a fact table from the database, as XML or JSON.
+///
+/// `dimensions` are `column=table` pairs, for dimension tables whose names
+/// don't follow the naming conventions.
+pub fn write_schema_skeleton(
+    table: &str,
+    dimensions: &[String],
+    format: &str,
+    backend: &dyn Backend,
+    sys: &mut SystemRunner,
+    ) -> Result<String, Error>
+{
+    let dimension_tables = dimensions.iter()
+        .map(|dimension| {
+            let mut parts = dimension.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(column), Some(dim_table)) => Ok((column.to_owned(), parse_table(dim_table))),
+                _ => Err(format_err!("Dimension {} is not column=table", dimension)),
             }
-        }
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let schema_config = sys.block_on(schema_skeleton(backend, parse_table(table), dimension_tables))?;
+
+    write_schema_config(&schema_config, format)
+}
+
+fn write_schema_config(schema_config: &SchemaConfigJson, format: &str) -> Result<String, Error> {
+    match format {
+        "xml" => schema_config_to_xml(schema_config),
+        "json" => schema_config_to_json(schema_config),
+        _ => bail!("Schema format not supported: {}", format),
     }
+}
 
-    Ok(schema)
+/// Table from `schema.table` or `table`.
+fn parse_table(table: &str) -> Table {
+    match table.rfind('.') {
+        Some(idx) => Table {
+            name: table[idx + 1..].to_owned(),
+            schema: Some(table[..idx].to_owned()),
+            primary_key: None,
+        },
+        None => Table {
+            name: table.to_owned(),
+            schema: None,
+            primary_key: None,
+        },
+    }
 }